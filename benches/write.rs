@@ -3,13 +3,15 @@ extern crate test;
 
 use rand::{prelude::ThreadRng, Rng};
 use test::Bencher;
-use zdb::{schema::*, table::*};
+use zdb::{schema::*, table::*, ZdbRow};
 
 static ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
 
-#[derive(Debug, Clone)]
+#[derive(ZdbRow, Debug, Clone)]
 struct OHLCV {
+  #[zdb(timestamp)]
   ts:       i64,
+  #[zdb(rename = "ticker")]
   symbol:   String,
   open:     f32,
   high:     f32,
@@ -53,37 +55,19 @@ fn generate_rows(row_count: usize, rng: &mut ThreadRng) -> Vec<OHLCV> {
   res
 }
 
-fn write_rows(rows: Vec<OHLCV>, index: i64) {
-  let schema = Schema::new(&format!("agg1d{}", index))
-    .add_cols(vec![
-      Column::new("ticker", ColumnType::Symbol16),
-      Column::new("open", ColumnType::F32),
-      Column::new("high", ColumnType::F32),
-      Column::new("low", ColumnType::F32),
-      Column::new("close", ColumnType::F32),
-      Column::new("close_un", ColumnType::F32),
-      Column::new("volume", ColumnType::U64),
-    ])
-    .partition_by(PartitionBy::Year);
+fn write_rows(mut rows: Vec<OHLCV>, index: i64) {
+  let schema = OHLCV::schema(&format!("agg1d{}", index)).partition_by(PartitionBy::Year);
 
   let mut agg1d = Table::create_or_open(schema).expect("Could not open table");
-  // Maybe one day we can do this dynamically...
-  for r in rows.drain(..) {
+  for mut r in rows.drain(..) {
     let ts = match agg1d.get_last_ts() {
       Some(ts) => ts,
       None => 0
     };
-    agg1d.put_timestamp(ts + r.ts);
-    agg1d.put_symbol(r.symbol);
-    agg1d.put_f32(r.open);
-    agg1d.put_f32(r.high);
-    agg1d.put_f32(r.low);
-    agg1d.put_f32(r.close);
-    agg1d.put_f32(r.close_un);
-    agg1d.put_u64(r.volume);
-    agg1d.write();
+    r.ts += ts;
+    agg1d.insert(&r).unwrap();
   }
-  agg1d.flush();
+  agg1d.flush().unwrap();
 }
 
 #[bench]
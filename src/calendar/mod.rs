@@ -1,4 +1,4 @@
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime};
 
 pub mod us_equity;
 
@@ -10,6 +10,6 @@ impl ToNaiveDateTime for i64 {
   fn to_naive_date_time(self) -> NaiveDateTime {
     let seconds = self / 1_000_000_000;
     let nanoseconds = self % 1_000_000_000;
-    NaiveDateTime::from_timestamp(seconds, nanoseconds as u32)
+    DateTime::from_timestamp(seconds, nanoseconds as u32).unwrap().naive_utc()
   }
 }
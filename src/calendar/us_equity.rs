@@ -1,8 +1,9 @@
 use chrono::{
   prelude::*,
   Duration, NaiveDate,
-  Weekday::{Mon, Sat, Sun, Thu}
+  Weekday::{Fri, Mon, Sat, Sun, Thu}
 };
+use std::ops::Range;
 
 fn get_easter(year: i32) -> NaiveDate {
   let aa = year % 19;
@@ -20,7 +21,7 @@ fn get_easter(year: i32) -> NaiveDate {
   let month = (hh + ll - 7 * mm + 114) / 31;
   let day = (hh + ll - 7 * mm + 114) % 31 + 1;
 
-  NaiveDate::from_ymd(year, month as u32, day as u32)
+  NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
 }
 
 fn is_weekend(date: &NaiveDate) -> bool { date.weekday() == Sat || date.weekday() == Sun }
@@ -34,7 +35,7 @@ pub fn is_market_open(date: &NaiveDate) -> bool {
   }
 
   // New year's
-  let mut new_year = NaiveDate::from_ymd(year, 1, 1);
+  let mut new_year = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
   while is_weekend(&new_year) {
     new_year += Duration::days(1);
   }
@@ -43,12 +44,12 @@ pub fn is_market_open(date: &NaiveDate) -> bool {
   }
 
   // MLK day on 3rd Mon of January
-  if date == &NaiveDate::from_weekday_of_month(year, 1, Mon, 3) {
+  if date == &NaiveDate::from_weekday_of_month_opt(year, 1, Mon, 3).unwrap() {
     return false;
   }
 
   // Washington's Birthday on 3rd Mon of February
-  if date == &NaiveDate::from_weekday_of_month(year, 2, Mon, 3) {
+  if date == &NaiveDate::from_weekday_of_month_opt(year, 2, Mon, 3).unwrap() {
     return false;
   }
 
@@ -60,7 +61,7 @@ pub fn is_market_open(date: &NaiveDate) -> bool {
   }
 
   // Memorial Day
-  let mut memorial_day = NaiveDate::from_ymd(year, 5, 31);
+  let mut memorial_day = NaiveDate::from_ymd_opt(year, 5, 31).unwrap();
   while memorial_day.weekday() != Mon {
     memorial_day -= Duration::days(1);
   }
@@ -69,7 +70,7 @@ pub fn is_market_open(date: &NaiveDate) -> bool {
   }
 
   // Independence Day
-  let mut independence_day = NaiveDate::from_ymd(year, 7, 4);
+  let mut independence_day = NaiveDate::from_ymd_opt(year, 7, 4).unwrap();
   if independence_day.weekday() == Sat {
     independence_day -= Duration::days(1);
   } else if independence_day.weekday() == Sun {
@@ -80,17 +81,17 @@ pub fn is_market_open(date: &NaiveDate) -> bool {
   }
 
   // Labor Day on first Mon of September
-  if date == &NaiveDate::from_weekday_of_month(year, 9, Mon, 1) {
+  if date == &NaiveDate::from_weekday_of_month_opt(year, 9, Mon, 1).unwrap() {
     return false;
   }
 
   // Thanksgiving on fourth Thu of November
-  if date == &NaiveDate::from_weekday_of_month(year, 11, Thu, 4) {
+  if date == &NaiveDate::from_weekday_of_month_opt(year, 11, Thu, 4).unwrap() {
     return false;
   }
 
   // Christmas
-  let mut christmas = NaiveDate::from_ymd(year, 12, 25);
+  let mut christmas = NaiveDate::from_ymd_opt(year, 12, 25).unwrap();
   if christmas.weekday() == Sat {
     christmas -= Duration::days(1);
   } else if christmas.weekday() == Sun {
@@ -103,15 +104,15 @@ pub fn is_market_open(date: &NaiveDate) -> bool {
   let disasters = &[
     // Ronald Reagan dead at 93
     // https://money.cnn.com/2004/06/11/markets/reagan_closings/index.htm
-    NaiveDate::from_ymd(2004, 6, 11),
+    NaiveDate::from_ymd_opt(2004, 6, 11).unwrap(),
     // Gerald R. Ford dead at 93
     // https://georgewbush-whitehouse.archives.gov/news/releases/2006/12/20061228-2.html
-    NaiveDate::from_ymd(2007, 1, 2),
+    NaiveDate::from_ymd_opt(2007, 1, 2).unwrap(),
     // Hurricane Sandy
-    NaiveDate::from_ymd(2012, 10, 29),
-    NaiveDate::from_ymd(2012, 10, 30),
+    NaiveDate::from_ymd_opt(2012, 10, 29).unwrap(),
+    NaiveDate::from_ymd_opt(2012, 10, 30).unwrap(),
     // George H.W. Bush dead at 94
-    NaiveDate::from_ymd(2018, 12, 5)
+    NaiveDate::from_ymd_opt(2018, 12, 5).unwrap()
   ];
 
   if disasters.contains(date) {
@@ -121,9 +122,67 @@ pub fn is_market_open(date: &NaiveDate) -> bool {
   return true;
 }
 
+// Eastern time's offset from UTC in hours. DST switches at 2am which is outside sessions.
+fn get_utc_offset(date: &NaiveDate) -> i64 {
+  let year = date.year();
+  let (dst_start, dst_end) = if year >= 2007 {
+    (
+      NaiveDate::from_weekday_of_month_opt(year, 3, Sun, 2).unwrap(),
+      NaiveDate::from_weekday_of_month_opt(year, 11, Sun, 1).unwrap()
+    )
+  } else {
+    let mut dst_end = NaiveDate::from_ymd_opt(year, 10, 31).unwrap();
+    while dst_end.weekday() != Sun {
+      dst_end -= Duration::days(1);
+    }
+    (NaiveDate::from_weekday_of_month_opt(year, 4, Sun, 1).unwrap(), dst_end)
+  };
+  if date >= &dst_start && date < &dst_end {
+    -4
+  } else {
+    -5
+  }
+}
+
+// Closes at 1pm
+fn is_early_close(date: &NaiveDate) -> bool {
+  let year = date.year();
+  let is_early_weekday = date.weekday() != Fri && !is_weekend(&date);
+
+  // Day before Independence Day unless it's observed that day
+  if date == &NaiveDate::from_ymd_opt(year, 7, 3).unwrap() && is_early_weekday {
+    return true;
+  }
+
+  // Day after Thanksgiving
+  let thanksgiving = NaiveDate::from_weekday_of_month_opt(year, 11, Thu, 4).unwrap();
+  if date == &(thanksgiving + Duration::days(1)) {
+    return true;
+  }
+
+  // Christmas Eve unless Christmas is observed that day
+  date == &NaiveDate::from_ymd_opt(year, 12, 24).unwrap() && is_early_weekday
+}
+
+// Nanoseconds since epoch of the regular trading session on `date`
+pub fn get_session(date: &NaiveDate) -> Option<Range<i64>> {
+  if !is_market_open(date) {
+    return None;
+  }
+  let to_nanoseconds = |hour: u32, minute: u32| {
+    let local = date.and_hms_opt(hour, minute, 0).unwrap().and_utc().timestamp_nanos_opt().unwrap();
+    local - get_utc_offset(date) * 60 * 60 * 1_000_000_000
+  };
+  let close_hour = if is_early_close(date) { 13 } else { 16 };
+  Some(to_nanoseconds(9, 30)..to_nanoseconds(close_hour, 0))
+}
+
 #[cfg(test)]
 mod tests {
-  use crate::calendar::us_equity::{get_easter, is_market_open};
+  use crate::calendar::{
+    us_equity::{get_easter, get_session, is_market_open},
+    ToNaiveDateTime
+  };
   use chrono::{
     NaiveDate,
     Weekday::{Mon, Thu}
@@ -132,47 +191,85 @@ mod tests {
   #[test]
   fn mlk() {
     assert_eq!(
-      NaiveDate::from_weekday_of_month(2004, 1, Mon, 3),
-      NaiveDate::from_ymd(2004, 01, 19)
+      NaiveDate::from_weekday_of_month_opt(2004, 1, Mon, 3).unwrap(),
+      NaiveDate::from_ymd_opt(2004, 01, 19).unwrap()
     );
   }
 
   #[test]
   fn washington() {
     assert_eq!(
-      NaiveDate::from_weekday_of_month(2004, 2, Mon, 3),
-      NaiveDate::from_ymd(2004, 02, 16)
+      NaiveDate::from_weekday_of_month_opt(2004, 2, Mon, 3).unwrap(),
+      NaiveDate::from_ymd_opt(2004, 02, 16).unwrap()
     );
   }
 
   #[test]
   fn easter() {
-    assert_eq!(get_easter(2004), NaiveDate::from_ymd(2004, 04, 11));
+    assert_eq!(get_easter(2004), NaiveDate::from_ymd_opt(2004, 04, 11).unwrap());
   }
 
   #[test]
   fn good_friday() {
-    assert_eq!(is_market_open(&NaiveDate::from_ymd(2004, 04, 09)), false);
+    assert_eq!(is_market_open(&NaiveDate::from_ymd_opt(2004, 04, 09).unwrap()), false);
   }
 
   #[test]
   fn labor() {
     assert_eq!(
-      NaiveDate::from_weekday_of_month(2004, 9, Mon, 1),
-      NaiveDate::from_ymd(2004, 9, 6)
+      NaiveDate::from_weekday_of_month_opt(2004, 9, Mon, 1).unwrap(),
+      NaiveDate::from_ymd_opt(2004, 9, 6).unwrap()
     );
   }
 
   #[test]
   fn thanksgiving() {
     assert_eq!(
-      NaiveDate::from_weekday_of_month(2004, 11, Thu, 4),
-      NaiveDate::from_ymd(2004, 11, 25)
+      NaiveDate::from_weekday_of_month_opt(2004, 11, Thu, 4).unwrap(),
+      NaiveDate::from_ymd_opt(2004, 11, 25).unwrap()
     );
   }
 
   #[test]
   fn christmas() {
-    assert_eq!(is_market_open(&NaiveDate::from_ymd(2004, 12, 25)), false);
+    assert_eq!(is_market_open(&NaiveDate::from_ymd_opt(2004, 12, 25).unwrap()), false);
+  }
+
+  #[test]
+  fn sessions() {
+    let session = |y, m, d| {
+      get_session(&NaiveDate::from_ymd_opt(y, m, d).unwrap()).map(|session| {
+        let to_string = |ts: i64| ts.to_naive_date_time().to_string();
+        (to_string(session.start), to_string(session.end))
+      })
+    };
+    let utc = |open: &str, close: &str| Some((open.to_owned(), close.to_owned()));
+    // EST
+    assert_eq!(
+      session(2021, 1, 4),
+      utc("2021-01-04 14:30:00", "2021-01-04 21:00:00")
+    );
+    // EDT
+    assert_eq!(
+      session(2021, 6, 1),
+      utc("2021-06-01 13:30:00", "2021-06-01 20:00:00")
+    );
+    // DST started on the first Sunday of April before 2007
+    assert_eq!(
+      session(2004, 4, 5),
+      utc("2004-04-05 13:30:00", "2004-04-05 20:00:00")
+    );
+    // Day after Thanksgiving
+    assert_eq!(
+      session(2021, 11, 26),
+      utc("2021-11-26 14:30:00", "2021-11-26 18:00:00")
+    );
+    // Christmas is observed on Friday the 24th
+    assert_eq!(session(2021, 12, 24), None);
+    assert_eq!(
+      session(2019, 12, 24),
+      utc("2019-12-24 14:30:00", "2019-12-24 18:00:00")
+    );
+    assert_eq!(session(2021, 1, 2), None);
   }
 }
@@ -0,0 +1,130 @@
+use crate::schema::ColumnType;
+use arrow_schema::ArrowError;
+use parquet::errors::ParquetError;
+use std::{fmt, io, path::PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+  TableNotFound(String),
+  TableExists(String),
+  TableLocked(String),
+  ColumnNotFound {
+    table:  String,
+    column: String
+  },
+  ColumnExists {
+    table:  String,
+    column: String
+  },
+  TypeMismatch {
+    column:   String,
+    expected: String,
+    actual:   ColumnType
+  },
+  Io {
+    path:  PathBuf,
+    error: io::Error
+  },
+  OutOfOrder {
+    from_ts: i64,
+    to_ts:   i64
+  },
+  NotNullable(String),
+  DecimalOverflow {
+    column: String,
+    value:  i64
+  },
+  SymbolOverflow {
+    column:   String,
+    capacity: usize
+  },
+  CorruptMeta {
+    path:   PathBuf,
+    reason: String
+  },
+  // Converting to or writing Arrow
+  Arrow(ArrowError),
+  Parquet(ParquetError),
+  // Bad user input like an unparsable date or a failing Julia query
+  Query(String)
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::TableNotFound(name) => write!(f, "table \"{}\" does not exist", name),
+      Error::TableExists(name) => write!(
+        f,
+        "Table {name:?} already exists. Try Table::open({name:?}) instead",
+        name = name
+      ),
+      Error::TableLocked(name) => {
+        write!(f, "table \"{}\" is locked by another writer", name)
+      }
+      Error::ColumnNotFound { table, column } => {
+        write!(f, "column {} does not exist on table {}", column, table)
+      }
+      Error::ColumnExists { table, column } => {
+        write!(f, "column {} already exists on table {}", column, table)
+      }
+      Error::TypeMismatch {
+        column,
+        expected,
+        actual
+      } => write!(
+        f,
+        "expected column {} to be {} but it is {:?}",
+        column, expected, actual
+      ),
+      Error::Io { path, error } => write!(f, "{:?}: {}", path, error),
+      Error::OutOfOrder { from_ts, to_ts } => {
+        write!(f, "timestamp {} is before {}", to_ts, from_ts)
+      }
+      Error::NotNullable(column) => write!(f, "column {} is not nullable", column),
+      Error::DecimalOverflow { column, value } => {
+        write!(f, "{} does not fit in column {}", value, column)
+      }
+      Error::SymbolOverflow { column, capacity } => write!(
+        f,
+        "column {} cannot hold more than {} symbols",
+        column, capacity
+      ),
+      Error::CorruptMeta { path, reason } => write!(f, "corrupt {:?}: {}", path, reason),
+      Error::Arrow(error) => write!(f, "arrow: {}", error),
+      Error::Parquet(error) => write!(f, "{}", error),
+      Error::Query(msg) => write!(f, "{}", msg)
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io { error, .. } => Some(error),
+      Error::Arrow(error) => Some(error),
+      Error::Parquet(error) => Some(error),
+      _ => None
+    }
+  }
+}
+
+impl From<ArrowError> for Error {
+  fn from(error: ArrowError) -> Self { Error::Arrow(error) }
+}
+
+impl From<ParquetError> for Error {
+  fn from(error: ParquetError) -> Self { Error::Parquet(error) }
+}
+
+pub trait IoContext<T> {
+  fn with_path(self, path: &PathBuf) -> Result<T, Error>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+  fn with_path(self, path: &PathBuf) -> Result<T, Error> {
+    self.map_err(|error| Error::Io {
+      path: path.clone(),
+      error
+    })
+  }
+}
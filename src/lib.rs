@@ -1,5 +1,11 @@
 pub mod calendar;
+pub mod error;
 pub mod schema;
 pub mod server;
+pub mod sql;
 pub mod table;
 pub mod test_symbols;
+
+pub use error::Error;
+pub use table::ZdbRow;
+pub use zdb_derive::ZdbRow;
@@ -1,3 +1,4 @@
+use crate::table::Resample;
 use serde::{Deserialize, Serialize};
 use std::{cmp::PartialEq, fmt, path::PathBuf};
 
@@ -16,7 +17,29 @@ pub enum ColumnType {
   F32,
   I64,
   U64,
-  F64
+  F64,
+  // Variable length. Stored as u64 end offsets into a separate data file.
+  String,
+  Bytes,
+  // Fixed point. Stores value * 10^scale.
+  Decimal32 { scale: u32 },
+  Decimal64 { scale: u32 }
+}
+
+// How a column's file is compressed once its partition is sealed
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum Codec {
+  None,
+  // Zigzag varint changes in the difference between rows. Good for timestamps and ids.
+  Delta,
+  // XOR with the previous row. Good for slowly changing floats like prices.
+  Gorilla,
+  // Bit-packed offsets from each block's minimum. Good for volumes.
+  FrameOfReference
+}
+
+impl Default for Codec {
+  fn default() -> Self { Codec::None }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -29,6 +52,11 @@ pub struct Column {
   pub resolution: i64,
   // If symbol column what filename to use
   pub sym_name:   String,
+  // Whether rows can be missing. Tracked in a bitmap next to the column's file.
+  #[serde(default)]
+  pub nullable:   bool,
+  #[serde(default)]
+  pub codec:      Codec
 }
 
 impl Column {
@@ -51,9 +79,15 @@ impl Column {
         ColumnType::F32 => 4,
         ColumnType::I64 => 8,
         ColumnType::U64 => 8,
-        ColumnType::F64 => 8
+        ColumnType::F64 => 8,
+        ColumnType::String => 8,
+        ColumnType::Bytes => 8,
+        ColumnType::Decimal32 { .. } => 4,
+        ColumnType::Decimal64 { .. } => 8
       },
-      sym_name: String::new()
+      sym_name: String::new(),
+      nullable: false,
+      codec: Codec::None
     }
   }
 
@@ -66,6 +100,16 @@ impl Column {
     self.sym_name = String::from(sym_name);
     self
   }
+
+  pub fn with_nulls(mut self) -> Column {
+    self.nullable = true;
+    self
+  }
+
+  pub fn with_codec(mut self, codec: Codec) -> Column {
+    self.codec = codec;
+    self
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
@@ -76,13 +120,22 @@ pub enum PartitionBy {
   Day
 }
 
+// Makes a table OHLCV bars of `source` that are updated whenever `source` flushes
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Aggregate {
+  pub source:   String,
+  pub resample: Resample
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Schema {
   #[serde(skip, default)]
   pub name: String,
   pub columns: Vec<Column>,
   pub partition_by: PartitionBy,
-  pub partition_dirs: Vec<PathBuf>
+  pub partition_dirs: Vec<PathBuf>,
+  #[serde(default)]
+  pub aggregate: Option<Aggregate>
 }
 
 impl fmt::Debug for Schema {
@@ -108,7 +161,8 @@ impl<'a> Schema {
       name: name.to_owned(),
       columns: vec![],
       partition_by: PartitionBy::None,
-      partition_dirs: vec![PathBuf::from("data")]
+      partition_dirs: vec![PathBuf::from("data")],
+      aggregate: None
     }
   }
 
@@ -138,7 +192,16 @@ impl<'a> Schema {
     self
   }
 
-  fn set_timestamp_size(&mut self) {
+  // Columns must be a timestamp, symbol, open, high, low, close and volume
+  pub fn aggregate(mut self, source: &str, resample: Resample) -> Self {
+    self.aggregate = Some(Aggregate {
+      source: source.to_owned(),
+      resample
+    });
+    self
+  }
+
+  pub(crate) fn set_timestamp_size(&mut self) {
     // Determine lengths of timestamp columns based on partition_by and their resolution
     let partition_by = self.partition_by;
     self
@@ -0,0 +1,115 @@
+use crate::{
+  error::Error,
+  schema::ColumnType,
+  server::{
+    ohlcv::{get_param, querify},
+    query::string_to_nanoseconds
+  },
+  table::{parse_duration, AsOf, ColumnBuffer, OwnedColumn, Table}
+};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Serialize)]
+struct ColumnResponse {
+  name:   String,
+  values: Vec<Value>
+}
+
+// Decimals are converted to f64 like /ohlcv and null rows are null
+fn get_values(column: &OwnedColumn) -> Vec<Value> {
+  let scale = match column.r#type {
+    ColumnType::Decimal32 { scale } | ColumnType::Decimal64 { scale } => Some(scale),
+    _ => None
+  };
+  let to_value = |v: i64| match scale {
+    Some(scale) => Value::from(v as f64 / 10_f64.powi(scale as i32)),
+    None => Value::from(v)
+  };
+  let mut res = match &column.data {
+    ColumnBuffer::Timestamp(v) => v.iter().map(|v| Value::from(*v)).collect::<Vec<_>>(),
+    ColumnBuffer::Symbol(v) | ColumnBuffer::String(v) => {
+      v.iter().map(|v| Value::from(v.as_str())).collect()
+    }
+    ColumnBuffer::I8(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::U8(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::I16(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::U16(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::I32(v) => v.iter().map(|v| to_value(*v as i64)).collect(),
+    ColumnBuffer::U32(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::F32(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::I64(v) => v.iter().map(|v| to_value(*v)).collect(),
+    ColumnBuffer::U64(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::F64(v) => v.iter().map(|v| Value::from(*v)).collect(),
+    ColumnBuffer::Bytes(v) => v.iter().map(|v| Value::from(v.clone())).collect()
+  };
+  if let Some(nulls) = &column.nulls {
+    for (value, is_null) in res.iter_mut().zip(nulls) {
+      if *is_null {
+        *value = Value::Null;
+      }
+    }
+  }
+  res
+}
+
+pub(super) fn serialize_columns(columns: &[OwnedColumn]) -> Vec<u8> {
+  let res = columns
+    .iter()
+    .map(|column| ColumnResponse {
+      name:   column.name.clone(),
+      values: get_values(column)
+    })
+    .collect::<Vec<_>>();
+  serde_json::to_vec(&res).unwrap()
+}
+
+pub(super) fn get_list<'a>(query_params: &[(&'a str, &'a str)], key: &str) -> Vec<&'a str> {
+  match get_param(query_params, key) {
+    None | Some("") => Vec::new(),
+    Some(columns) => columns.split(',').map(|c| c.trim()).collect()
+  }
+}
+
+// Joins each row of {left} with the last row of {right} at or before it. Columns are chosen with
+// ?left=a,b&right=c,d and rows can also be matched on a symbol column with ?by=sym and limited to
+// right rows at most ?tolerance=1s before.
+pub fn get_asof(path: &str) -> Result<Vec<OwnedColumn>, Error> {
+  let mut query_parts = path.split('?');
+  let parts = query_parts.next().unwrap().split('/').skip(2).collect::<Vec<_>>();
+  let query_params = match query_parts.next() {
+    Some(query_params) => querify(query_params),
+    None => Vec::new()
+  };
+  let (left_name, right_name, from, to) = match parts[..] {
+    [left_name, right_name, from, to] => (left_name, right_name, from, to),
+    _ => {
+      return Err(Error::Query(String::from(
+        "url must be in format /asof/{left}/{right}/{from}/{to}"
+      )))
+    }
+  };
+  let mut from = string_to_nanoseconds(from)?;
+  let mut to = string_to_nanoseconds(to)?;
+  if from > to {
+    let tmp = from;
+    from = to;
+    to = tmp;
+  }
+  let left = Table::open(left_name)?;
+  let right = Table::open(right_name)?;
+  let mut asof = AsOf::new(
+    &get_list(&query_params, "left"),
+    &get_list(&query_params, "right")
+  );
+  if let Some(by) = get_param(&query_params, "by").filter(|by| !by.is_empty()) {
+    asof = asof.by(by);
+  }
+  if let Some(tolerance) = get_param(&query_params, "tolerance").filter(|t| !t.is_empty()) {
+    asof = asof.tolerance(parse_duration(tolerance)?);
+  }
+
+  left.asof_join(&right, from, to, &asof)
+}
+
+pub fn asof(path: &str) -> Result<Vec<u8>, Error> { Ok(serialize_columns(&get_asof(path)?)) }
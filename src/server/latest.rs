@@ -0,0 +1,39 @@
+use crate::{
+  error::Error,
+  server::{
+    asof::{get_list, serialize_columns},
+    ohlcv::querify,
+    query::string_to_nanoseconds
+  },
+  table::{OwnedColumn, Table}
+};
+
+// The last row of each symbol at or before {as_of} or of all rows if it's left out. Columns are
+// chosen with ?columns=a,b and symbols with ?symbols=AAPL,MSFT.
+pub fn get_latest(path: &str) -> Result<Vec<OwnedColumn>, Error> {
+  let mut query_parts = path.split('?');
+  let parts = query_parts.next().unwrap().split('/').skip(2).collect::<Vec<_>>();
+  let query_params = match query_parts.next() {
+    Some(query_params) => querify(query_params),
+    None => Vec::new()
+  };
+  let (table_name, symbol_column, as_of) = match parts[..] {
+    [table_name, symbol_column] => (table_name, symbol_column, i64::MAX),
+    [table_name, symbol_column, as_of] => {
+      (table_name, symbol_column, string_to_nanoseconds(as_of)?)
+    }
+    _ => {
+      return Err(Error::Query(String::from(concat!(
+        "url must be in format /latest/{table}/{symbol_column} or ",
+        "/latest/{table}/{symbol_column}/{as_of}"
+      ))))
+    }
+  };
+  let table = Table::open(table_name)?;
+  let columns = get_list(&query_params, "columns");
+  let symbols = get_list(&query_params, "symbols");
+
+  table.latest_by(symbol_column, as_of, &columns, &symbols)
+}
+
+pub fn latest(path: &str) -> Result<Vec<u8>, Error> { Ok(serialize_columns(&get_latest(path)?)) }
@@ -1,13 +1,26 @@
+pub mod asof;
 pub mod julia;
+pub mod latest;
 pub mod ohlcv;
 pub mod query;
+pub mod scan;
 
 use crate::{
+  error::Error,
   server::query::{run_query, serialize_jl_value, Query},
-  table::Table
+  sql::run_sql,
+  table::{to_record_batch, OwnedColumn, Table}
 };
+use arrow_array::RecordBatch;
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{Schema, SchemaRef};
+use asof::{get_asof, serialize_columns};
+use latest::get_latest;
 use ohlcv::ohlcv;
-use std::{io::prelude::*, net::TcpStream};
+use scan::get_scan;
+use std::{io::prelude::*, iter::once, net::TcpStream};
+
+const ARROW_STREAM: &str = "application/vnd.apache.arrow.stream";
 
 // Since we have to embed Julia we use a process per-connection
 static mut BUFFER: [u8; 1024] = [0; 1024];
@@ -37,11 +50,78 @@ pub fn write_contents(
   stream.flush().unwrap();
 }
 
+pub fn write_error(stream: TcpStream, err: Error) {
+  let code = match err {
+    Error::TableNotFound(_) | Error::ColumnNotFound { .. } => 404,
+    Error::Io { .. } | Error::CorruptMeta { .. } | Error::Arrow(_) | Error::Parquet(_) => 500,
+    _ => 400
+  };
+  write_contents(stream, code, err.to_string().as_bytes(), None);
+}
+
+fn accepts_arrow(headers: &[httparse::Header]) -> bool {
+  headers.iter().any(|header| {
+    header.name.eq_ignore_ascii_case("accept")
+      && String::from_utf8_lossy(header.value).contains(ARROW_STREAM)
+  })
+}
+
+// Writes each batch as it's read. There's no content-length so the body ends when the connection
+// closes.
+pub fn write_batches(
+  mut stream: TcpStream,
+  schema: SchemaRef,
+  batches: impl Iterator<Item = Result<RecordBatch, Error>>
+) {
+  let mut batches = batches.peekable();
+  if let Some(Err(_)) = batches.peek() {
+    return write_error(stream, batches.next().unwrap().unwrap_err());
+  }
+  let header = format!(
+    "HTTP/1.1 200 OK\ncontent-type: {}\r\naccess-control-allow-origin: *\r\nconnection: close\n\n",
+    ARROW_STREAM
+  );
+  stream.write_all(header.as_bytes()).unwrap();
+  // Leaves the stream without an end marker so clients see it was cut short
+  if let Err(err) = write_ipc(&mut stream, &schema, batches) {
+    eprintln!("error writing arrow stream: {}", err);
+  }
+  stream.flush().unwrap();
+}
+
+fn write_ipc(
+  stream: &mut TcpStream,
+  schema: &Schema,
+  batches: impl Iterator<Item = Result<RecordBatch, Error>>
+) -> Result<(), Error> {
+  let mut writer = StreamWriter::try_new(stream, schema)?;
+  for batch in batches {
+    writer.write(&batch?)?;
+  }
+  Ok(writer.finish()?)
+}
+
+// Arrow IPC if the client accepts it and JSON otherwise
+pub fn write_columns(stream: TcpStream, columns: Result<Vec<OwnedColumn>, Error>, arrow: bool) {
+  let columns = match columns {
+    Ok(columns) => columns,
+    Err(err) => return write_error(stream, err)
+  };
+  if !arrow {
+    return write_contents(stream, 200, &serialize_columns(&columns), None);
+  }
+  match to_record_batch(&columns) {
+    Ok(batch) => write_batches(stream, batch.schema(), once(Ok(batch))),
+    Err(err) => write_error(stream, err)
+  }
+}
+
 pub fn handle_connection(mut stream: TcpStream, process_num: i64) {
   let len = unsafe { stream.read(&mut BUFFER).unwrap() };
   let mut headers = [httparse::EMPTY_HEADER; 16];
   let mut req = httparse::Request::new(&mut headers);
   let headers_len = unsafe { req.parse(&BUFFER).unwrap().unwrap() };
+  let arrow = accepts_arrow(req.headers);
   let method = match req.method {
     Some(m) => m,
     None => return write_contents(stream, 400, "No method specified".as_bytes(), None)
@@ -95,12 +175,10 @@ pub fn handle_connection(mut stream: TcpStream, process_num: i64) {
           None
         );
       }
-      let table = Table::open(&table_name.unwrap());
-      if let Err(_e) = table {
-        let err = format!("table \"{}\" does not exist", table_name.unwrap());
-        return write_contents(stream, 400, err.as_bytes(), None);
-      }
-      let table = table.unwrap();
+      let table = match Table::open(&table_name.unwrap()) {
+        Ok(table) => table,
+        Err(err) => return write_error(stream, err)
+      };
       match table
         .schema
         .columns
@@ -111,23 +189,35 @@ pub fn handle_connection(mut stream: TcpStream, process_num: i64) {
           let serialized = serde_json::to_vec(&table.column_symbols[index].symbols).unwrap();
           write_contents(stream, 200, &serialized, None);
         }
-        None => {
-          let err = format!(
-            "Column {} does not exist on table {}",
-            column.unwrap(),
-            table.schema.name
-          );
-          write_contents(stream, 400, err.as_bytes(), None);
-        }
+        None => write_error(stream, Error::ColumnNotFound {
+          table:  table.schema.name.clone(),
+          column: column.unwrap().to_string()
+        })
       }
     } else if path.starts_with("/ohlcv") {
       match ohlcv(&path) {
-        Err(err) => {
-          let err = format!("error parsing ohlcv: {}", err.to_string());
-          return write_contents(stream, 400, err.as_bytes(), None);
-        }
+        Err(err) => write_error(stream, err),
         Ok(res) => write_contents(stream, 200, &res, None)
       }
+    } else if path.starts_with("/asof") {
+      write_columns(stream, get_asof(&path), arrow);
+    } else if path.starts_with("/latest") {
+      write_columns(stream, get_latest(&path), arrow);
+    } else if path.starts_with("/scan") {
+      let scan = match get_scan(&path) {
+        Ok(scan) => scan,
+        Err(err) => return write_error(stream, err)
+      };
+      if !arrow {
+        return write_columns(stream, scan.to_owned_columns(), arrow);
+      }
+      match scan.table.partition_iter(scan.from_ts, scan.to_ts, scan.get_columns()) {
+        Ok(items) => {
+          let batches = items.into_arrow();
+          write_batches(stream, batches.schema(), batches)
+        }
+        Err(err) => write_error(stream, err)
+      }
     } else {
       write_contents(stream, 404, "Not found".as_bytes(), None);
     }
@@ -147,9 +237,22 @@ pub fn handle_connection(mut stream: TcpStream, process_num: i64) {
           // let res = format!("{:#04x?}", serialized);
           write_contents(stream, 200, serialized, None);
         }
-        Err(err) => write_contents(stream, 400, err.to_string().as_bytes(), None)
+        Err(err) => write_error(stream, err)
       }
     }
+  } else if method == "POST" && path == "/sql" {
+    let body = match body {
+      Some(b) => b,
+      None => return write_contents(stream, 400, "Never receieved body".as_bytes(), None)
+    };
+    let sql = match std::str::from_utf8(body) {
+      Ok(sql) => sql,
+      Err(err) => {
+        let err = format!("error parsing body: {}", err.to_string());
+        return write_contents(stream, 400, err.as_bytes(), None);
+      }
+    };
+    write_columns(stream, run_sql(sql), arrow);
   }
 }
 
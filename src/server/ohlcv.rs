@@ -1,11 +1,13 @@
-use crate::{server::query::string_to_nanoseconds, table::Table, schema::ColumnType};
-use serde::Serialize;
-use std::{
-  collections::HashMap,
-  io::{Error, ErrorKind}
+use crate::{
+  error::Error,
+  schema::ColumnType,
+  server::query::string_to_nanoseconds,
+  table::{parse_duration, scan::PartitionColumn, Alignment, Bars, Resample, Table}
 };
+use serde::Serialize;
+use std::collections::HashMap;
 
-fn querify<'a>(string: &'a str) -> Vec<(&'a str, &'a str)> {
+pub(super) fn querify<'a>(string: &'a str) -> Vec<(&'a str, &'a str)> {
   let mut v = Vec::new();
   for pair in string.split('&') {
     let mut it = pair.split('=').take(2);
@@ -18,22 +20,44 @@ fn querify<'a>(string: &'a str) -> Vec<(&'a str, &'a str)> {
   v
 }
 
-fn get_symbols<'a>(query_params: Vec<(&'a str, &'a str)>) -> Option<Vec<&'a str>> {
-  for (k, v) in query_params {
-    if k == "symbols" {
-      return Some(v.split(',').map(|s| s.trim()).collect::<Vec<_>>());
-    }
+pub(super) fn get_param<'a>(query_params: &[(&'a str, &'a str)], key: &str) -> Option<&'a str> {
+  query_params.iter().find(|(k, _v)| *k == key).map(|(_k, v)| *v)
+}
+
+fn get_symbols<'a>(query_params: &[(&'a str, &'a str)]) -> Option<Vec<&'a str>> {
+  get_param(query_params, "symbols").map(|v| v.split(',').map(|s| s.trim()).collect::<Vec<_>>())
+}
+
+// Decimals are converted to f64 which prints them exactly for up to 15 significant digits
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Price {
+  F32(f32),
+  F64(f64)
+}
+
+fn get_price(column: &PartitionColumn, row_index: usize) -> Result<Price, Error> {
+  match column.column.r#type {
+    ColumnType::F32 => Ok(Price::F32(column.get_f32()[row_index])),
+    ColumnType::F64 => Ok(Price::F64(column.get_f64()[row_index])),
+    ColumnType::Decimal32 { scale } | ColumnType::Decimal64 { scale } => Ok(Price::F64(
+      column.get_decimal(row_index) as f64 / 10_f64.powi(scale as i32)
+    )),
+    actual => Err(Error::TypeMismatch {
+      column: column.column.name.clone(),
+      expected: String::from("F32, F64 or Decimal"),
+      actual
+    })
   }
-  None
 }
 
 #[derive(Serialize)]
 struct OHLCVs {
   t: Vec<i64>,
-  o: Vec<f32>,
-  h: Vec<f32>,
-  l: Vec<f32>,
-  c: Vec<f32>,
+  o: Vec<Price>,
+  h: Vec<Price>,
+  l: Vec<Price>,
+  c: Vec<Price>,
   v: Vec<u64>
 }
 
@@ -44,37 +68,105 @@ struct OHLCVsResponse {
   max_date: i64
 }
 
-pub fn ohlcv(path: &str) -> std::io::Result<Vec<u8>> {
-  let mut query_parts = path.split('?');
-  let mut parts = query_parts.next().unwrap().split('/');
-  let symbol_query = match query_parts.next() {
-    Some(query_params) => get_symbols(querify(query_params)),
-    None => None
+// Resamples trades with "price" and "size" columns or bars. Calendar alignment is opt-in with
+// ?calendar=us_equity.
+fn resample(
+  table: &Table,
+  from: i64,
+  to: i64,
+  duration: &str,
+  query_params: &[(&str, &str)]
+) -> Result<Vec<u8>, Error> {
+  let mut resample = Resample::new(parse_duration(duration)?);
+  let has_column = |name: &str| table.schema.columns.iter().any(|c| c.name == name);
+  if !has_column("open") && has_column("price") {
+    resample = resample.trades("price", "size");
+  }
+  resample = match get_param(query_params, "calendar") {
+    None | Some("") => resample,
+    Some("us_equity") => resample.align(Alignment::UsEquity),
+    Some(calendar) => return Err(Error::Query(format!("unknown calendar {}", calendar)))
+  };
+  if let Some(symbols) = get_symbols(query_params) {
+    if symbols[0] != "" {
+      resample = resample.symbols(&symbols);
+    }
+  }
+  // Bars of F32 prices only hold F32 values
+  let price_column = resample.price.as_deref().unwrap_or("open");
+  let is_f32 = table
+    .schema
+    .columns
+    .iter()
+    .any(|c| c.name == price_column && c.r#type == ColumnType::F32);
+  let to_prices = |prices: Vec<f64>| {
+    prices
+      .into_iter()
+      .map(|p| if is_f32 { Price::F32(p as f32) } else { Price::F64(p) })
+      .collect::<Vec<_>>()
   };
-  parts.next();
-  parts.next();
-  let table_name = parts.next(); // TODO: duration like 5Minutes
-  let from = parts.next();
-  let to = parts.next();
 
-  if table_name.is_none() || from.is_none() || to.is_none() {
-    return Err(Error::new(
-      ErrorKind::Other,
-      "url must be in format /ohlcv/{table}/{from}/{to}"
-    ));
+  let mut res = OHLCVsResponse {
+    results:  HashMap::new(),
+    min_date: i64::MAX,
+    max_date: i64::MIN
+  };
+  for (symbol, bars) in table.resample(from, to, &resample)? {
+    let Bars { t, o, h, l, c, v } = bars;
+    res.min_date = res.min_date.min(t[0]);
+    res.max_date = res.max_date.max(t[t.len() - 1]);
+    res.results.insert(symbol, OHLCVs {
+      t,
+      o: to_prices(o),
+      h: to_prices(h),
+      l: to_prices(l),
+      c: to_prices(c),
+      v
+    });
   }
-  let mut from = string_to_nanoseconds(from.unwrap())?;
-  let mut to = string_to_nanoseconds(to.unwrap())?;
+
+  Ok(serde_json::to_vec(&res).unwrap())
+}
+
+pub fn ohlcv(path: &str) -> Result<Vec<u8>, Error> {
+  let mut query_parts = path.split('?');
+  let parts = query_parts.next().unwrap().split('/').skip(2).collect::<Vec<_>>();
+  let query_params = match query_parts.next() {
+    Some(query_params) => querify(query_params),
+    None => Vec::new()
+  };
+  let symbol_query = get_symbols(&query_params);
+  let (table_name, duration, from, to) = match parts[..] {
+    [table_name, from, to] => (table_name, None, from, to),
+    [table_name, duration, from, to] => (table_name, Some(duration), from, to),
+    _ => {
+      return Err(Error::Query(String::from(concat!(
+        "url must be in format /ohlcv/{table}/{from}/{to} or ",
+        "/ohlcv/{table}/{duration}/{from}/{to}"
+      ))))
+    }
+  };
+  let mut from = string_to_nanoseconds(from)?;
+  let mut to = string_to_nanoseconds(to)?;
   if from > to {
     let tmp = from;
     from = to;
     to = tmp;
   }
-  let table = Table::open(&table_name.unwrap())?;
+  let table = Table::open(table_name)?;
+  if let Some(duration) = duration {
+    return resample(&table, from, to, duration, &query_params);
+  }
 
-  let partitions = table.partition_iter(from, to, vec![
+  let mut partitions = table.partition_iter(from, to, vec![
     "ts", "sym", "open", "high", "low", "close", "volume",
-  ]);
+  ])?;
+  // Only reads the requested symbols' rows
+  if let Some(symbols) = &symbol_query {
+    if symbols[0] != "" {
+      partitions = partitions.filter_symbols("sym", symbols)?;
+    }
+  }
   let total_rows = partitions
     .partitions
     .iter()
@@ -86,16 +178,9 @@ pub fn ohlcv(path: &str) -> std::io::Result<Vec<u8>> {
     max_date: i64::MIN
   };
   for partition in partitions {
+    let partition = partition?;
     for i in 0..partition[0].row_count {
       let symbol = partition[1].get_symbol(i);
-      match symbol_query {
-        Some(ref symbols) => {
-          if symbols[0] != "" && !symbols.contains(&symbol) {
-            continue;
-          }
-        }
-        None => {}
-      };
       let ohlcvs = match res.results.get_mut(symbol) {
         Some(v) => v,
         None => {
@@ -119,16 +204,22 @@ pub fn ohlcv(path: &str) -> std::io::Result<Vec<u8>> {
         res.min_date = ts;
       }
       ohlcvs.t.push(ts);
-      ohlcvs.o.push(partition[2].get_f32()[i]);
-      ohlcvs.h.push(partition[3].get_f32()[i]);
-      ohlcvs.l.push(partition[4].get_f32()[i]);
-      ohlcvs.c.push(partition[5].get_f32()[i]);
+      ohlcvs.o.push(get_price(&partition[2], i)?);
+      ohlcvs.h.push(get_price(&partition[3], i)?);
+      ohlcvs.l.push(get_price(&partition[4], i)?);
+      ohlcvs.c.push(get_price(&partition[5], i)?);
       ohlcvs.v.push(match partition[6].column.r#type {
         ColumnType::U64 => partition[6].get_u64()[i],
         ColumnType::U32 => partition[6].get_u32()[i] as u64,
         ColumnType::U16 => partition[6].get_u16()[i] as u64,
         ColumnType::U8 => partition[6].get_u8()[i] as u64,
-        _ => panic!("Unsupported volume column type {:?}", partition[6].column.r#type)
+        actual => {
+          return Err(Error::TypeMismatch {
+            column: partition[6].column.name.clone(),
+            expected: String::from("unsigned integer"),
+            actual
+          })
+        }
       });
     }
   }
@@ -1,14 +1,14 @@
 use crate::{
   c_str,
+  error::Error,
   schema::{Column, ColumnType},
   server::julia::*,
-  table::{scan::PartitionColumn, Table}
+  table::{scan::PartitionColumn, Filter, Table}
 };
 use chrono::{DateTime, NaiveDate};
 use serde::{de, Deserialize};
 use std::{
   ffi::{c_void, CStr, CString},
-  io::{Error, ErrorKind},
   slice::from_raw_parts,
   time::Instant
 };
@@ -20,7 +20,7 @@ macro_rules! check_julia_error {
       // https://discourse.julialang.org/t/julia-exceptions-in-c/18387
       let err = jl_unbox_voidpointer(jl_eval_string(c_str!("pointer(sprint(showerror, ccall(:jl_exception_occurred, Any, ())))")));
       let err = CStr::from_ptr(err as *const i8).to_str().unwrap();
-      return Err(Error::new(ErrorKind::Other, err));
+      return Err(Error::Query(err.to_owned()));
     }
   }
 }
@@ -30,21 +30,23 @@ fn get_expected_type(column: &Column) -> *mut jl_datatype_t {
     match column.r#type {
       ColumnType::I8 => jl_int8_type,
       ColumnType::I16 => jl_int16_type,
-      ColumnType::I32 => jl_int32_type,
-      ColumnType::I64 => jl_int64_type,
+      ColumnType::I32 | ColumnType::Decimal32 { .. } => jl_int32_type,
+      ColumnType::I64 | ColumnType::Decimal64 { .. } => jl_int64_type,
       ColumnType::U8 | ColumnType::Symbol8 => jl_uint8_type,
       ColumnType::U16 | ColumnType::Symbol16 => jl_uint16_type,
       ColumnType::U32 | ColumnType::Symbol32 => jl_uint32_type,
       ColumnType::U64 => jl_uint64_type,
       ColumnType::F32 => jl_float32_type,
       ColumnType::F64 => jl_float64_type,
-      ColumnType::Timestamp => jl_int64_type
+      ColumnType::Timestamp => jl_int64_type,
+      ColumnType::String => jl_string_type,
+      ColumnType::Bytes => jl_eval_string(c_str!("Vector{UInt8}")) as *mut jl_datatype_t
     }
   }
 }
 
 static NICE_FORMAT: &str = "%Y-%m-%d";
-pub fn string_to_nanoseconds(value: &str) -> std::io::Result<i64> {
+pub fn string_to_nanoseconds(value: &str) -> Result<i64, Error> {
   // Nanoseconds since epoch?
   if value.len() > 4 {
     let nanoseconds = value.parse::<i64>();
@@ -54,15 +56,15 @@ pub fn string_to_nanoseconds(value: &str) -> std::io::Result<i64> {
   }
   // TODO: check date is in valid range before calling timestamp_nanos
   match DateTime::parse_from_rfc3339(&value) {
-    Ok(date) => Ok(date.timestamp_nanos()),
+    Ok(date) => Ok(date.timestamp_nanos_opt().unwrap()),
     Err(_e) => match NaiveDate::parse_from_str(&value, &NICE_FORMAT) {
-      Ok(date) => Ok(date.and_hms(0, 0, 0).timestamp_nanos()),
+      Ok(date) => Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_nanos_opt().unwrap()),
       Err(_e) => {
         let msg = format!(
           "Could not parse {} in RFC3339 or {} format",
           &value, &NICE_FORMAT
         );
-        Err(Error::new(ErrorKind::Other, msg))
+        Err(Error::Query(msg))
       }
     }
   }
@@ -110,12 +112,15 @@ where
 
 #[derive(Deserialize)]
 pub struct Query {
-  pub table: String,
-  pub query: String,
+  pub table:  String,
+  pub query:  String,
   #[serde(deserialize_with = "string_to_datetime")]
-  pub from:  i64,
+  pub from:   i64,
   #[serde(deserialize_with = "string_to_datetime")]
-  pub to:    i64
+  pub to:     i64,
+  // Only rows matching this are passed to the query
+  #[serde(default)]
+  pub filter: Option<Filter>
 }
 
 unsafe fn get_julia_1d_array(
@@ -144,13 +149,60 @@ unsafe fn get_julia_1d_array(
   ) as *mut jl_value_t;
 }
 
-pub fn run_query(query: &mut Query) -> std::io::Result<*mut jl_value_t> {
-  let table = Table::open(&query.table);
-  if let Err(_e) = table {
-    let err = format!("table \"{}\" does not exist", query.table);
-    return Err(Error::new(ErrorKind::Other, err));
-  }
-  let table = table.unwrap();
+// Copies values out of the heap into a Vector{String} or Vector{Vector{UInt8}}
+unsafe fn get_julia_values(partition_col: &PartitionColumn) -> *mut jl_value_t {
+  let heap = partition_col.column.heap.as_ref().unwrap();
+  let bytes_type = jl_eval_string(c_str!("Vector{UInt8}"));
+  let data = jl_ptr_to_array_1d(
+    bytes_type,
+    heap.data.as_ptr() as *mut c_void,
+    heap.data.len(),
+    0
+  ) as *mut jl_value_t;
+  let offsets = jl_ptr_to_array_1d(
+    jl_eval_string(c_str!("Vector{UInt64}")),
+    partition_col.get_u64().as_mut_ptr() as *mut c_void,
+    partition_col.row_count,
+    0
+  ) as *mut jl_value_t;
+  let value_type = match partition_col.column.r#type {
+    ColumnType::String => jl_string_type as *mut jl_value_t,
+    _ => bytes_type
+  };
+  let mut args = vec![
+    value_type,
+    data,
+    offsets,
+    jl_box_uint64(partition_col.get_heap_start() as u64),
+  ];
+
+  jl_call(
+    jl_get_function(jl_main_module, "zdb_values"),
+    args.as_mut_ptr(),
+    args.len() as i32
+  )
+}
+
+// Julia Bools are a byte each
+unsafe fn get_julia_null_mask(
+  partition_col: &PartitionColumn,
+  arg_type: &*mut jl_datatype_t,
+  tmp_masks: &mut Vec<Vec<bool>>
+) -> *mut jl_value_t {
+  let mask = partition_col.get_null_mask();
+  let ptr = mask.as_ptr();
+  tmp_masks.push(mask);
+
+  return jl_ptr_to_array_1d(
+    *arg_type as *mut jl_value_t,
+    ptr as *mut c_void,
+    partition_col.row_count,
+    0
+  ) as *mut jl_value_t;
+}
+
+pub fn run_query(query: &mut Query) -> Result<*mut jl_value_t, Error> {
+  let table = Table::open(&query.table)?;
 
   // Clear previously set module
   let jl_string = CString::new(format!("module Scan {}\nend", query.query)).unwrap();
@@ -159,10 +211,9 @@ pub fn run_query(query: &mut Query) -> std::io::Result<*mut jl_value_t> {
     check_julia_error!();
     let scan_fn = jl_eval_string(c_str!("Scan.scan"));
     if !jl_exception_occurred().is_null() || !jl_typeis(scan_fn, jl_function_type) {
-      return Err(Error::new(
-        ErrorKind::Other,
+      return Err(Error::Query(String::from(
         "must define function \"scan\" in query"
-      ));
+      )));
     }
     let n_args = jl_eval_string(c_str!("typeof(Scan.scan).name.mt.defs.func.nargs"));
     let n_args = (jl_unbox_int32(n_args) - 1) as usize;
@@ -182,17 +233,31 @@ pub fn run_query(query: &mut Query) -> std::io::Result<*mut jl_value_t> {
       (*arg_types).length - 1
     );
 
+    // Nullable columns can also be passed as a Vector{Bool} named <column>_nulls that's true for
+    // missing rows
+    let mut column_names = Vec::with_capacity(arg_names.len());
+    let mut is_masks = Vec::with_capacity(arg_names.len());
     for (arg_name, arg_type) in arg_names.iter().zip(arg_types.iter()) {
-      let column = table.schema.columns.iter().find(|c| &c.name == arg_name);
-      if column.is_none() {
-        let err = format!(
-          "column {} does not exist on table {}",
-          arg_name, table.schema.name
-        );
-        return Err(Error::new(ErrorKind::Other, err));
-      }
-      let column = column.unwrap();
-      let expected_type = get_expected_type(&column);
+      let find_column = |name: &str| table.schema.columns.iter().find(|c| c.name == name);
+      let (column, is_mask) = match find_column(arg_name) {
+        Some(column) => (column, false),
+        None => match arg_name.strip_suffix("_nulls").and_then(find_column) {
+          Some(column) if column.nullable => (column, true),
+          _ => {
+            return Err(Error::ColumnNotFound {
+              table:  table.schema.name.clone(),
+              column: arg_name.to_string()
+            })
+          }
+        }
+      };
+      column_names.push(column.name.as_str());
+      is_masks.push(is_mask);
+      let expected_type = if is_mask {
+        jl_bool_type
+      } else {
+        get_expected_type(&column)
+      };
       let arg_params = (*(*arg_type)).parameters as *mut jl_svec_t;
       let arg_params = from_raw_parts(
         jl_svec_data(arg_params) as *mut *mut jl_value_t,
@@ -204,22 +269,36 @@ pub fn run_query(query: &mut Query) -> std::io::Result<*mut jl_value_t> {
       {
         let expected_type = jl_symbol_name((*(*expected_type).name).name);
         let expected_type = CStr::from_ptr(expected_type as *const i8);
-        let mut err = format!(
-          "expected parameter {} to be of type Vector{{{:?}}}",
-          arg_name, expected_type
-        );
-        err.retain(|c| c != '"');
-        return Err(Error::new(ErrorKind::Other, err));
+        let mut expected = format!("Vector{{{:?}}}", expected_type);
+        expected.retain(|c| c != '"');
+        return Err(Error::TypeMismatch {
+          column: arg_name.to_string(),
+          expected,
+          actual: column.r#type
+        });
       }
     }
-    let partitions = table.partition_iter(query.from, query.to, arg_names);
+    let mut partitions = table.partition_iter(query.from, query.to, column_names)?;
+    if let Some(filter) = &query.filter {
+      partitions = partitions.filter(filter.clone())?;
+    }
     let mut res = jl_nothing;
     let now = Instant::now();
     for partition in partitions {
+      let partition = partition?;
       let mut args: Vec<*mut jl_value_t> = Vec::new();
       let mut tmp_columns: Vec<Vec<i64>> = Vec::new();
-      for (partition_col, arg_type) in partition.iter().zip(arg_types.iter()) {
-        args.push(get_julia_1d_array(partition_col, arg_type, &mut tmp_columns));
+      let mut tmp_masks: Vec<Vec<bool>> = Vec::new();
+      for ((partition_col, arg_type), is_mask) in
+        partition.iter().zip(arg_types.iter()).zip(is_masks.iter())
+      {
+        if *is_mask {
+          args.push(get_julia_null_mask(partition_col, arg_type, &mut tmp_masks));
+        } else if partition_col.column.heap.is_some() {
+          args.push(get_julia_values(partition_col));
+        } else {
+          args.push(get_julia_1d_array(partition_col, arg_type, &mut tmp_columns));
+        }
       }
       res = jl_call(scan_fn, args.as_mut_ptr(), args.len() as i32);
       check_julia_error!();
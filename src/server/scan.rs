@@ -0,0 +1,72 @@
+use crate::{
+  error::Error,
+  server::{asof::get_list, ohlcv::querify, query::string_to_nanoseconds},
+  table::{OwnedColumn, Table}
+};
+
+// Rows of {table} between {from} and {to}. Columns are chosen with ?columns=a,b and default to all
+// of them.
+pub struct Scan {
+  pub table:   Table,
+  pub from_ts: i64,
+  pub to_ts:   i64,
+  pub columns: Vec<String>
+}
+
+pub fn get_scan(path: &str) -> Result<Scan, Error> {
+  let mut query_parts = path.split('?');
+  let parts = query_parts.next().unwrap().split('/').skip(2).collect::<Vec<_>>();
+  let query_params = match query_parts.next() {
+    Some(query_params) => querify(query_params),
+    None => Vec::new()
+  };
+  let (table_name, from, to) = match parts[..] {
+    [table_name, from, to] => (table_name, from, to),
+    _ => {
+      return Err(Error::Query(String::from(
+        "url must be in format /scan/{table}/{from}/{to}"
+      )))
+    }
+  };
+  let from_ts = string_to_nanoseconds(from)?;
+  let to_ts = string_to_nanoseconds(to)?;
+  let table = Table::open(table_name)?;
+  let mut columns = get_list(&query_params, "columns");
+  if columns.is_empty() {
+    columns = table.schema.columns.iter().map(|c| c.name.as_str()).collect();
+  }
+  let columns = columns.iter().map(|c| c.to_string()).collect();
+
+  Ok(Scan {
+    table,
+    from_ts,
+    to_ts,
+    columns
+  })
+}
+
+impl Scan {
+  pub fn get_columns(&self) -> Vec<&str> { self.columns.iter().map(|c| c.as_str()).collect() }
+
+  // Copies every row for a JSON response
+  pub fn to_owned_columns(&self) -> Result<Vec<OwnedColumn>, Error> {
+    let items = self.table.partition_iter(self.from_ts, self.to_ts, self.get_columns())?;
+    let mut res = self
+      .get_columns()
+      .iter()
+      .map(|name| {
+        let column = self.table.schema.columns.iter().find(|c| &c.name == name).unwrap();
+        OwnedColumn::new(column, false)
+      })
+      .collect::<Vec<_>>();
+    for partition in items {
+      let partition = partition?;
+      for (res, column) in res.iter_mut().zip(&partition) {
+        for row_index in 0..column.row_count {
+          res.push(column, row_index);
+        }
+      }
+    }
+    Ok(res)
+  }
+}
@@ -0,0 +1,392 @@
+mod parse;
+
+use crate::{
+  error::Error,
+  schema::{Column, ColumnType},
+  server::query::string_to_nanoseconds,
+  table::{AggregateFn, Aggregation, Compare, Filter, GroupBy, OwnedColumn, Table}
+};
+pub use parse::{parse, Condition, Expr, Literal, OrderBy, Select, SelectItem};
+use std::cmp::{max, min, Ordering};
+
+fn get_column<'a>(table: &'a Table, name: &str) -> Result<&'a Column, Error> {
+  table
+    .schema
+    .columns
+    .iter()
+    .find(|c| c.name == name)
+    .ok_or_else(|| Error::ColumnNotFound {
+      table:  table.schema.name.clone(),
+      column: name.to_owned()
+    })
+}
+
+fn is_symbol(column: &Column) -> bool {
+  matches!(
+    column.r#type,
+    ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32
+  )
+}
+
+// Numbers are nanoseconds and strings are dates like in URLs
+fn get_timestamp(literal: &Literal) -> Result<i64, Error> {
+  match literal {
+    Literal::Number(number) => number
+      .parse::<i64>()
+      .map_err(|_| Error::Query(format!("{} is not a timestamp", number))),
+    Literal::String(string) => string_to_nanoseconds(string)
+  }
+}
+
+fn get_number(column: &Column, literal: &Literal) -> Result<f64, Error> {
+  match literal {
+    Literal::Number(number) => number
+      .parse::<f64>()
+      .map_err(|_| Error::Query(format!("{} is not a number", number))),
+    Literal::String(string) if column.r#type == ColumnType::Timestamp => {
+      Ok(string_to_nanoseconds(string)? as f64)
+    }
+    Literal::String(string) => Err(Error::Query(format!(
+      "can't compare column {} to '{}'",
+      column.name, string
+    )))
+  }
+}
+
+fn get_filter(table: &Table, condition: &Condition) -> Result<Filter, Error> {
+  let get_filters = |conditions: &[Condition]| {
+    conditions
+      .iter()
+      .map(|condition| get_filter(table, condition))
+      .collect::<Result<Vec<_>, _>>()
+  };
+  Ok(match condition {
+    Condition::Compare(name, op, literal) => {
+      let column = get_column(table, name)?;
+      match (is_symbol(column), op, literal) {
+        (true, Compare::Eq, Literal::String(symbol)) => Filter::is_in(name, &[symbol]),
+        (true, ..) => {
+          return Err(Error::Query(format!(
+            "Symbol column {} can only be compared with = 'symbol' or IN",
+            name
+          )))
+        }
+        (false, ..) => Filter::compare(name, *op, get_number(column, literal)?)
+      }
+    }
+    Condition::In(name, literals) => {
+      let column = get_column(table, name)?;
+      if is_symbol(column) {
+        let symbols = literals
+          .iter()
+          .map(|literal| match literal {
+            Literal::String(symbol) => Ok(symbol.clone()),
+            Literal::Number(number) => Err(Error::Query(format!(
+              "Symbol column {} can't be compared to {}",
+              name, number
+            )))
+          })
+          .collect::<Result<Vec<_>, _>>()?;
+        Filter::In(name.clone(), symbols)
+      } else {
+        Filter::Or(
+          literals
+            .iter()
+            .map(|literal| Ok(Filter::compare(name, Compare::Eq, get_number(column, literal)?)))
+            .collect::<Result<Vec<_>, Error>>()?
+        )
+      }
+    }
+    Condition::Between(name, from, to) => {
+      let column = get_column(table, name)?;
+      Filter::compare(name, Compare::Ge, get_number(column, from)?)
+        .and(Filter::compare(name, Compare::Le, get_number(column, to)?))
+    }
+    Condition::And(conditions) => Filter::And(get_filters(conditions)?),
+    Condition::Or(conditions) => Filter::Or(get_filters(conditions)?)
+  })
+}
+
+// Pulls the timestamp range out of the top level of `condition` and compiles the rest to a Filter
+fn get_range(
+  table: &Table,
+  condition: Option<&Condition>
+) -> Result<(i64, i64, Option<Filter>), Error> {
+  let ts_name = &table.schema.columns[0].name;
+  let conditions = match condition {
+    None => Vec::new(),
+    Some(Condition::And(conditions)) => conditions.iter().collect(),
+    Some(condition) => vec![condition]
+  };
+  let (mut from_ts, mut to_ts) = (i64::MIN, i64::MAX);
+  let mut filters = Vec::new();
+  for condition in conditions {
+    match condition {
+      Condition::Compare(name, op, literal) if name == ts_name && *op != Compare::Ne => {
+        let ts = get_timestamp(literal)?;
+        match op {
+          Compare::Lt => to_ts = min(to_ts, ts.saturating_sub(1)),
+          Compare::Le => to_ts = min(to_ts, ts),
+          Compare::Eq => {
+            from_ts = max(from_ts, ts);
+            to_ts = min(to_ts, ts);
+          }
+          Compare::Ge => from_ts = max(from_ts, ts),
+          Compare::Gt => from_ts = max(from_ts, ts.saturating_add(1)),
+          Compare::Ne => unreachable!()
+        }
+      }
+      Condition::Between(name, from, to) if name == ts_name => {
+        from_ts = max(from_ts, get_timestamp(from)?);
+        to_ts = min(to_ts, get_timestamp(to)?);
+      }
+      condition => filters.push(get_filter(table, condition)?)
+    }
+  }
+  let filter = match filters.len() {
+    0 => None,
+    1 => filters.pop(),
+    _ => Some(Filter::And(filters))
+  };
+  Ok((from_ts, to_ts, filter))
+}
+
+fn set_bucket(
+  group_by: &mut GroupBy,
+  ts_name: &str,
+  duration: i64,
+  column: &str
+) -> Result<(), Error> {
+  if column != ts_name {
+    return Err(Error::Query(format!(
+      "only the timestamp column {} can be bucketed",
+      ts_name
+    )));
+  }
+  match group_by.bucket {
+    Some(bucket) if bucket != duration => Err(Error::Query(String::from(
+      "only one bucket duration is allowed"
+    ))),
+    _ => {
+      group_by.bucket = Some(duration);
+      Ok(())
+    }
+  }
+}
+
+// Where a selected item is in the result of Table::group_by
+enum Output {
+  Group(usize),
+  Bucket,
+  Aggregate(usize)
+}
+
+impl Select {
+  // Runs a scan or a GroupBy on `table` followed by ORDER BY and LIMIT
+  pub fn run(&self, table: &Table) -> Result<Vec<OwnedColumn>, Error> {
+    let (from_ts, to_ts, filter) = get_range(table, self.condition.as_ref())?;
+    let is_aggregate = self.sample_by.is_some() ||
+      !self.group_by.is_empty() ||
+      self
+        .items
+        .iter()
+        .any(|item| matches!(item.expr, Expr::Aggregate(..) | Expr::CountAll));
+    let mut res = if is_aggregate {
+      self.aggregate(table, from_ts, to_ts, filter)?
+    } else {
+      self.scan(table, from_ts, to_ts, filter)?
+    };
+    if !self.order_by.is_empty() {
+      res = self.sort(res)?;
+    }
+    if let Some(limit) = self.limit {
+      if res.first().is_some_and(|column| column.len() > limit) {
+        let rows = (0..limit).collect::<Vec<_>>();
+        res = res.iter().map(|column| column.take(&rows)).collect();
+      }
+    }
+    Ok(res)
+  }
+
+  fn scan(
+    &self,
+    table: &Table,
+    from_ts: i64,
+    to_ts: i64,
+    filter: Option<Filter>
+  ) -> Result<Vec<OwnedColumn>, Error> {
+    let names = if self.items.is_empty() {
+      table.schema.columns.iter().map(|c| c.name.as_str()).collect()
+    } else {
+      self
+        .items
+        .iter()
+        .map(|item| match &item.expr {
+          Expr::Column(name) => Ok(name.as_str()),
+          _ => Err(Error::Query(String::from("time_bucket needs GROUP BY or an aggregate")))
+        })
+        .collect::<Result<Vec<_>, _>>()?
+    };
+    let mut partitions = table.partition_iter(from_ts, to_ts, names.clone())?;
+    if let Some(filter) = filter {
+      partitions = partitions.filter(filter)?;
+    }
+    let mut res = names
+      .iter()
+      .map(|name| Ok(OwnedColumn::new(get_column(table, name)?, false)))
+      .collect::<Result<Vec<_>, Error>>()?;
+    // Without ORDER BY only LIMIT rows are read
+    let limit = match self.limit {
+      Some(limit) if self.order_by.is_empty() => limit,
+      _ => usize::MAX
+    };
+    let mut row_count = 0;
+    'partitions: for partition in partitions {
+      let partition = partition?;
+      for row_index in 0..partition[0].row_count {
+        if row_count == limit {
+          break 'partitions;
+        }
+        for (res, column) in res.iter_mut().zip(&partition) {
+          res.push(column, row_index);
+        }
+        row_count += 1;
+      }
+    }
+    for (res, item) in res.iter_mut().zip(&self.items) {
+      if let Some(alias) = &item.alias {
+        res.name = alias.clone();
+      }
+    }
+    Ok(res)
+  }
+
+  fn aggregate(
+    &self,
+    table: &Table,
+    from_ts: i64,
+    to_ts: i64,
+    filter: Option<Filter>
+  ) -> Result<Vec<OwnedColumn>, Error> {
+    if self.items.is_empty() {
+      return Err(Error::Query(String::from("SELECT * can't be grouped")));
+    }
+    let ts_name = &table.schema.columns[0].name;
+    let mut group_by = GroupBy::new();
+    group_by.bucket = self.sample_by;
+    group_by.filter = filter;
+    for item in &self.items {
+      if let Expr::Bucket(duration, column) = &item.expr {
+        set_bucket(&mut group_by, ts_name, *duration, column)?;
+      }
+    }
+    for expr in &self.group_by {
+      match expr {
+        Expr::Bucket(duration, column) => set_bucket(&mut group_by, ts_name, *duration, column)?,
+        Expr::Column(name) => {
+          let item = self.items.iter().find(|item| item.alias.as_ref() == Some(name));
+          match item.map(|item| &item.expr) {
+            Some(Expr::Bucket(..)) => {}
+            _ if name == ts_name => {}
+            _ => group_by = group_by.by(name)
+          }
+        }
+        _ => return Err(Error::Query(String::from("can't GROUP BY an aggregate")))
+      }
+    }
+
+    let mut outputs = Vec::new();
+    for item in &self.items {
+      outputs.push(match &item.expr {
+        Expr::Column(name) => match group_by.columns.iter().position(|c| c == name) {
+          Some(index) => Output::Group(index),
+          None if name == ts_name && group_by.bucket.is_some() => Output::Bucket,
+          // Like SAMPLE BY in QuestDB other columns are keys without GROUP BY
+          None if self.group_by.is_empty() => {
+            group_by = group_by.by(name);
+            Output::Group(group_by.columns.len() - 1)
+          }
+          None => {
+            return Err(Error::Query(format!(
+              "column {} must be in GROUP BY or an aggregate",
+              name
+            )))
+          }
+        },
+        Expr::Bucket(..) => Output::Bucket,
+        Expr::CountAll => {
+          group_by = group_by.aggregate(AggregateFn::Count, ts_name);
+          Output::Aggregate(group_by.aggregations.len() - 1)
+        }
+        Expr::Aggregate(function, column, weight) => {
+          group_by.aggregations.push(Aggregation {
+            function: *function,
+            column:   column.clone(),
+            weight:   weight.clone()
+          });
+          Output::Aggregate(group_by.aggregations.len() - 1)
+        }
+      });
+    }
+
+    let columns = table.group_by(from_ts, to_ts, &group_by)?;
+    let bucket_index = group_by.columns.len();
+    let first_aggregate = bucket_index + group_by.bucket.map_or(0, |_| 1);
+    Ok(
+      outputs
+        .iter()
+        .zip(&self.items)
+        .map(|(output, item)| {
+          let mut column = match output {
+            Output::Group(index) => columns[*index].clone(),
+            Output::Bucket => columns[bucket_index].clone(),
+            Output::Aggregate(index) => columns[first_aggregate + index].clone()
+          };
+          match (&item.alias, &item.expr) {
+            (Some(alias), _) => column.name = alias.clone(),
+            (None, Expr::CountAll) => column.name = String::from("count"),
+            _ => {}
+          }
+          column
+        })
+        .collect()
+    )
+  }
+
+  fn sort(&self, columns: Vec<OwnedColumn>) -> Result<Vec<OwnedColumn>, Error> {
+    let keys = self
+      .order_by
+      .iter()
+      .map(|order_by| {
+        let index = columns.iter().position(|c| c.name == order_by.column);
+        let index = index.ok_or_else(|| {
+          Error::Query(format!("ORDER BY {} is not selected", order_by.column))
+        })?;
+        Ok((index, order_by.descending))
+      })
+      .collect::<Result<Vec<_>, Error>>()?;
+    let mut rows = (0..columns.first().map_or(0, |c| c.len())).collect::<Vec<_>>();
+    rows.sort_by(|a, b| {
+      keys
+        .iter()
+        .map(|(index, descending)| {
+          let order = columns[*index].compare_rows(*a, *b);
+          if *descending {
+            order.reverse()
+          } else {
+            order
+          }
+        })
+        .find(|order| *order != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+    });
+    Ok(columns.iter().map(|column| column.take(&rows)).collect())
+  }
+}
+
+// Compiles `sql` to a scan or GroupBy of its table and runs it
+pub fn run_sql(sql: &str) -> Result<Vec<OwnedColumn>, Error> {
+  let select = parse(sql)?;
+  let table = Table::open(&select.table)?;
+  select.run(&table)
+}
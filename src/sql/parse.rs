@@ -0,0 +1,492 @@
+use crate::{
+  error::Error,
+  table::{parse_duration, AggregateFn, Compare}
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  // Keywords and identifiers. Keywords are case insensitive.
+  Word(String),
+  // Identifiers that may be keywords
+  Quoted(String),
+  // Kept as text so timestamps don't lose precision. Includes a unit like "1h".
+  Number(String),
+  String(String),
+  Punct(&'static str)
+}
+
+// Longest first
+static PUNCTS: [&str; 12] = ["<=", ">=", "!=", "<>", "(", ")", ",", "*", "=", "<", ">", "-"];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, Error> {
+  let mut res = Vec::new();
+  let chars = sql.chars().collect::<Vec<_>>();
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    let start = i;
+    if c.is_whitespace() || c == ';' {
+      i += 1;
+    } else if c.is_ascii_alphabetic() || c == '_' {
+      while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+        i += 1;
+      }
+      res.push(Token::Word(chars[start..i].iter().collect()));
+    } else if c.is_ascii_digit() || c == '.' {
+      while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '.') {
+        i += 1;
+      }
+      res.push(Token::Number(chars[start..i].iter().collect()));
+    } else if c == '\'' || c == '"' {
+      // Quotes are escaped by doubling them
+      let mut value = String::new();
+      loop {
+        i += 1;
+        match chars.get(i) {
+          None => return Err(Error::Query(format!("unterminated {} at {}", c, start))),
+          Some(q) if *q == c && chars.get(i + 1) == Some(&c) => {
+            value.push(c);
+            i += 1;
+          }
+          Some(q) if *q == c => break,
+          Some(other) => value.push(*other)
+        }
+      }
+      i += 1;
+      res.push(if c == '\'' { Token::String(value) } else { Token::Quoted(value) });
+    } else {
+      let rest = chars[i..].iter().take(2).collect::<String>();
+      match PUNCTS.iter().find(|p| rest.starts_with(*p)) {
+        Some(punct) => {
+          i += punct.len();
+          res.push(Token::Punct(punct));
+        }
+        None => return Err(Error::Query(format!("unexpected {} at {}", c, start)))
+      }
+    }
+  }
+  Ok(res)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+  Number(String),
+  String(String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+  Compare(String, Compare, Literal),
+  In(String, Vec<Literal>),
+  // Inclusive
+  Between(String, Literal, Literal),
+  And(Vec<Condition>),
+  Or(Vec<Condition>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+  Column(String),
+  // count(*)
+  CountAll,
+  // Vwap has a volume column
+  Aggregate(AggregateFn, String, Option<String>),
+  // time_bucket('1h', ts)
+  Bucket(i64, String)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectItem {
+  pub expr:  Expr,
+  pub alias: Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+  pub column:     String,
+  pub descending: bool
+}
+
+// SELECT items FROM table [WHERE condition] [SAMPLE BY duration] [GROUP BY exprs]
+// [ORDER BY columns] [LIMIT n]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+  // Every column if empty
+  pub items:     Vec<SelectItem>,
+  pub table:     String,
+  pub condition: Option<Condition>,
+  pub sample_by: Option<i64>,
+  pub group_by:  Vec<Expr>,
+  pub order_by:  Vec<OrderBy>,
+  pub limit:     Option<usize>
+}
+
+struct Parser {
+  tokens: Vec<Token>,
+  index:  usize
+}
+
+impl Parser {
+  fn peek(&self) -> Option<&Token> { self.tokens.get(self.index) }
+
+  fn next(&mut self) -> Result<Token, Error> {
+    let token = self.tokens.get(self.index).cloned();
+    self.index += 1;
+    token.ok_or_else(|| Error::Query(String::from("unexpected end of query")))
+  }
+
+  fn unexpected<T>(&self, expected: &str) -> Result<T, Error> {
+    Err(Error::Query(match self.tokens.get(self.index - 1) {
+      Some(token) => format!("expected {} but got {:?}", expected, token),
+      None => format!("expected {} but got end of query", expected)
+    }))
+  }
+
+  fn is_keyword(&self, keyword: &str) -> bool {
+    matches!(self.peek(), Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword))
+  }
+
+  // Consumes `keyword` if it's next
+  fn keyword(&mut self, keyword: &str) -> bool {
+    let res = self.is_keyword(keyword);
+    if res {
+      self.index += 1;
+    }
+    res
+  }
+
+  fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+    self.index += 1;
+    match self.tokens.get(self.index - 1) {
+      Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => Ok(()),
+      _ => self.unexpected(&keyword.to_uppercase())
+    }
+  }
+
+  fn punct(&mut self, punct: &'static str) -> bool {
+    let res = self.peek() == Some(&Token::Punct(punct));
+    if res {
+      self.index += 1;
+    }
+    res
+  }
+
+  fn expect_punct(&mut self, punct: &str) -> Result<(), Error> {
+    self.index += 1;
+    match self.tokens.get(self.index - 1) {
+      Some(Token::Punct(p)) if *p == punct => Ok(()),
+      _ => self.unexpected(punct)
+    }
+  }
+
+  fn identifier(&mut self) -> Result<String, Error> {
+    match self.next()? {
+      Token::Word(word) if !is_reserved(&word.to_lowercase()) => Ok(word),
+      Token::Quoted(name) => Ok(name),
+      _ => self.unexpected("a name")
+    }
+  }
+
+  fn literal(&mut self) -> Result<Literal, Error> {
+    let negative = self.punct("-");
+    match self.next()? {
+      Token::Number(number) if negative => Ok(Literal::Number(format!("-{}", number))),
+      Token::Number(number) => Ok(Literal::Number(number)),
+      Token::String(string) if !negative => Ok(Literal::String(string)),
+      _ => self.unexpected("a number or 'string'")
+    }
+  }
+
+  fn duration(&mut self) -> Result<i64, Error> {
+    match self.next()? {
+      Token::Number(duration) | Token::String(duration) => parse_duration(&duration),
+      _ => self.unexpected("a duration like 1h")
+    }
+  }
+
+  fn expr(&mut self) -> Result<Expr, Error> {
+    let name = self.identifier()?;
+    if !self.punct("(") {
+      return Ok(Expr::Column(name));
+    }
+    let function = match name.to_lowercase().as_str() {
+      "count" if self.punct("*") => {
+        self.expect_punct(")")?;
+        return Ok(Expr::CountAll);
+      }
+      "time_bucket" => {
+        let duration = self.duration()?;
+        self.expect_punct(",")?;
+        let column = self.identifier()?;
+        self.expect_punct(")")?;
+        return Ok(Expr::Bucket(duration, column));
+      }
+      "sum" => AggregateFn::Sum,
+      "count" => AggregateFn::Count,
+      "min" => AggregateFn::Min,
+      "max" => AggregateFn::Max,
+      "avg" | "mean" => AggregateFn::Mean,
+      "first" => AggregateFn::First,
+      "last" => AggregateFn::Last,
+      "vwap" => AggregateFn::Vwap,
+      "stddev" | "stddev_samp" => AggregateFn::Stddev,
+      _ => return Err(Error::Query(format!("unknown function {}", name)))
+    };
+    let column = self.identifier()?;
+    let weight = if function == AggregateFn::Vwap {
+      self.expect_punct(",")?;
+      Some(self.identifier()?)
+    } else {
+      None
+    };
+    self.expect_punct(")")?;
+    Ok(Expr::Aggregate(function, column, weight))
+  }
+
+  fn select_item(&mut self) -> Result<SelectItem, Error> {
+    let expr = self.expr()?;
+    let alias = if self.keyword("as") { Some(self.identifier()?) } else { None };
+    Ok(SelectItem { expr, alias })
+  }
+
+  fn condition(&mut self) -> Result<Condition, Error> {
+    let mut conditions = vec![self.and_condition()?];
+    while self.keyword("or") {
+      conditions.push(self.and_condition()?);
+    }
+    Ok(if conditions.len() == 1 { conditions.remove(0) } else { Condition::Or(conditions) })
+  }
+
+  fn and_condition(&mut self) -> Result<Condition, Error> {
+    let mut conditions = vec![self.primary_condition()?];
+    while self.keyword("and") {
+      conditions.push(self.primary_condition()?);
+    }
+    Ok(if conditions.len() == 1 { conditions.remove(0) } else { Condition::And(conditions) })
+  }
+
+  fn primary_condition(&mut self) -> Result<Condition, Error> {
+    if self.punct("(") {
+      let condition = self.condition()?;
+      self.expect_punct(")")?;
+      return Ok(condition);
+    }
+    let column = self.identifier()?;
+    if self.keyword("in") {
+      self.expect_punct("(")?;
+      let mut literals = vec![self.literal()?];
+      while self.punct(",") {
+        literals.push(self.literal()?);
+      }
+      self.expect_punct(")")?;
+      return Ok(Condition::In(column, literals));
+    }
+    if self.keyword("between") {
+      let from = self.literal()?;
+      self.expect_keyword("and")?;
+      let to = self.literal()?;
+      return Ok(Condition::Between(column, from, to));
+    }
+    let op = match self.next()? {
+      Token::Punct("<") => Compare::Lt,
+      Token::Punct("<=") => Compare::Le,
+      Token::Punct("=") => Compare::Eq,
+      Token::Punct("!=") | Token::Punct("<>") => Compare::Ne,
+      Token::Punct(">=") => Compare::Ge,
+      Token::Punct(">") => Compare::Gt,
+      _ => return self.unexpected("a comparison, IN or BETWEEN")
+    };
+    Ok(Condition::Compare(column, op, self.literal()?))
+  }
+
+  fn select(&mut self) -> Result<Select, Error> {
+    self.expect_keyword("select")?;
+    let mut items = Vec::new();
+    if !self.punct("*") {
+      items.push(self.select_item()?);
+      while self.punct(",") {
+        items.push(self.select_item()?);
+      }
+    }
+    self.expect_keyword("from")?;
+    let table = self.identifier()?;
+    let condition = if self.keyword("where") { Some(self.condition()?) } else { None };
+    let sample_by = if self.keyword("sample") {
+      self.expect_keyword("by")?;
+      Some(self.duration()?)
+    } else {
+      None
+    };
+    let mut group_by = Vec::new();
+    if self.keyword("group") {
+      self.expect_keyword("by")?;
+      group_by.push(self.expr()?);
+      while self.punct(",") {
+        group_by.push(self.expr()?);
+      }
+    }
+    let mut order_by = Vec::new();
+    if self.keyword("order") {
+      self.expect_keyword("by")?;
+      loop {
+        let column = self.identifier()?;
+        let descending = self.keyword("desc");
+        if !descending {
+          self.keyword("asc");
+        }
+        order_by.push(OrderBy { column, descending });
+        if !self.punct(",") {
+          break;
+        }
+      }
+    }
+    let limit = if self.keyword("limit") {
+      match self.next()? {
+        Token::Number(limit) => match limit.parse::<usize>() {
+          Ok(limit) => Some(limit),
+          Err(_) => return self.unexpected("a row count")
+        },
+        _ => return self.unexpected("a row count")
+      }
+    } else {
+      None
+    };
+    if self.peek().is_some() {
+      self.index += 1;
+      return self.unexpected("end of query");
+    }
+
+    Ok(Select {
+      items,
+      table,
+      condition,
+      sample_by,
+      group_by,
+      order_by,
+      limit
+    })
+  }
+}
+
+fn is_reserved(word: &str) -> bool {
+  matches!(
+    word,
+    "select"
+      | "from"
+      | "where"
+      | "and"
+      | "or"
+      | "in"
+      | "between"
+      | "as"
+      | "sample"
+      | "group"
+      | "order"
+      | "by"
+      | "asc"
+      | "desc"
+      | "limit"
+  )
+}
+
+pub fn parse(sql: &str) -> Result<Select, Error> {
+  let mut parser = Parser {
+    tokens: tokenize(sql)?,
+    index:  0
+  };
+  parser.select()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tokens() {
+    assert_eq!(tokenize("a>=-1.5 'it''s'\"Col\"").unwrap(), vec![
+      Token::Word(String::from("a")),
+      Token::Punct(">="),
+      Token::Punct("-"),
+      Token::Number(String::from("1.5")),
+      Token::String(String::from("it's")),
+      Token::Quoted(String::from("Col")),
+    ]);
+    assert!(tokenize("a = 'b").is_err());
+    assert!(tokenize("a ~ b").is_err());
+  }
+
+  #[test]
+  fn parse_select() {
+    let select = parse(
+      "SELECT sym, time_bucket('1h', ts) AS hour, vwap(price, size), count(*) FROM trades WHERE \
+       ts >= '2021-06-01' AND (sym IN ('AAPL', 'MSFT') OR price BETWEEN 1 AND 2) GROUP BY sym, \
+       hour ORDER BY hour DESC, sym LIMIT 10;"
+    )
+    .unwrap();
+    let hour = 60 * 60 * 1_000_000_000;
+    assert_eq!(select.items, vec![
+      SelectItem {
+        expr:  Expr::Column(String::from("sym")),
+        alias: None
+      },
+      SelectItem {
+        expr:  Expr::Bucket(hour, String::from("ts")),
+        alias: Some(String::from("hour"))
+      },
+      SelectItem {
+        expr:  Expr::Aggregate(
+          AggregateFn::Vwap,
+          String::from("price"),
+          Some(String::from("size"))
+        ),
+        alias: None
+      },
+      SelectItem {
+        expr:  Expr::CountAll,
+        alias: None
+      },
+    ]);
+    assert_eq!(select.table, "trades");
+    let number = |n: &str| Literal::Number(String::from(n));
+    assert_eq!(
+      select.condition,
+      Some(Condition::And(vec![
+        Condition::Compare(
+          String::from("ts"),
+          Compare::Ge,
+          Literal::String(String::from("2021-06-01"))
+        ),
+        Condition::Or(vec![
+          Condition::In(String::from("sym"), vec![
+            Literal::String(String::from("AAPL")),
+            Literal::String(String::from("MSFT")),
+          ]),
+          Condition::Between(String::from("price"), number("1"), number("2")),
+        ]),
+      ]))
+    );
+    assert_eq!(select.group_by, vec![
+      Expr::Column(String::from("sym")),
+      Expr::Column(String::from("hour")),
+    ]);
+    assert_eq!(select.order_by, vec![
+      OrderBy {
+        column:     String::from("hour"),
+        descending: true
+      },
+      OrderBy {
+        column:     String::from("sym"),
+        descending: false
+      },
+    ]);
+    assert_eq!(select.limit, Some(10));
+
+    let select = parse("select * from t sample by 5m").unwrap();
+    assert!(select.items.is_empty());
+    assert_eq!(select.sample_by, Some(5 * 60 * 1_000_000_000));
+    assert!(parse("select from t").is_err());
+    assert!(parse("select a from t where").is_err());
+    assert!(parse("select a from t limit 1 2").is_err());
+    assert!(parse("select median(a) from t").is_err());
+  }
+}
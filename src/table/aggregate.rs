@@ -0,0 +1,130 @@
+use crate::{
+  calendar::ToNaiveDateTime,
+  error::{Error, IoContext},
+  schema::{ColumnType, PartitionBy},
+  table::{
+    index::is_symbol,
+    read::get_col_dir,
+    resample::{check_type, is_price, is_volume},
+    Bars, PartitionMeta, Table
+  }
+};
+use std::{collections::HashMap, fs::remove_dir_all};
+
+impl Table {
+  // Aggregate tables are OHLCV bars with a timestamp, symbol, open, high, low, close and volume
+  fn check_aggregate_columns(&self) -> Result<(), Error> {
+    let columns = &self.schema.columns;
+    if columns.len() != 7 {
+      return Err(Error::Query(format!(
+        "aggregate table {} must have 7 columns: timestamp, symbol, open, high, low, close and \
+         volume",
+        self.schema.name
+      )));
+    }
+    check_type(&columns[1], "a Symbol", is_symbol(columns[1].r#type))?;
+    for column in &columns[2..6] {
+      check_type(column, "F32, F64 or Decimal", is_price(column.r#type))?;
+    }
+    check_type(&columns[6], "unsigned integer", is_volume(columns[6].r#type))
+  }
+
+  // Writes bars of the source table's rows and records this table in the source's _meta. The
+  // source can't have a writer in another Table.
+  pub(super) fn backfill_aggregate(&mut self) -> Result<(), Error> {
+    self.check_aggregate_columns()?;
+    let aggregate = self.schema.aggregate.clone().unwrap();
+    let mut source = Table::open(&aggregate.source)?;
+    source.lock()?;
+    let bars = source.resample(i64::MIN, i64::MAX, &aggregate.resample)?;
+    self.write_bars(bars)?;
+    if !source.derived.contains(&self.schema.name) {
+      source.derived.push(self.schema.name.clone());
+      source.write_meta()?;
+    }
+    Ok(())
+  }
+
+  fn write_bars(&mut self, bars: HashMap<String, Bars>) -> Result<(), Error> {
+    let mut rows = bars
+      .iter()
+      .flat_map(|(symbol, bars)| (0..bars.t.len()).map(move |i| (bars.t[i], symbol, i)))
+      .collect::<Vec<_>>();
+    rows.sort_unstable();
+    for (ts, symbol, i) in rows {
+      let bars = &bars[symbol];
+      self.put_timestamp(ts)?;
+      self.put_symbol(symbol)?;
+      for price in &[bars.o[i], bars.h[i], bars.l[i], bars.c[i]] {
+        match self.schema.columns[self.column_index].r#type {
+          ColumnType::F32 => self.put_f32(*price as f32)?,
+          ColumnType::F64 => self.put_f64(*price)?,
+          ColumnType::Decimal32 { scale } | ColumnType::Decimal64 { scale } => {
+            self.put_decimal((price * 10_f64.powi(scale as i32)).round() as i64)?
+          }
+          r#type => panic!("ColumnType {:?} is not a price", r#type)
+        }
+      }
+      let volume = bars.v[i];
+      match self.schema.columns[self.column_index].r#type {
+        ColumnType::U64 => self.put_u64(volume)?,
+        ColumnType::U32 => self.put_u32(volume as u32)?,
+        ColumnType::U16 => self.put_u16(volume as u16)?,
+        _ => self.put_u8(volume as u8)?
+      }
+      self.write()?;
+    }
+    self.flush()
+  }
+
+  // Start of the partition bars with rows from `ts` on are in
+  fn get_partition_start(&self, ts: i64) -> i64 {
+    match self.schema.partition_by {
+      PartitionBy::None => i64::MIN,
+      _ if ts < 0 => i64::MIN,
+      _ => self.get_partition_ts(ts.to_naive_date_time(), 0)
+    }
+  }
+
+  // Rewrites partitions of `name` with bars that may include rows from `from_ts` on
+  fn update_aggregate(&self, name: &str, from_ts: i64) -> Result<(), Error> {
+    let mut table = Table::open(name)?;
+    table.lock()?;
+    let resample = match &table.schema.aggregate {
+      Some(aggregate) if aggregate.source == self.schema.name => aggregate.resample.clone(),
+      // Dropped or recreated
+      _ => return Ok(())
+    };
+    // Bars start at or before their rows so earlier bars end before `from_ts`
+    let rewrite_from = table.get_partition_start(from_ts.saturating_sub(resample.duration - 1));
+    let stale = table
+      .partition_meta
+      .iter()
+      .filter(|(_partition, meta)| meta.max_ts >= rewrite_from)
+      .map(|(partition, meta)| (partition.clone(), meta.clone()))
+      .collect::<Vec<(String, PartitionMeta)>>();
+    for (partition, _meta) in &stale {
+      table.partition_meta.remove(partition);
+    }
+    table.write_meta()?;
+    for (partition, meta) in &stale {
+      let dir = get_col_dir(name, &meta.dir, partition);
+      remove_dir_all(&dir).with_path(&dir)?;
+    }
+    let bars = self.resample(rewrite_from, i64::MAX, &resample)?;
+    table.write_bars(bars)
+  }
+
+  // Brings tables aggregated from this one up to date with rows written since the last flush
+  pub(super) fn update_aggregates(&mut self) -> Result<(), Error> {
+    let from_ts = match self.dirty_from {
+      Some(from_ts) => from_ts,
+      None => return Ok(())
+    };
+    for name in &self.derived {
+      self.update_aggregate(name, from_ts)?;
+    }
+    self.dirty_from = None;
+    Ok(())
+  }
+}
@@ -0,0 +1,429 @@
+use crate::{
+  error::{Error, IoContext},
+  schema::{Codec, Column, ColumnType},
+  table::{
+    codec::{check_codec, decompress_column, get_blocks_path},
+    get_col_path,
+    heap::{get_heap_len, get_heap_path, get_range},
+    index::{get_index_path, is_symbol},
+    nulls::{get_bitmap_len, get_nulls_path},
+    read::{get_capacity, get_symbols_path, is_variable_length, read_column_symbols},
+    zones::get_zones_path,
+    PartitionMeta, Table
+  }
+};
+use std::{
+  convert::{TryFrom, TryInto},
+  fs::{hard_link, read, remove_file, OpenOptions},
+  io::{ErrorKind, Write},
+  path::PathBuf
+};
+
+// Schema changes write new files first, then commit by writing _meta and only then remove old
+// files. A crash before the commit leaves stray files that the next attempt overwrites.
+
+enum Number {
+  // Scaled integer and its scale
+  Int(i128, u32),
+  Float(f64)
+}
+
+fn is_numeric(r#type: ColumnType) -> bool {
+  !matches!(
+    r#type,
+    ColumnType::Timestamp
+      | ColumnType::Symbol8
+      | ColumnType::Symbol16
+      | ColumnType::Symbol32
+      | ColumnType::String
+      | ColumnType::Bytes
+  )
+}
+
+fn read_number(r#type: ColumnType, bytes: &[u8]) -> Number {
+  match r#type {
+    ColumnType::I8 => Number::Int(bytes[0] as i8 as i128, 0),
+    ColumnType::U8 | ColumnType::Symbol8 => Number::Int(bytes[0] as i128, 0),
+    ColumnType::I16 => Number::Int(i16::from_le_bytes(bytes.try_into().unwrap()) as i128, 0),
+    ColumnType::U16 | ColumnType::Symbol16 => {
+      Number::Int(u16::from_le_bytes(bytes.try_into().unwrap()) as i128, 0)
+    }
+    ColumnType::I32 => Number::Int(i32::from_le_bytes(bytes.try_into().unwrap()) as i128, 0),
+    ColumnType::U32 | ColumnType::Symbol32 => {
+      Number::Int(u32::from_le_bytes(bytes.try_into().unwrap()) as i128, 0)
+    }
+    ColumnType::I64 => Number::Int(i64::from_le_bytes(bytes.try_into().unwrap()) as i128, 0),
+    ColumnType::U64 => Number::Int(u64::from_le_bytes(bytes.try_into().unwrap()) as i128, 0),
+    ColumnType::Decimal32 { scale } => {
+      Number::Int(i32::from_le_bytes(bytes.try_into().unwrap()) as i128, scale)
+    }
+    ColumnType::Decimal64 { scale } => {
+      Number::Int(i64::from_le_bytes(bytes.try_into().unwrap()) as i128, scale)
+    }
+    ColumnType::F32 => Number::Float(f32::from_le_bytes(bytes.try_into().unwrap()) as f64),
+    ColumnType::F64 => Number::Float(f64::from_le_bytes(bytes.try_into().unwrap())),
+    r#type => panic!("ColumnType {:?} is not a number", r#type)
+  }
+}
+
+fn to_int(number: &Number, scale: u32) -> Option<i128> {
+  match *number {
+    Number::Int(val, from_scale) if from_scale <= scale => {
+      val.checked_mul(10_i128.pow(scale - from_scale))
+    }
+    Number::Int(val, from_scale) => Some(val / 10_i128.pow(from_scale - scale)),
+    Number::Float(val) => {
+      let val = (val * 10_f64.powi(scale as i32)).round();
+      if val.is_finite() {
+        Some(val as i128)
+      } else {
+        None
+      }
+    }
+  }
+}
+
+fn to_float(number: &Number) -> f64 {
+  match *number {
+    Number::Int(val, scale) => val as f64 / 10_f64.powi(scale as i32),
+    Number::Float(val) => val
+  }
+}
+
+macro_rules! write_int {
+  ($out: expr, $number: expr, $scale: expr, $_type: ty) => {
+    to_int($number, $scale)
+      .and_then(|val| <$_type>::try_from(val).ok())
+      .map(|val| $out.copy_from_slice(&val.to_le_bytes()))
+      .is_some()
+  };
+}
+
+// Returns false if `number` doesn't fit
+fn write_number(r#type: ColumnType, number: &Number, out: &mut [u8]) -> bool {
+  match r#type {
+    ColumnType::I8 => write_int!(out, number, 0, i8),
+    ColumnType::U8 | ColumnType::Symbol8 => write_int!(out, number, 0, u8),
+    ColumnType::I16 => write_int!(out, number, 0, i16),
+    ColumnType::U16 | ColumnType::Symbol16 => write_int!(out, number, 0, u16),
+    ColumnType::I32 => write_int!(out, number, 0, i32),
+    ColumnType::U32 | ColumnType::Symbol32 => write_int!(out, number, 0, u32),
+    ColumnType::I64 => write_int!(out, number, 0, i64),
+    ColumnType::U64 => write_int!(out, number, 0, u64),
+    ColumnType::Decimal32 { scale } => write_int!(out, number, scale, i32),
+    ColumnType::Decimal64 { scale } => write_int!(out, number, scale, i64),
+    ColumnType::F32 => {
+      out.copy_from_slice(&(to_float(number) as f32).to_le_bytes());
+      true
+    }
+    ColumnType::F64 => {
+      out.copy_from_slice(&to_float(number).to_le_bytes());
+      true
+    }
+    r#type => panic!("ColumnType {:?} is not a number", r#type)
+  }
+}
+
+// Suffixes of the column's main file and the files next to it
+fn get_suffixes(col_path: &PathBuf, column: &Column) -> Vec<&'static str> {
+  let mut res = vec![""];
+  if column.nullable {
+    res.push(".nulls");
+  }
+  if is_variable_length(column.r#type) {
+    res.push(".data");
+  }
+  if get_blocks_path(col_path).exists() {
+    res.push(".blocks");
+  }
+  if get_zones_path(col_path).exists() {
+    res.push(".zones");
+  }
+  if get_index_path(col_path).exists() {
+    res.push(".index");
+  }
+  res
+}
+
+fn with_suffix(col_path: &PathBuf, suffix: &str) -> PathBuf {
+  let mut path = col_path.clone().into_os_string();
+  path.push(suffix);
+  PathBuf::from(path)
+}
+
+fn get_files(col_path: &PathBuf, column: &Column) -> Vec<PathBuf> {
+  get_suffixes(col_path, column)
+    .iter()
+    .map(|suffix| with_suffix(col_path, suffix))
+    .collect()
+}
+
+fn write_file(path: &PathBuf, bytes: &[u8], len: usize) -> Result<(), Error> {
+  let mut f = OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .open(path)
+    .with_path(path)?;
+  f.write_all(bytes)
+    .and_then(|_| f.set_len(len as u64))
+    .and_then(|_| f.sync_all())
+    .with_path(path)
+}
+
+fn link_file(from: &PathBuf, to: &PathBuf) -> Result<(), Error> {
+  remove_stale(to)?;
+  hard_link(from, to).with_path(to)
+}
+
+fn remove_stale(path: &PathBuf) -> Result<(), Error> {
+  match remove_file(path) {
+    Err(error) if error.kind() != ErrorKind::NotFound => Err(Error::Io {
+      path: path.clone(),
+      error
+    }),
+    _ => Ok(())
+  }
+}
+
+impl Table {
+  fn get_column_index(&self, name: &str) -> Result<usize, Error> {
+    self
+      .schema
+      .columns
+      .iter()
+      .position(|c| c.name == name)
+      .ok_or_else(|| Error::ColumnNotFound {
+        table:  self.schema.name.clone(),
+        column: name.to_owned()
+      })
+  }
+
+  fn get_partitions(&self) -> Vec<(String, PartitionMeta)> {
+    self
+      .partition_meta
+      .iter()
+      .map(|(partition, meta)| (partition.clone(), meta.clone()))
+      .collect()
+  }
+
+  fn get_path(&self, partition: &str, meta: &PartitionMeta, column: &Column) -> PathBuf {
+    get_col_path(&meta.dir, &self.schema.name, partition, column)
+  }
+
+  // Commits pending rows and closes the current partition so it's reopened with the new schema
+  pub(super) fn close_partition(&mut self) -> Result<(), Error> {
+    if self.column_index != 0 {
+      return Err(Error::Query(String::from(
+        "cannot change the schema in the middle of a row"
+      )));
+    }
+    self.lock()?;
+    self.flush()?;
+    self.columns.clear();
+    self.cur_partition_meta = PartitionMeta::default();
+    Ok(())
+  }
+
+  fn get_data_path(&self) -> PathBuf { self.meta_path.parent().unwrap().to_path_buf() }
+
+  // Columns are added to the end. Existing rows read as null if the column is nullable and zero
+  // (or empty) otherwise.
+  pub fn add_column(&mut self, column: Column) -> Result<(), Error> {
+    check_codec(&column)?;
+    self.close_partition()?;
+    if self.schema.columns.iter().any(|c| c.name == column.name) {
+      return Err(Error::ColumnExists {
+        table:  self.schema.name.clone(),
+        column: column.name
+      });
+    }
+    self.schema.columns.push(column);
+    self.schema.set_timestamp_size();
+    let column = self.schema.columns.last().unwrap().clone();
+
+    for (partition, meta) in self.get_partitions() {
+      let path = self.get_path(&partition, &meta, &column);
+      write_file(&path, &[], meta.row_count * column.size)?;
+      if column.nullable {
+        let bitmap_len = get_bitmap_len(meta.row_count);
+        write_file(&get_nulls_path(&path), &vec![0xff; bitmap_len], bitmap_len)?;
+      }
+      if is_variable_length(column.r#type) {
+        write_file(&get_heap_path(&path), &[], 0)?;
+      }
+    }
+    let data_path = self.get_data_path();
+    let mut symbols = read_column_symbols(&data_path, &self.schema)?;
+    self.column_symbols.push(symbols.pop().unwrap());
+    self.write_meta()
+  }
+
+  pub fn drop_column(&mut self, name: &str) -> Result<(), Error> {
+    self.close_partition()?;
+    let index = self.get_column_index(name)?;
+    if index == 0 {
+      return Err(Error::Query(String::from("cannot drop the timestamp column")));
+    }
+    let column = self.schema.columns.remove(index);
+    let symbols = self.column_symbols.remove(index);
+    self.write_meta()?;
+
+    for (partition, meta) in self.get_partitions() {
+      for path in get_files(&self.get_path(&partition, &meta, &column), &column) {
+        remove_stale(&path)?;
+      }
+    }
+    // Symbols in a sym_name file may be shared with other columns
+    if is_symbol(column.r#type) && column.sym_name.is_empty() {
+      remove_stale(&symbols.path)?;
+    }
+    Ok(())
+  }
+
+  pub fn rename_column(&mut self, name: &str, new_name: &str) -> Result<(), Error> {
+    self.close_partition()?;
+    let index = self.get_column_index(name)?;
+    if self.schema.columns.iter().any(|c| c.name == new_name) {
+      return Err(Error::ColumnExists {
+        table:  self.schema.name.clone(),
+        column: new_name.to_owned()
+      });
+    }
+    let column = self.schema.columns[index].clone();
+    let mut new_column = column.clone();
+    new_column.name = new_name.to_owned();
+
+    let mut old_files = Vec::new();
+    for (partition, meta) in self.get_partitions() {
+      let old_path = self.get_path(&partition, &meta, &column);
+      let new_path = self.get_path(&partition, &meta, &new_column);
+      for suffix in get_suffixes(&old_path, &column) {
+        let old_file = with_suffix(&old_path, suffix);
+        link_file(&old_file, &with_suffix(&new_path, suffix))?;
+        old_files.push(old_file);
+      }
+    }
+    if is_symbol(column.r#type) && column.sym_name.is_empty() {
+      let old_path = self.column_symbols[index].path.clone();
+      let new_path = get_symbols_path(&self.get_data_path(), &new_column);
+      if old_path.exists() {
+        link_file(&old_path, &new_path)?;
+        old_files.push(old_path);
+      }
+      self.column_symbols[index].path = new_path;
+    }
+    self.schema.columns[index] = new_column;
+    self.write_meta()?;
+
+    for path in old_files {
+      remove_stale(&path)?;
+    }
+    Ok(())
+  }
+
+  // Supports converting between numeric types (including Decimals), between Symbol sizes and
+  // between String and Bytes. Fails without changing anything if a value doesn't fit.
+  pub fn alter_column_type(&mut self, name: &str, r#type: ColumnType) -> Result<(), Error> {
+    self.close_partition()?;
+    let index = self.get_column_index(name)?;
+    let column = self.schema.columns[index].clone();
+    if column.r#type == r#type {
+      return Ok(());
+    }
+    let mut new_column = Column {
+      r#type,
+      size: Column::new(name, r#type).size,
+      ..column.clone()
+    };
+    if check_codec(&new_column).is_err() {
+      new_column.codec = Codec::None;
+    }
+    let is_supported = (is_numeric(column.r#type) && is_numeric(r#type))
+      || (is_symbol(column.r#type) && is_symbol(r#type))
+      || (is_variable_length(column.r#type) && is_variable_length(r#type));
+    if index == 0 || !is_supported {
+      return Err(Error::Query(format!(
+        "cannot convert column {} from {:?} to {:?}",
+        name, column.r#type, r#type
+      )));
+    }
+    let capacity = get_capacity(&new_column);
+    if is_symbol(r#type) && self.column_symbols[index].symbols.len() + 1 >= capacity {
+      return Err(Error::SymbolOverflow {
+        column:   name.to_owned(),
+        capacity: capacity - 1
+      });
+    }
+    // Only a Decimal's scale can change without changing the file name
+    let partitions = self.get_partitions();
+    if let Some((partition, meta)) = partitions.first() {
+      if self.get_path(partition, meta, &column) == self.get_path(partition, meta, &new_column) {
+        return Err(Error::Query(format!(
+          "cannot change the scale of {} in place. Add a new column instead",
+          name
+        )));
+      }
+    }
+
+    let mut old_files = Vec::new();
+    for (partition, meta) in partitions {
+      let old_path = self.get_path(&partition, &meta, &column);
+      let new_path = self.get_path(&partition, &meta, &new_column);
+      let data = if meta.sealed && get_blocks_path(&old_path).exists() {
+        let rows = 0..meta.row_count;
+        decompress_column(&old_path, &column, meta.row_count, rows)?.1.to_vec()
+      } else {
+        read(&old_path).with_path(&old_path)?
+      };
+      let data = &data[..meta.row_count * column.size];
+
+      if is_variable_length(r#type) {
+        let heap_path = get_heap_path(&old_path);
+        let heap = read(&heap_path).with_path(&heap_path)?;
+        if r#type == ColumnType::String {
+          for row_index in 0..meta.row_count {
+            if std::str::from_utf8(&heap[get_range(data, row_index)]).is_err() {
+              return Err(Error::Query(format!(
+                "column {} has invalid UTF-8 in partition {} row {}",
+                name, partition, row_index
+              )));
+            }
+          }
+        }
+        let heap_len = get_heap_len(data, meta.row_count);
+        link_file(&old_path, &new_path)?;
+        write_file(&get_heap_path(&new_path), &heap[..heap_len], heap_len)?;
+      } else {
+        let mut new_data = vec![0_u8; meta.row_count * new_column.size];
+        for row_index in 0..meta.row_count {
+          let number = read_number(
+            column.r#type,
+            &data[row_index * column.size..(row_index + 1) * column.size]
+          );
+          let out = &mut new_data[row_index * new_column.size..(row_index + 1) * new_column.size];
+          if !write_number(r#type, &number, out) {
+            let _ = remove_file(&new_path);
+            return Err(Error::Query(format!(
+              "column {} partition {} row {} does not fit in {:?}",
+              name, partition, row_index, r#type
+            )));
+          }
+        }
+        write_file(&new_path, &new_data, new_data.len())?;
+      }
+      if column.nullable {
+        link_file(&get_nulls_path(&old_path), &get_nulls_path(&new_path))?;
+      }
+      old_files.extend(get_files(&old_path, &column));
+    }
+    self.schema.columns[index] = new_column;
+    self.write_meta()?;
+
+    for path in old_files {
+      remove_stale(&path)?;
+    }
+    Ok(())
+  }
+}
+
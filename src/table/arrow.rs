@@ -0,0 +1,277 @@
+use crate::{
+  error::Error,
+  schema::{Column, ColumnType},
+  table::{
+    scan::{PartitionColumn, PartitionIterator},
+    ColumnBuffer, OwnedColumn
+  }
+};
+use arrow_array::{
+  types::{ArrowDictionaryKeyType, UInt16Type, UInt32Type, UInt8Type},
+  ArrayRef, Decimal128Array, DictionaryArray, Float32Array, Float64Array, Int16Array, Int32Array,
+  Int64Array, Int8Array, LargeBinaryArray, LargeStringArray, PrimitiveArray, RecordBatch,
+  StringArray, TimestampNanosecondArray, UInt16Array, UInt32Array, UInt64Array, UInt8Array
+};
+use arrow_buffer::{ArrowNativeType, BooleanBuffer, Buffer, NullBuffer, OffsetBuffer, ScalarBuffer};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use std::{iter::once, panic::AssertUnwindSafe, ptr::NonNull, sync::Arc};
+
+const TIMEZONE: &str = "UTC";
+
+pub fn get_data_type(r#type: ColumnType) -> DataType {
+  let dictionary = |key: DataType| DataType::Dictionary(Box::new(key), Box::new(DataType::Utf8));
+  match r#type {
+    ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Nanosecond, Some(TIMEZONE.into())),
+    // Keys are the stored symbol ids
+    ColumnType::Symbol8 => dictionary(DataType::UInt8),
+    ColumnType::Symbol16 => dictionary(DataType::UInt16),
+    ColumnType::Symbol32 => dictionary(DataType::UInt32),
+    ColumnType::I8 => DataType::Int8,
+    ColumnType::U8 => DataType::UInt8,
+    ColumnType::I16 => DataType::Int16,
+    ColumnType::U16 => DataType::UInt16,
+    ColumnType::I32 => DataType::Int32,
+    ColumnType::U32 => DataType::UInt32,
+    ColumnType::F32 => DataType::Float32,
+    ColumnType::I64 => DataType::Int64,
+    ColumnType::U64 => DataType::UInt64,
+    ColumnType::F64 => DataType::Float64,
+    ColumnType::String => DataType::LargeUtf8,
+    ColumnType::Bytes => DataType::LargeBinary,
+    // Enough digits for any i32 or i64
+    ColumnType::Decimal32 { scale } => DataType::Decimal128(10, scale as i8),
+    ColumnType::Decimal64 { scale } => DataType::Decimal128(19, scale as i8)
+  }
+}
+
+pub fn get_field(column: &Column) -> Field {
+  Field::new(&column.name, get_data_type(column.r#type), column.nullable)
+}
+
+// Dictionary values for a symbol column. Id 0 is null so the rest line up with symbol ids.
+pub fn get_dictionary(symbols: &[String]) -> ArrayRef {
+  let values = once(None).chain(symbols.iter().map(|symbol| Some(symbol.as_str())));
+  Arc::new(values.collect::<StringArray>())
+}
+
+fn get_timestamps(values: ScalarBuffer<i64>, nulls: Option<NullBuffer>) -> ArrayRef {
+  Arc::new(TimestampNanosecondArray::new(values, nulls).with_timezone(TIMEZONE))
+}
+
+fn get_decimals(
+  values: impl Iterator<Item = i128>,
+  nulls: Option<NullBuffer>,
+  r#type: ColumnType
+) -> Result<ArrayRef, Error> {
+  let (precision, scale) = match get_data_type(r#type) {
+    DataType::Decimal128(precision, scale) => (precision, scale),
+    data_type => panic!("DataType {:?} is not a Decimal128", data_type)
+  };
+  let array = Decimal128Array::new(values.collect(), nulls);
+  Ok(Arc::new(array.with_precision_and_scale(precision, scale)?))
+}
+
+impl PartitionColumn<'_> {
+  fn get_nulls(&self) -> Option<NullBuffer> {
+    if !self.is_nullable() {
+      return None;
+    }
+    Some(NullBuffer::new(BooleanBuffer::collect_bool(self.row_count, |i| !self.is_null(i))))
+  }
+
+  // Wraps the mapped rows without copying. The column's files stay mapped until every array
+  // sharing them is dropped.
+  fn into_buffer<T: ArrowNativeType>(self) -> ScalarBuffer<T> {
+    let ptr = NonNull::new(self.slice.as_mut_ptr()).unwrap();
+    let len = self.slice.len();
+    let owner = Arc::new(AssertUnwindSafe(self.column));
+    let buffer = unsafe { Buffer::from_custom_allocation(ptr, len, owner) };
+    ScalarBuffer::new(buffer, 0, self.row_count)
+  }
+
+  fn into_dictionary<K: ArrowDictionaryKeyType>(
+    self,
+    nulls: Option<NullBuffer>,
+    values: ArrayRef
+  ) -> Result<ArrayRef, Error> {
+    let keys = PrimitiveArray::<K>::new(self.into_buffer(), nulls);
+    Ok(Arc::new(DictionaryArray::try_new(keys, values)?))
+  }
+
+  // Offsets are copied to prepend the first value's start. Values are the mapped heap.
+  fn into_heap(self) -> (OffsetBuffer<i64>, Buffer) {
+    let start = self.get_heap_start() as i64;
+    let ends = self.get_u64().iter().map(|end| *end as i64);
+    let offsets = OffsetBuffer::new(once(start).chain(ends).collect());
+    let heap = &self.column.heap.as_ref().unwrap().data;
+    let (ptr, len) = (NonNull::new(heap.as_ptr() as *mut u8).unwrap(), heap.len());
+    let owner = Arc::new(AssertUnwindSafe(self.column));
+    (offsets, unsafe { Buffer::from_custom_allocation(ptr, len, owner) })
+  }
+
+  // Fixed width columns share the partition's mapped files. Symbols become dictionary arrays over
+  // `dictionary` or the column's symbols if it's None.
+  pub fn into_arrow(self, dictionary: Option<&ArrayRef>) -> Result<ArrayRef, Error> {
+    let nulls = self.get_nulls();
+    let r#type = self.column.r#type;
+    let values = || match dictionary {
+      Some(dictionary) => dictionary.clone(),
+      None => get_dictionary(self.symbols)
+    };
+    Ok(match r#type {
+      ColumnType::Timestamp if self.column.size == 8 => get_timestamps(self.into_buffer(), nulls),
+      ColumnType::Timestamp => {
+        let values = (0..self.row_count).map(|i| self.get_timestamp(i)).collect();
+        get_timestamps(values, nulls)
+      }
+      ColumnType::Symbol8 => {
+        let values = values();
+        self.into_dictionary::<UInt8Type>(nulls, values)?
+      }
+      ColumnType::Symbol16 => {
+        let values = values();
+        self.into_dictionary::<UInt16Type>(nulls, values)?
+      }
+      ColumnType::Symbol32 => {
+        let values = values();
+        self.into_dictionary::<UInt32Type>(nulls, values)?
+      }
+      ColumnType::I8 => Arc::new(Int8Array::new(self.into_buffer(), nulls)),
+      ColumnType::U8 => Arc::new(UInt8Array::new(self.into_buffer(), nulls)),
+      ColumnType::I16 => Arc::new(Int16Array::new(self.into_buffer(), nulls)),
+      ColumnType::U16 => Arc::new(UInt16Array::new(self.into_buffer(), nulls)),
+      ColumnType::I32 => Arc::new(Int32Array::new(self.into_buffer(), nulls)),
+      ColumnType::U32 => Arc::new(UInt32Array::new(self.into_buffer(), nulls)),
+      ColumnType::F32 => Arc::new(Float32Array::new(self.into_buffer(), nulls)),
+      ColumnType::I64 => Arc::new(Int64Array::new(self.into_buffer(), nulls)),
+      ColumnType::U64 => Arc::new(UInt64Array::new(self.into_buffer(), nulls)),
+      ColumnType::F64 => Arc::new(Float64Array::new(self.into_buffer(), nulls)),
+      ColumnType::String => {
+        let (offsets, values) = self.into_heap();
+        Arc::new(LargeStringArray::try_new(offsets, values, nulls)?)
+      }
+      ColumnType::Bytes => {
+        let (offsets, values) = self.into_heap();
+        Arc::new(LargeBinaryArray::try_new(offsets, values, nulls)?)
+      }
+      // Widened to i128
+      ColumnType::Decimal32 { .. } | ColumnType::Decimal64 { .. } => {
+        get_decimals((0..self.row_count).map(|i| self.get_decimal(i) as i128), nulls, r#type)?
+      }
+    })
+  }
+}
+
+// One record batch per scanned partition. Symbol dictionaries are shared between batches so IPC
+// streams only send them once.
+pub struct RecordBatches<'a> {
+  items:        PartitionIterator<'a>,
+  schema:       SchemaRef,
+  dictionaries: Vec<Option<ArrayRef>>
+}
+
+impl<'a> PartitionIterator<'a> {
+  pub fn into_arrow(self) -> RecordBatches<'a> {
+    let fields = self.columns.iter().map(|c| get_field(&c.column)).collect::<Vec<_>>();
+    let dictionaries = self
+      .columns
+      .iter()
+      .map(|c| match c.column.r#type {
+        ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32 => {
+          Some(get_dictionary(c.symbols))
+        }
+        _ => None
+      })
+      .collect();
+    RecordBatches {
+      items: self,
+      schema: Arc::new(Schema::new(fields)),
+      dictionaries
+    }
+  }
+}
+
+impl RecordBatches<'_> {
+  pub fn schema(&self) -> SchemaRef { self.schema.clone() }
+}
+
+impl Iterator for RecordBatches<'_> {
+  type Item = Result<RecordBatch, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let partition = match self.items.next()? {
+      Ok(partition) => partition,
+      Err(err) => return Some(Err(err))
+    };
+    let columns = partition
+      .into_iter()
+      .zip(&self.dictionaries)
+      .map(|(column, dictionary)| column.into_arrow(dictionary.as_ref()))
+      .collect::<Result<Vec<_>, _>>();
+    Some(columns.and_then(|columns| Ok(RecordBatch::try_new(self.schema(), columns)?)))
+  }
+}
+
+fn get_symbols<K: ArrowDictionaryKeyType>(
+  values: &[String],
+  nulls: &Option<Vec<bool>>
+) -> ArrayRef {
+  let is_null = |i: usize| nulls.as_ref().is_some_and(|nulls| nulls[i]);
+  let values = values.iter().enumerate().map(|(i, v)| Some(v.as_str()).filter(|_| !is_null(i)));
+  Arc::new(values.collect::<DictionaryArray<K>>())
+}
+
+impl OwnedColumn {
+  pub fn get_field(&self) -> Field {
+    Field::new(&self.name, get_data_type(self.r#type), self.nulls.is_some())
+  }
+
+  // Copies the values. Symbols become dictionary arrays over the symbols that appear.
+  pub fn to_arrow(&self) -> Result<ArrayRef, Error> {
+    let nulls = self
+      .nulls
+      .as_ref()
+      .map(|nulls| NullBuffer::new(nulls.iter().map(|is_null| !is_null).collect()));
+    Ok(match (&self.data, self.r#type) {
+      (ColumnBuffer::Timestamp(v), _) => get_timestamps(v.clone().into(), nulls),
+      (ColumnBuffer::Symbol(v), ColumnType::Symbol8) => get_symbols::<UInt8Type>(v, &self.nulls),
+      (ColumnBuffer::Symbol(v), ColumnType::Symbol16) => {
+        get_symbols::<UInt16Type>(v, &self.nulls)
+      }
+      (ColumnBuffer::Symbol(v), _) => get_symbols::<UInt32Type>(v, &self.nulls),
+      (ColumnBuffer::I32(v), ColumnType::Decimal32 { .. }) => {
+        get_decimals(v.iter().map(|v| *v as i128), nulls, self.r#type)?
+      }
+      (ColumnBuffer::I64(v), ColumnType::Decimal64 { .. }) => {
+        get_decimals(v.iter().map(|v| *v as i128), nulls, self.r#type)?
+      }
+      (ColumnBuffer::I8(v), _) => Arc::new(Int8Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::U8(v), _) => Arc::new(UInt8Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::I16(v), _) => Arc::new(Int16Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::U16(v), _) => Arc::new(UInt16Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::I32(v), _) => Arc::new(Int32Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::U32(v), _) => Arc::new(UInt32Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::F32(v), _) => Arc::new(Float32Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::I64(v), _) => Arc::new(Int64Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::U64(v), _) => Arc::new(UInt64Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::F64(v), _) => Arc::new(Float64Array::new(v.clone().into(), nulls)),
+      (ColumnBuffer::String(v), _) => {
+        let offsets = OffsetBuffer::from_lengths(v.iter().map(|v| v.len()));
+        let values = Buffer::from_vec(v.iter().flat_map(|v| v.bytes()).collect::<Vec<_>>());
+        Arc::new(LargeStringArray::try_new(offsets, values, nulls)?)
+      }
+      (ColumnBuffer::Bytes(v), _) => {
+        let offsets = OffsetBuffer::from_lengths(v.iter().map(|v| v.len()));
+        let values = Buffer::from_vec(v.iter().flatten().copied().collect::<Vec<_>>());
+        Arc::new(LargeBinaryArray::try_new(offsets, values, nulls)?)
+      }
+    })
+  }
+}
+
+// Query results as a single record batch
+pub fn to_record_batch(columns: &[OwnedColumn]) -> Result<RecordBatch, Error> {
+  let schema = Schema::new(columns.iter().map(|c| c.get_field()).collect::<Vec<_>>());
+  let arrays = columns.iter().map(|c| c.to_arrow()).collect::<Result<Vec<_>, _>>()?;
+  Ok(RecordBatch::try_new(Arc::new(schema), arrays)?)
+}
@@ -0,0 +1,182 @@
+use crate::{
+  error::Error,
+  table::{index::is_symbol, resample::check_type, scan::PartitionColumn, OwnedColumn, Table}
+};
+
+// Which columns to join and how to match rows. Each left row is matched with the last right row
+// at or before it.
+#[derive(Debug, Clone)]
+pub struct AsOf {
+  pub left_columns:  Vec<String>,
+  pub right_columns: Vec<String>,
+  // Symbol column with this name in both tables that rows must also match on
+  pub by:            Option<String>,
+  // Right rows more than this many nanoseconds before a left row don't match
+  pub tolerance:     Option<i64>
+}
+
+impl AsOf {
+  pub fn new(left_columns: &[&str], right_columns: &[&str]) -> Self {
+    Self {
+      left_columns:  left_columns.iter().map(|c| c.to_string()).collect(),
+      right_columns: right_columns.iter().map(|c| c.to_string()).collect(),
+      by:            None,
+      tolerance:     None
+    }
+  }
+
+  pub fn by(mut self, column: &str) -> Self {
+    self.by = Some(column.to_owned());
+    self
+  }
+
+  pub fn tolerance(mut self, tolerance: i64) -> Self {
+    self.tolerance = Some(tolerance);
+    self
+  }
+
+  // Timestamp, then `by`, then `columns`
+  fn get_scan_columns<'a>(&'a self, table: &'a Table, columns: &'a [String]) -> Vec<&'a str> {
+    let mut res = vec![table.schema.columns[0].name.as_str()];
+    if let Some(by) = &self.by {
+      res.push(by);
+    }
+    res.extend(columns.iter().map(|c| c.as_str()));
+    res
+  }
+}
+
+impl Table {
+  // Joins each of this table's rows between `from_ts` and `to_ts` with `right`'s last row at or
+  // before it. Merges both tables' sorted timestamps so each is scanned once. Returns the left
+  // columns followed by the right columns.
+  pub fn asof_join(
+    &self,
+    right: &Table,
+    from_ts: i64,
+    to_ts: i64,
+    asof: &AsOf
+  ) -> Result<Vec<OwnedColumn>, Error> {
+    let by_index = match &asof.by {
+      Some(by) => {
+        for table in &[self, right] {
+          let column = table.schema.columns.iter().find(|c| &c.name == by);
+          let column = column.ok_or_else(|| Error::ColumnNotFound {
+            table:  table.schema.name.clone(),
+            column: by.clone()
+          })?;
+          check_type(column, "a Symbol", is_symbol(column.r#type))?;
+        }
+        Some(right.schema.columns.iter().position(|c| &c.name == by).unwrap())
+      }
+      None => None
+    };
+    let left_columns = asof.get_scan_columns(self, &asof.left_columns);
+    let right_columns = asof.get_scan_columns(right, &asof.right_columns);
+    let first_column = left_columns.len() - asof.left_columns.len();
+    let left = self.partition_iter(from_ts, to_ts, left_columns)?;
+    // The prevailing right row may be long before `from_ts`
+    let right_from_ts = match asof.tolerance {
+      Some(tolerance) => from_ts.saturating_sub(tolerance),
+      None => i64::MIN
+    };
+    let mut right_items = right.partition_iter(right_from_ts, to_ts, right_columns)?;
+    let get_column = |table: &Table, name: &str| {
+      table.schema.columns.iter().find(|c| c.name == name).unwrap().clone()
+    };
+    let mut res = asof
+      .left_columns
+      .iter()
+      .map(|name| OwnedColumn::new(&get_column(self, name), false))
+      .chain(
+        asof
+          .right_columns
+          .iter()
+          .map(|name| OwnedColumn::new(&get_column(right, name), true))
+      )
+      .collect::<Vec<_>>();
+    let (left_res, right_res) = res.split_at_mut(asof.left_columns.len());
+
+    // Right partitions are kept while they hold a prevailing row
+    let mut items: Vec<Option<Vec<PartitionColumn>>> = Vec::new();
+    let mut cursor = (0, 0);
+    // Last (item, row) for each right symbol id or for 0 if there's no `by`
+    let mut prevailing: Vec<Option<(usize, usize)>> = Vec::new();
+    // Left symbol id to right symbol id
+    let mut left_to_right: Vec<Option<Option<usize>>> = Vec::new();
+    for partition in left {
+      let partition = partition?;
+      for row_index in 0..partition[0].row_count {
+        let ts = partition[0].get_timestamp(row_index);
+        // Advance right rows up to `ts`
+        loop {
+          if items.len() == cursor.0 {
+            match right_items.next() {
+              Some(item) => items.push(Some(item?)),
+              None => break
+            }
+          }
+          let item = items[cursor.0].as_ref().unwrap();
+          if cursor.1 == item[0].row_count {
+            cursor = (cursor.0 + 1, 0);
+            continue;
+          }
+          if item[0].get_timestamp(cursor.1) > ts {
+            break;
+          }
+          let key = if by_index.is_some() { item[1].get_symbol_id(cursor.1) } else { 0 };
+          if prevailing.len() <= key {
+            prevailing.resize(key + 1, None);
+          }
+          prevailing[key] = Some(cursor);
+          cursor.1 += 1;
+        }
+
+        let key = match by_index {
+          Some(by_index) => {
+            let left_id = partition[1].get_symbol_id(row_index);
+            if left_to_right.len() <= left_id {
+              left_to_right.resize(left_id + 1, None);
+            }
+            *left_to_right[left_id].get_or_insert_with(|| {
+              let symbol = partition[1].get_symbol(row_index);
+              right.column_symbols[by_index].symbol_nums.get(symbol).copied()
+            })
+          }
+          None => Some(0)
+        };
+        let matched = key
+          .and_then(|key| prevailing.get(key).copied().flatten())
+          .map(|(item_index, right_index)| (items[item_index].as_ref().unwrap(), right_index))
+          .filter(|(item, right_index)| match asof.tolerance {
+            Some(tolerance) => ts - item[0].get_timestamp(*right_index) <= tolerance,
+            None => true
+          });
+
+        for (res, column) in left_res.iter_mut().zip(&partition[first_column..]) {
+          res.push(column, row_index);
+        }
+        match matched {
+          Some((item, right_index)) => {
+            for (res, column) in right_res.iter_mut().zip(&item[first_column..]) {
+              res.push(column, right_index);
+            }
+          }
+          None => right_res.iter_mut().for_each(|res| res.push_null())
+        }
+      }
+
+      // Drop right partitions that no longer hold a prevailing row
+      for (item_index, item) in items.iter_mut().enumerate().take(cursor.0) {
+        let is_prevailing = prevailing
+          .iter()
+          .any(|p| p.is_some_and(|(i, _right_index)| i == item_index));
+        if !is_prevailing {
+          *item = None;
+        }
+      }
+    }
+
+    Ok(res)
+  }
+}
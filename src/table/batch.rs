@@ -0,0 +1,453 @@
+use crate::{
+  error::Error,
+  schema::{Column, ColumnType},
+  table::{heap::get_heap_len, scan::PartitionColumn, Table}
+};
+use std::{cmp::Ordering, mem::size_of, slice::from_raw_parts};
+
+// One slice per schema column, all the same length
+#[derive(Debug, Clone, Copy)]
+pub enum ColumnData<'a> {
+  Timestamp(&'a [i64]),
+  Symbol(&'a [String]),
+  I8(&'a [i8]),
+  U8(&'a [u8]),
+  I16(&'a [i16]),
+  U16(&'a [u16]),
+  I32(&'a [i32]),
+  U32(&'a [u32]),
+  F32(&'a [f32]),
+  I64(&'a [i64]),
+  U64(&'a [u64]),
+  F64(&'a [f64]),
+  String(&'a [String]),
+  Bytes(&'a [Vec<u8>])
+}
+
+// Owned version of ColumnData
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnBuffer {
+  Timestamp(Vec<i64>),
+  Symbol(Vec<String>),
+  I8(Vec<i8>),
+  U8(Vec<u8>),
+  I16(Vec<i16>),
+  U16(Vec<u16>),
+  I32(Vec<i32>),
+  U32(Vec<u32>),
+  F32(Vec<f32>),
+  I64(Vec<i64>),
+  U64(Vec<u64>),
+  F64(Vec<f64>),
+  String(Vec<String>),
+  Bytes(Vec<Vec<u8>>)
+}
+
+impl ColumnBuffer {
+  pub fn as_column_data(&self) -> ColumnData<'_> {
+    match self {
+      ColumnBuffer::Timestamp(v) => ColumnData::Timestamp(v),
+      ColumnBuffer::Symbol(v) => ColumnData::Symbol(v),
+      ColumnBuffer::I8(v) => ColumnData::I8(v),
+      ColumnBuffer::U8(v) => ColumnData::U8(v),
+      ColumnBuffer::I16(v) => ColumnData::I16(v),
+      ColumnBuffer::U16(v) => ColumnData::U16(v),
+      ColumnBuffer::I32(v) => ColumnData::I32(v),
+      ColumnBuffer::U32(v) => ColumnData::U32(v),
+      ColumnBuffer::F32(v) => ColumnData::F32(v),
+      ColumnBuffer::I64(v) => ColumnData::I64(v),
+      ColumnBuffer::U64(v) => ColumnData::U64(v),
+      ColumnBuffer::F64(v) => ColumnData::F64(v),
+      ColumnBuffer::String(v) => ColumnData::String(v),
+      ColumnBuffer::Bytes(v) => ColumnData::Bytes(v)
+    }
+  }
+}
+
+// A column of query results. Symbols are resolved to strings and decimals stay scaled integers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedColumn {
+  pub name:   String,
+  pub r#type: ColumnType,
+  pub data:   ColumnBuffer,
+  // True for null rows
+  pub nulls:  Option<Vec<bool>>
+}
+
+impl OwnedColumn {
+  pub(crate) fn new(column: &Column, nullable: bool) -> Self {
+    let data = match column.r#type {
+      ColumnType::Timestamp => ColumnBuffer::Timestamp(Vec::new()),
+      ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32 => {
+        ColumnBuffer::Symbol(Vec::new())
+      }
+      ColumnType::I8 => ColumnBuffer::I8(Vec::new()),
+      ColumnType::U8 => ColumnBuffer::U8(Vec::new()),
+      ColumnType::I16 => ColumnBuffer::I16(Vec::new()),
+      ColumnType::U16 => ColumnBuffer::U16(Vec::new()),
+      ColumnType::I32 | ColumnType::Decimal32 { .. } => ColumnBuffer::I32(Vec::new()),
+      ColumnType::U32 => ColumnBuffer::U32(Vec::new()),
+      ColumnType::F32 => ColumnBuffer::F32(Vec::new()),
+      ColumnType::I64 | ColumnType::Decimal64 { .. } => ColumnBuffer::I64(Vec::new()),
+      ColumnType::U64 => ColumnBuffer::U64(Vec::new()),
+      ColumnType::F64 => ColumnBuffer::F64(Vec::new()),
+      ColumnType::String => ColumnBuffer::String(Vec::new()),
+      ColumnType::Bytes => ColumnBuffer::Bytes(Vec::new())
+    };
+    Self {
+      name: column.name.clone(),
+      r#type: column.r#type,
+      data,
+      nulls: if nullable || column.nullable { Some(Vec::new()) } else { None }
+    }
+  }
+
+  pub(crate) fn push(&mut self, column: &PartitionColumn, row_index: usize) {
+    if let Some(nulls) = &mut self.nulls {
+      nulls.push(column.is_null(row_index));
+    }
+    match &mut self.data {
+      ColumnBuffer::Timestamp(v) => v.push(column.get_timestamp(row_index)),
+      ColumnBuffer::Symbol(v) => v.push(column.get_symbol(row_index).to_owned()),
+      ColumnBuffer::I8(v) => v.push(column.get_i8()[row_index]),
+      ColumnBuffer::U8(v) => v.push(column.get_u8()[row_index]),
+      ColumnBuffer::I16(v) => v.push(column.get_i16()[row_index]),
+      ColumnBuffer::U16(v) => v.push(column.get_u16()[row_index]),
+      ColumnBuffer::I32(v) => v.push(column.get_i32()[row_index]),
+      ColumnBuffer::U32(v) => v.push(column.get_u32()[row_index]),
+      ColumnBuffer::F32(v) => v.push(column.get_f32()[row_index]),
+      ColumnBuffer::I64(v) => v.push(column.get_i64()[row_index]),
+      ColumnBuffer::U64(v) => v.push(column.get_u64()[row_index]),
+      ColumnBuffer::F64(v) => v.push(column.get_f64()[row_index]),
+      ColumnBuffer::String(v) => v.push(column.get_string(row_index).to_owned()),
+      ColumnBuffer::Bytes(v) => v.push(column.get_binary(row_index).to_vec())
+    }
+  }
+
+  // Zero or empty
+  pub(super) fn push_null(&mut self) {
+    if let Some(nulls) = &mut self.nulls {
+      nulls.push(true);
+    }
+    match &mut self.data {
+      ColumnBuffer::Timestamp(v) => v.push(0),
+      ColumnBuffer::Symbol(v) => v.push(String::new()),
+      ColumnBuffer::I8(v) => v.push(0),
+      ColumnBuffer::U8(v) => v.push(0),
+      ColumnBuffer::I16(v) => v.push(0),
+      ColumnBuffer::U16(v) => v.push(0),
+      ColumnBuffer::I32(v) => v.push(0),
+      ColumnBuffer::U32(v) => v.push(0),
+      ColumnBuffer::F32(v) => v.push(0.0),
+      ColumnBuffer::I64(v) => v.push(0),
+      ColumnBuffer::U64(v) => v.push(0),
+      ColumnBuffer::F64(v) => v.push(0.0),
+      ColumnBuffer::String(v) => v.push(String::new()),
+      ColumnBuffer::Bytes(v) => v.push(Vec::new())
+    }
+  }
+
+  pub fn len(&self) -> usize { self.data.as_column_data().len() }
+
+  pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+  // Nulls come first
+  pub fn compare_rows(&self, a: usize, b: usize) -> Ordering {
+    if let Some(nulls) = &self.nulls {
+      match (nulls[a], nulls[b]) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Less,
+        (false, true) => return Ordering::Greater,
+        (false, false) => {}
+      }
+    }
+    match &self.data {
+      ColumnBuffer::Timestamp(v) | ColumnBuffer::I64(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::Symbol(v) | ColumnBuffer::String(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::I8(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::U8(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::I16(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::U16(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::I32(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::U32(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::F32(v) => v[a].partial_cmp(&v[b]).unwrap_or(Ordering::Equal),
+      ColumnBuffer::U64(v) => v[a].cmp(&v[b]),
+      ColumnBuffer::F64(v) => v[a].partial_cmp(&v[b]).unwrap_or(Ordering::Equal),
+      ColumnBuffer::Bytes(v) => v[a].cmp(&v[b])
+    }
+  }
+
+  // The values at `rows` in that order
+  pub fn take(&self, rows: &[usize]) -> Self {
+    macro_rules! take {
+      ($variant: ident, $v: expr) => {
+        ColumnBuffer::$variant(rows.iter().map(|i| $v[*i].clone()).collect())
+      };
+    }
+    let data = match &self.data {
+      ColumnBuffer::Timestamp(v) => take!(Timestamp, v),
+      ColumnBuffer::Symbol(v) => take!(Symbol, v),
+      ColumnBuffer::I8(v) => take!(I8, v),
+      ColumnBuffer::U8(v) => take!(U8, v),
+      ColumnBuffer::I16(v) => take!(I16, v),
+      ColumnBuffer::U16(v) => take!(U16, v),
+      ColumnBuffer::I32(v) => take!(I32, v),
+      ColumnBuffer::U32(v) => take!(U32, v),
+      ColumnBuffer::F32(v) => take!(F32, v),
+      ColumnBuffer::I64(v) => take!(I64, v),
+      ColumnBuffer::U64(v) => take!(U64, v),
+      ColumnBuffer::F64(v) => take!(F64, v),
+      ColumnBuffer::String(v) => take!(String, v),
+      ColumnBuffer::Bytes(v) => take!(Bytes, v)
+    };
+    Self {
+      name: self.name.clone(),
+      r#type: self.r#type,
+      data,
+      nulls: self.nulls.as_ref().map(|nulls| rows.iter().map(|i| nulls[*i]).collect())
+    }
+  }
+}
+
+fn as_bytes<T>(slice: &[T]) -> &[u8] {
+  unsafe { from_raw_parts(slice.as_ptr() as *const u8, slice.len() * size_of::<T>()) }
+}
+
+impl<'a> ColumnData<'a> {
+  pub fn len(&self) -> usize {
+    match self {
+      ColumnData::Timestamp(s) => s.len(),
+      ColumnData::Symbol(s) => s.len(),
+      ColumnData::I8(s) => s.len(),
+      ColumnData::U8(s) => s.len(),
+      ColumnData::I16(s) => s.len(),
+      ColumnData::U16(s) => s.len(),
+      ColumnData::I32(s) => s.len(),
+      ColumnData::U32(s) => s.len(),
+      ColumnData::F32(s) => s.len(),
+      ColumnData::I64(s) => s.len(),
+      ColumnData::U64(s) => s.len(),
+      ColumnData::F64(s) => s.len(),
+      ColumnData::String(s) => s.len(),
+      ColumnData::Bytes(s) => s.len()
+    }
+  }
+
+  pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      ColumnData::Timestamp(_) => "Timestamp",
+      ColumnData::Symbol(_) => "Symbol",
+      ColumnData::I8(_) => "I8",
+      ColumnData::U8(_) => "U8",
+      ColumnData::I16(_) => "I16",
+      ColumnData::U16(_) => "U16",
+      ColumnData::I32(_) => "I32",
+      ColumnData::U32(_) => "U32",
+      ColumnData::F32(_) => "F32",
+      ColumnData::I64(_) => "I64",
+      ColumnData::U64(_) => "U64",
+      ColumnData::F64(_) => "F64",
+      ColumnData::String(_) => "String",
+      ColumnData::Bytes(_) => "Bytes"
+    }
+  }
+
+  fn is_type(&self, r#type: ColumnType) -> bool {
+    match (self, r#type) {
+      (ColumnData::Timestamp(_), ColumnType::Timestamp) => true,
+      (ColumnData::Symbol(_), ColumnType::Symbol8)
+      | (ColumnData::Symbol(_), ColumnType::Symbol16)
+      | (ColumnData::Symbol(_), ColumnType::Symbol32) => true,
+      (ColumnData::I8(_), ColumnType::I8) => true,
+      (ColumnData::U8(_), ColumnType::U8) => true,
+      (ColumnData::I16(_), ColumnType::I16) => true,
+      (ColumnData::U16(_), ColumnType::U16) => true,
+      (ColumnData::I32(_), ColumnType::I32) => true,
+      (ColumnData::U32(_), ColumnType::U32) => true,
+      (ColumnData::F32(_), ColumnType::F32) => true,
+      (ColumnData::I64(_), ColumnType::I64) => true,
+      (ColumnData::U64(_), ColumnType::U64) => true,
+      (ColumnData::F64(_), ColumnType::F64) => true,
+      (ColumnData::String(_), ColumnType::String) => true,
+      // Scaled values
+      (ColumnData::I32(_), ColumnType::Decimal32 { .. }) => true,
+      (ColumnData::I64(_), ColumnType::Decimal64 { .. }) => true,
+      (ColumnData::Bytes(_), ColumnType::Bytes) => true,
+      _ => false
+    }
+  }
+
+  // Raw little-endian bytes of rows `from..to`. Only valid for fixed width columns.
+  fn get_bytes(&self, from: usize, to: usize) -> &'a [u8] {
+    match *self {
+      ColumnData::Timestamp(s) => as_bytes(&s[from..to]),
+      ColumnData::I8(s) => as_bytes(&s[from..to]),
+      ColumnData::U8(s) => as_bytes(&s[from..to]),
+      ColumnData::I16(s) => as_bytes(&s[from..to]),
+      ColumnData::U16(s) => as_bytes(&s[from..to]),
+      ColumnData::I32(s) => as_bytes(&s[from..to]),
+      ColumnData::U32(s) => as_bytes(&s[from..to]),
+      ColumnData::F32(s) => as_bytes(&s[from..to]),
+      ColumnData::I64(s) => as_bytes(&s[from..to]),
+      ColumnData::U64(s) => as_bytes(&s[from..to]),
+      ColumnData::F64(s) => as_bytes(&s[from..to]),
+      ColumnData::Symbol(_) | ColumnData::String(_) | ColumnData::Bytes(_) => {
+        panic!("{} columns are not fixed width", self.type_name())
+      }
+    }
+  }
+}
+
+fn check_batch(table_name: &str, schema: &[Column], batch: &[ColumnData]) -> Result<usize, Error> {
+  if batch.len() != schema.len() {
+    return Err(Error::ColumnNotFound {
+      table:  table_name.to_owned(),
+      column: format!("#{}", batch.len().min(schema.len()))
+    });
+  }
+  let row_count = batch.first().map(|c| c.len()).unwrap_or(0);
+  for (column, data) in schema.iter().zip(batch.iter()) {
+    if !data.is_type(column.r#type) {
+      return Err(Error::TypeMismatch {
+        column:   column.name.clone(),
+        expected: data.type_name().to_owned(),
+        actual:   column.r#type
+      });
+    }
+    if data.len() != row_count {
+      return Err(Error::Query(format!(
+        "column {} has {} rows but expected {}",
+        column.name,
+        data.len(),
+        row_count
+      )));
+    }
+  }
+
+  Ok(row_count)
+}
+
+impl Table {
+  // Appends whole columns at once. Splits rows across partitions and copies fixed-width columns
+  // straight into the partition's files.
+  pub fn append_batch(&mut self, batch: &[ColumnData]) -> Result<(), Error> {
+    let row_count = check_batch(&self.schema.name, &self.schema.columns, batch)?;
+    if self.column_index != 0 {
+      return Err(Error::Query(String::from(
+        "cannot append a batch in the middle of a row"
+      )));
+    }
+    let timestamps = match batch.first() {
+      Some(ColumnData::Timestamp(timestamps)) => *timestamps,
+      _ => {
+        return Err(Error::Query(String::from(
+          "first column must be a timestamp"
+        )))
+      }
+    };
+    if row_count == 0 {
+      return Ok(());
+    }
+    self.lock()?;
+    let resolution = self.schema.columns[0].resolution;
+    // For the WAL
+    let mut symbol_indices = vec![Vec::<u32>::new(); batch.len()];
+
+    let mut from = 0;
+    while from < row_count {
+      self.seek_partition(timestamps[from] / resolution * resolution)?;
+      let mut to = from;
+      while to < row_count {
+        let val = timestamps[to] / resolution * resolution;
+        if val < self.cur_partition_meta.min_ts || val > self.cur_partition_meta.max_ts {
+          break;
+        }
+        to += 1;
+      }
+      let start_row = self.cur_partition_meta.row_count;
+      self.reserve(start_row + to - from + 1)?;
+
+      for (column_index, data) in batch.iter().enumerate() {
+        let size = self.schema.columns[column_index].size;
+        match data {
+          ColumnData::Timestamp(values) => {
+            let col_resolution = self.schema.columns[column_index].resolution;
+            for (i, val) in values[from..to].iter().enumerate() {
+              let val = val / col_resolution * col_resolution;
+              if column_index == 0 {
+                self.track_ts(val, start_row + i);
+              }
+              let rel_val = self.encode_ts(column_index, val);
+              let offset = (start_row + i) * size;
+              self.columns[column_index].data[offset..offset + size]
+                .copy_from_slice(&rel_val.to_le_bytes()[..size]);
+            }
+          }
+          ColumnData::Symbol(values) => {
+            for (i, val) in values[from..to].iter().enumerate() {
+              let index = self.get_symbol_index(column_index, val)?;
+              symbol_indices[column_index].push(index as u32);
+              let offset = (start_row + i) * size;
+              self.columns[column_index].data[offset..offset + size]
+                .copy_from_slice(&(index as u32).to_le_bytes()[..size]);
+            }
+          }
+          ColumnData::String(_) | ColumnData::Bytes(_) => {
+            for i in 0..to - from {
+              let value = match data {
+                ColumnData::String(values) => values[from + i].as_bytes(),
+                ColumnData::Bytes(values) => &values[from + i][..],
+                _ => unreachable!()
+              };
+              let column = &mut self.columns[column_index];
+              let start = get_heap_len(&column.data, start_row + i);
+              column.heap.as_mut().unwrap().put(start, value)?;
+              let offset = (start_row + i) * size;
+              column.data[offset..offset + size]
+                .copy_from_slice(&((start + value.len()) as u64).to_le_bytes());
+            }
+          }
+          _ => {
+            let offset = start_row * size;
+            self.columns[column_index].data[offset..offset + (to - from) * size]
+              .copy_from_slice(data.get_bytes(from, to));
+          }
+        }
+      }
+      for column in &mut self.columns {
+        if let Some(nulls) = &mut column.nulls {
+          for row_index in start_row..start_row + to - from {
+            nulls.set(row_index, false);
+          }
+        }
+      }
+      self.cur_partition_meta.row_count += to - from;
+      from = to;
+    }
+
+    let mut payload = (row_count as u32).to_le_bytes().to_vec();
+    for (column_index, data) in batch.iter().enumerate() {
+      match data {
+        ColumnData::Symbol(_) => {
+          payload.extend_from_slice(as_bytes(&symbol_indices[column_index]))
+        }
+        ColumnData::String(values) => {
+          for value in values.iter() {
+            payload.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            payload.extend_from_slice(value.as_bytes());
+          }
+        }
+        ColumnData::Bytes(values) => {
+          for value in values.iter() {
+            payload.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            payload.extend_from_slice(value);
+          }
+        }
+        _ => payload.extend_from_slice(data.get_bytes(0, row_count))
+      }
+    }
+    self.wal_write_batch(&payload)
+  }
+}
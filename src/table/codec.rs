@@ -0,0 +1,441 @@
+use crate::{
+  error::{Error, IoContext},
+  schema::{Codec, Column, ColumnType},
+  table::read::is_variable_length
+};
+use memmap::MmapMut;
+use std::{
+  cmp::{max, min},
+  convert::TryInto,
+  fs::{File, OpenOptions},
+  io::Write,
+  ops::Range,
+  path::PathBuf
+};
+
+// Sealed columns are split into blocks of 64KB of raw values which are encoded separately so any
+// row can be read by decoding a single block. The file is the encoded blocks followed by each
+// block's u64 end offset and then the u64 block count.
+pub const BLOCK_SIZE: usize = 64 * 1024;
+
+pub fn get_blocks_path(col_path: &PathBuf) -> PathBuf {
+  let mut path = col_path.clone().into_os_string();
+  path.push(".blocks");
+  PathBuf::from(path)
+}
+
+pub fn get_block_rows(column: &Column) -> usize { BLOCK_SIZE / column.size }
+
+fn is_signed(column: &Column) -> bool {
+  match column.r#type {
+    ColumnType::I8
+    | ColumnType::I16
+    | ColumnType::I32
+    | ColumnType::I64
+    | ColumnType::Decimal32 { .. }
+    | ColumnType::Decimal64 { .. } => true,
+    // Smaller timestamps are unsigned offsets from the partition's start
+    ColumnType::Timestamp => column.size == 8,
+    _ => false
+  }
+}
+
+pub fn check_codec(column: &Column) -> Result<(), Error> {
+  let is_float = column.r#type == ColumnType::F32 || column.r#type == ColumnType::F64;
+  let expected = match column.codec {
+    Codec::None => return Ok(()),
+    Codec::Gorilla if is_float => return Ok(()),
+    Codec::Gorilla => "F32 or F64",
+    _ if !is_float && !is_variable_length(column.r#type) => return Ok(()),
+    _ => "an integer, Symbol or Timestamp"
+  };
+  Err(Error::TypeMismatch {
+    column:   column.name.clone(),
+    expected: format!("{} to use {:?}", expected, column.codec),
+    actual:   column.r#type
+  })
+}
+
+// Values are widened to u64. Signed values are sign extended so differences stay small.
+fn read_value(bytes: &[u8], signed: bool) -> u64 {
+  let mut buf = [0_u8; 8];
+  buf[..bytes.len()].copy_from_slice(bytes);
+  let val = u64::from_le_bytes(buf);
+  let shift = 64 - 8 * bytes.len() as u32;
+  if signed && shift != 0 {
+    ((val << shift) as i64 >> shift) as u64
+  } else {
+    val
+  }
+}
+
+fn write_value(val: u64, out: &mut [u8]) {
+  let len = out.len();
+  out.copy_from_slice(&val.to_le_bytes()[..len]);
+}
+
+fn write_varint(mut val: u64, out: &mut Vec<u8>) {
+  while val >= 0x80 {
+    out.push(val as u8 | 0x80);
+    val >>= 7;
+  }
+  out.push(val as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+  let mut res = 0_u64;
+  let mut shift = 0;
+  loop {
+    let byte = data[*pos];
+    *pos += 1;
+    res |= ((byte & 0x7f) as u64) << shift;
+    if byte < 0x80 {
+      return res;
+    }
+    shift += 7;
+  }
+}
+
+struct BitWriter {
+  out:  Vec<u8>,
+  byte: u8,
+  used: u32
+}
+
+impl BitWriter {
+  fn new(out: Vec<u8>) -> Self { Self { out, byte: 0, used: 0 } }
+
+  // Writes the low `bits` bits of `val`, most significant first
+  fn write(&mut self, val: u64, mut bits: u32) {
+    while bits > 0 {
+      let take = min(8 - self.used, bits);
+      let chunk = (val >> (bits - take)) as u8 & ((1_u16 << take) - 1) as u8;
+      self.byte = ((self.byte as u16) << take) as u8 | chunk;
+      self.used += take;
+      bits -= take;
+      if self.used == 8 {
+        self.out.push(self.byte);
+        self.byte = 0;
+        self.used = 0;
+      }
+    }
+  }
+
+  fn finish(mut self) -> Vec<u8> {
+    if self.used > 0 {
+      self.out.push(self.byte << (8 - self.used));
+    }
+    self.out
+  }
+}
+
+struct BitReader<'a> {
+  data: &'a [u8],
+  pos:  usize,
+  used: u32
+}
+
+impl<'a> BitReader<'a> {
+  fn new(data: &'a [u8]) -> Self { Self { data, pos: 0, used: 0 } }
+
+  fn read(&mut self, mut bits: u32) -> u64 {
+    let mut res = 0_u64;
+    while bits > 0 {
+      let take = min(8 - self.used, bits);
+      let byte = self.data[self.pos] as u64;
+      let chunk = (byte >> (8 - self.used - take)) & ((1 << take) - 1);
+      res = (res << take) | chunk;
+      self.used += take;
+      bits -= take;
+      if self.used == 8 {
+        self.pos += 1;
+        self.used = 0;
+      }
+    }
+    res
+  }
+}
+
+// Stores the change in each row's delta so evenly spaced values like timestamps take a byte
+fn encode_delta(values: impl Iterator<Item = u64>, out: &mut Vec<u8>) {
+  let mut prev = 0_u64;
+  let mut prev_delta = 0_u64;
+  for val in values {
+    let delta = val.wrapping_sub(prev);
+    let delta_of_delta = delta.wrapping_sub(prev_delta) as i64;
+    write_varint(((delta_of_delta << 1) ^ (delta_of_delta >> 63)) as u64, out);
+    prev = val;
+    prev_delta = delta;
+  }
+}
+
+fn decode_delta(data: &[u8], row_count: usize, mut put: impl FnMut(usize, u64)) {
+  let mut pos = 0;
+  let mut prev = 0_u64;
+  let mut prev_delta = 0_u64;
+  for row_index in 0..row_count {
+    let zigzag = read_varint(data, &mut pos);
+    let delta_of_delta = (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+    prev_delta = prev_delta.wrapping_add(delta_of_delta as u64);
+    prev = prev.wrapping_add(prev_delta);
+    put(row_index, prev);
+  }
+}
+
+// Flipping the sign bit keeps signed values ordered as u64s
+fn encode_frame_of_reference(values: &[u64], signed: bool, out: &mut Vec<u8>) {
+  let flip = if signed { 1 << 63 } else { 0 };
+  let min_val = values.iter().map(|v| v ^ flip).min().unwrap_or(0);
+  let max_val = values.iter().map(|v| v ^ flip).max().unwrap_or(0);
+  let bits = 64 - (max_val - min_val).leading_zeros();
+  out.extend_from_slice(&min_val.to_le_bytes());
+  out.push(bits as u8);
+  let mut writer = BitWriter::new(std::mem::take(out));
+  for val in values {
+    writer.write((val ^ flip) - min_val, bits);
+  }
+  *out = writer.finish();
+}
+
+fn decode_frame_of_reference(
+  data: &[u8],
+  row_count: usize,
+  signed: bool,
+  mut put: impl FnMut(usize, u64)
+) {
+  let flip = if signed { 1 << 63 } else { 0 };
+  let min_val = u64::from_le_bytes(data[..8].try_into().unwrap());
+  let bits = data[8] as u32;
+  let mut reader = BitReader::new(&data[9..]);
+  for row_index in 0..row_count {
+    put(row_index, (reader.read(bits) + min_val) ^ flip);
+  }
+}
+
+// Facebook's Gorilla float compression. Each value is XORed with the previous one. Identical
+// values take one bit and otherwise only the bits between the XOR's leading and trailing zeros
+// are stored, reusing the previous window when they fit in it.
+fn encode_gorilla(values: &[u64], value_bits: u32, out: &mut Vec<u8>) {
+  let mut writer = BitWriter::new(std::mem::take(out));
+  let mut prev = 0_u64;
+  let mut window: Option<(u32, u32)> = None;
+  for (i, val) in values.iter().enumerate() {
+    if i == 0 {
+      writer.write(*val, value_bits);
+      prev = *val;
+      continue;
+    }
+    let xor = val ^ prev;
+    prev = *val;
+    if xor == 0 {
+      writer.write(0, 1);
+      continue;
+    }
+    writer.write(1, 1);
+    let leading = xor.leading_zeros() - (64 - value_bits);
+    let trailing = xor.trailing_zeros();
+    match window {
+      Some((prev_leading, prev_trailing))
+        if leading >= prev_leading && trailing >= prev_trailing =>
+      {
+        writer.write(0, 1);
+        writer.write(xor >> prev_trailing, value_bits - prev_leading - prev_trailing);
+      }
+      _ => {
+        let len = value_bits - leading - trailing;
+        writer.write(1, 1);
+        writer.write(leading as u64, 6);
+        writer.write(len as u64 - 1, 6);
+        writer.write(xor >> trailing, len);
+        window = Some((leading, trailing));
+      }
+    }
+  }
+  *out = writer.finish();
+}
+
+fn decode_gorilla(data: &[u8], row_count: usize, value_bits: u32, mut put: impl FnMut(usize, u64)) {
+  let mut reader = BitReader::new(data);
+  let mut prev = 0_u64;
+  let mut window = (0, 0);
+  for row_index in 0..row_count {
+    if row_index == 0 {
+      prev = reader.read(value_bits);
+    } else if reader.read(1) == 1 {
+      if reader.read(1) == 1 {
+        let leading = reader.read(6) as u32;
+        let len = reader.read(6) as u32 + 1;
+        window = (leading, value_bits - leading - len);
+      }
+      let (leading, trailing) = window;
+      prev ^= reader.read(value_bits - leading - trailing) << trailing;
+    }
+    put(row_index, prev);
+  }
+}
+
+pub fn encode_block(column: &Column, raw: &[u8], out: &mut Vec<u8>) {
+  let signed = is_signed(column);
+  let values = raw
+    .chunks_exact(column.size)
+    .map(|bytes| read_value(bytes, signed));
+  match column.codec {
+    Codec::None => out.extend_from_slice(raw),
+    Codec::Delta => encode_delta(values, out),
+    Codec::FrameOfReference => encode_frame_of_reference(&values.collect::<Vec<_>>(), signed, out),
+    Codec::Gorilla => encode_gorilla(&values.collect::<Vec<_>>(), 8 * column.size as u32, out)
+  }
+}
+
+// Decodes into `out` which must be the block's raw length
+pub fn decode_block(column: &Column, data: &[u8], out: &mut [u8]) {
+  let size = column.size;
+  let row_count = out.len() / size;
+  let put = |row_index: usize, val: u64| {
+    write_value(val, &mut out[row_index * size..(row_index + 1) * size])
+  };
+  match column.codec {
+    Codec::None => out.copy_from_slice(&data[..out.len()]),
+    Codec::Delta => decode_delta(data, row_count, put),
+    Codec::FrameOfReference => decode_frame_of_reference(data, row_count, is_signed(column), put),
+    Codec::Gorilla => decode_gorilla(data, row_count, 8 * size as u32, put)
+  }
+}
+
+// Writes the first `row_count` rows of `raw` to the column's .blocks file
+pub fn compress_column(
+  col_path: &PathBuf,
+  column: &Column,
+  raw: &[u8],
+  row_count: usize
+) -> Result<(), Error> {
+  let block_len = get_block_rows(column) * column.size;
+  let mut out = Vec::new();
+  let mut ends = Vec::new();
+  for block in raw[..row_count * column.size].chunks(block_len) {
+    encode_block(column, block, &mut out);
+    ends.push(out.len() as u64);
+  }
+  for end in &ends {
+    out.extend_from_slice(&end.to_le_bytes());
+  }
+  out.extend_from_slice(&(ends.len() as u64).to_le_bytes());
+
+  let path = get_blocks_path(col_path);
+  let mut file = OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .open(&path)
+    .with_path(&path)?;
+  file
+    .write_all(&out)
+    .and_then(|_| file.sync_all())
+    .with_path(&path)
+}
+
+// Decodes the blocks covering `rows` into an anonymous map of all `row_count` rows. Pages of
+// blocks that aren't decoded are never touched so they take no memory.
+pub fn decompress_column(
+  col_path: &PathBuf,
+  column: &Column,
+  row_count: usize,
+  rows: Range<usize>
+) -> Result<(File, MmapMut), Error> {
+  let path = get_blocks_path(col_path);
+  let file = OpenOptions::new().read(true).open(&path).with_path(&path)?;
+  let blocks = unsafe { memmap::Mmap::map(&file).with_path(&path)? };
+  let corrupt = |reason: String| Error::CorruptMeta {
+    path: path.clone(),
+    reason
+  };
+  if blocks.len() < 8 {
+    return Err(corrupt(String::from("missing block count")));
+  }
+  let get_u64 = |offset: usize| u64::from_le_bytes(blocks[offset..offset + 8].try_into().unwrap());
+  let block_count = get_u64(blocks.len() - 8) as usize;
+  let block_rows = get_block_rows(column);
+  if block_count != row_count.div_ceil(block_rows) || blocks.len() < 8 + 8 * block_count {
+    return Err(corrupt(format!(
+      "expected {} rows but found {} blocks",
+      row_count, block_count
+    )));
+  }
+  let index = blocks.len() - 8 - 8 * block_count;
+
+  let len = row_count * column.size;
+  let mut data = MmapMut::map_anon(max(len, 1)).with_path(&path)?;
+  if rows.start < rows.end {
+    for block_index in rows.start / block_rows..=(rows.end - 1) / block_rows {
+      let start = match block_index {
+        0 => 0,
+        i => get_u64(index + 8 * (i - 1)) as usize
+      };
+      let end = get_u64(index + 8 * block_index) as usize;
+      let from = block_index * block_rows * column.size;
+      let to = min(from + block_rows * column.size, len);
+      decode_block(column, &blocks[start..end], &mut data[from..to]);
+    }
+  }
+
+  Ok((file, data))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn round_trip(column: &Column, raw: &[u8]) -> usize {
+    let mut encoded = Vec::new();
+    encode_block(column, raw, &mut encoded);
+    let mut decoded = vec![0_u8; raw.len()];
+    decode_block(column, &encoded, &mut decoded);
+    assert_eq!(decoded, raw, "{:?}", column.codec);
+    encoded.len()
+  }
+
+  #[test]
+  fn codecs() {
+    let ts = (0..1000_i64).map(|i| 1_600_000_000_000_000_000 + i * 60_000_000_000);
+    let ts = ts.flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<_>>();
+    let column = Column::new("ts", ColumnType::Timestamp).with_codec(Codec::Delta);
+    assert!(round_trip(&column, &ts) < ts.len() / 4);
+
+    let prices = (0..1000).map(|i| 100.0 + (i / 10) as f64 * 0.25);
+    let prices = prices.flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<_>>();
+    let column = Column::new("price", ColumnType::F64).with_codec(Codec::Gorilla);
+    assert!(round_trip(&column, &prices) < prices.len() / 4);
+    let noise = (0..1000_u32).map(|i| (i.wrapping_mul(2654435761) as f32).sqrt() - 50.0);
+    let noise = noise.flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<_>>();
+    let column = Column::new("noise", ColumnType::F32).with_codec(Codec::Gorilla);
+    round_trip(&column, &noise);
+
+    let volumes = (0..1000_i32).map(|i| -500 + i % 37);
+    let volumes = volumes.flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<_>>();
+    let column = Column::new("volume", ColumnType::I32).with_codec(Codec::FrameOfReference);
+    assert!(round_trip(&column, &volumes) < volumes.len() / 4);
+    let column = Column::new("volume", ColumnType::I32).with_codec(Codec::Delta);
+    round_trip(&column, &volumes);
+
+    let extremes = [u64::MAX, 0, 1, u64::MAX - 1];
+    let extremes = extremes.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<_>>();
+    for codec in &[Codec::Delta, Codec::FrameOfReference] {
+      round_trip(&Column::new("u", ColumnType::U64).with_codec(*codec), &extremes);
+      round_trip(&Column::new("i", ColumnType::I64).with_codec(*codec), &extremes);
+    }
+    round_trip(&Column::new("f", ColumnType::F64).with_codec(Codec::Gorilla), &extremes);
+    round_trip(&Column::new("e", ColumnType::U8).with_codec(Codec::Delta), &[]);
+  }
+
+  #[test]
+  fn check_codecs() {
+    let column = Column::new("price", ColumnType::F32).with_codec(Codec::Delta);
+    assert!(matches!(check_codec(&column), Err(Error::TypeMismatch { .. })));
+    let column = Column::new("volume", ColumnType::U64).with_codec(Codec::Gorilla);
+    assert!(matches!(check_codec(&column), Err(Error::TypeMismatch { .. })));
+    let column = Column::new("id", ColumnType::String).with_codec(Codec::Delta);
+    assert!(matches!(check_codec(&column), Err(Error::TypeMismatch { .. })));
+    let column = Column::new("ticker", ColumnType::Symbol16).with_codec(Codec::FrameOfReference);
+    assert!(check_codec(&column).is_ok());
+  }
+}
@@ -0,0 +1,225 @@
+use crate::{
+  error::Error,
+  schema::Column,
+  table::{
+    index::{get_symbol_id, is_symbol},
+    zones::{get_value, has_zones},
+    Compare, Table, TableColumn
+  }
+};
+use serde::{Deserialize, Serialize};
+use std::{cmp::max, ops::Range};
+
+// Rows a scan yields. Comparisons use the same values as zone maps: decimals are divided by
+// 10^scale and timestamps are nanoseconds. Nulls match nothing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Filter {
+  // `column op value` on a number or Timestamp column
+  Compare(String, Compare, f64),
+  // A Symbol column is one of these symbols
+  In(String, Vec<String>),
+  And(Vec<Filter>),
+  Or(Vec<Filter>)
+}
+
+impl Filter {
+  pub fn compare(column: &str, op: Compare, value: f64) -> Self {
+    Filter::Compare(column.to_owned(), op, value)
+  }
+
+  pub fn is_in(column: &str, symbols: &[&str]) -> Self {
+    Filter::In(column.to_owned(), symbols.iter().map(|s| s.to_string()).collect())
+  }
+
+  pub fn and(self, other: Filter) -> Self {
+    match self {
+      Filter::And(mut filters) => {
+        filters.push(other);
+        Filter::And(filters)
+      }
+      filter => Filter::And(vec![filter, other])
+    }
+  }
+
+  pub fn or(self, other: Filter) -> Self {
+    match self {
+      Filter::Or(mut filters) => {
+        filters.push(other);
+        Filter::Or(filters)
+      }
+      filter => Filter::Or(vec![filter, other])
+    }
+  }
+}
+
+// A Filter with columns resolved to indexes into a list of columns to read and symbols resolved
+// to sorted symbol ids
+#[derive(Debug)]
+pub enum Predicate {
+  Compare(usize, Compare, f64),
+  In(usize, Vec<usize>),
+  And(Vec<Predicate>),
+  Or(Vec<Predicate>)
+}
+
+impl Predicate {
+  // Adds columns `filter` needs to `columns`
+  pub fn new(table: &Table, filter: &Filter, columns: &mut Vec<Column>) -> Result<Self, Error> {
+    let mut add_column = |name: &str| -> Result<(usize, usize), Error> {
+      let table_index = table
+        .schema
+        .columns
+        .iter()
+        .position(|c| c.name == name)
+        .ok_or_else(|| Error::ColumnNotFound {
+          table:  table.schema.name.clone(),
+          column: name.to_owned()
+        })?;
+      let index = match columns.iter().position(|c| c.name == name) {
+        Some(index) => index,
+        None => {
+          columns.push(table.schema.columns[table_index].clone());
+          columns.len() - 1
+        }
+      };
+      Ok((table_index, index))
+    };
+    Ok(match filter {
+      Filter::Compare(column, op, value) => {
+        let (table_index, index) = add_column(column)?;
+        let column = &table.schema.columns[table_index];
+        if !has_zones(column.r#type) {
+          return Err(Error::TypeMismatch {
+            column:   column.name.clone(),
+            expected: String::from("a number"),
+            actual:   column.r#type
+          });
+        }
+        Predicate::Compare(index, *op, *value)
+      }
+      Filter::In(column, symbols) => {
+        let (table_index, index) = add_column(column)?;
+        let column = &table.schema.columns[table_index];
+        if !is_symbol(column.r#type) {
+          return Err(Error::TypeMismatch {
+            column:   column.name.clone(),
+            expected: String::from("a Symbol"),
+            actual:   column.r#type
+          });
+        }
+        // Unknown symbols can't match
+        let symbol_nums = &table.column_symbols[table_index].symbol_nums;
+        let mut symbol_ids = symbols
+          .iter()
+          .filter_map(|symbol| symbol_nums.get(symbol).copied())
+          .collect::<Vec<_>>();
+        symbol_ids.sort_unstable();
+        symbol_ids.dedup();
+        Predicate::In(index, symbol_ids)
+      }
+      Filter::And(filters) => Predicate::And(
+        filters
+          .iter()
+          .map(|filter| Predicate::new(table, filter, columns))
+          .collect::<Result<Vec<_>, _>>()?
+      ),
+      Filter::Or(filters) => Predicate::Or(
+        filters
+          .iter()
+          .map(|filter| Predicate::new(table, filter, columns))
+          .collect::<Result<Vec<_>, _>>()?
+      )
+    })
+  }
+
+  // Whether symbol indexes alone find exactly the matching rows so they needn't be tested
+  pub fn is_exact(&self) -> bool {
+    match self {
+      Predicate::Compare(..) => false,
+      Predicate::In(..) => true,
+      Predicate::And(predicates) | Predicate::Or(predicates) => {
+        predicates.iter().all(Self::is_exact)
+      }
+    }
+  }
+
+  // `columns` are opened in the same order they were added in `new`
+  pub fn test(&self, columns: &[TableColumn], row_index: usize, min_ts: i64) -> bool {
+    match self {
+      Predicate::Compare(index, op, value) => {
+        let column = &columns[*index];
+        if column.nulls.as_ref().map_or(false, |nulls| nulls.get(row_index)) {
+          return false;
+        }
+        op.test(get_value(column, row_index, min_ts), *value)
+      }
+      Predicate::In(index, symbol_ids) => {
+        let symbol_id = get_symbol_id(&columns[*index], row_index);
+        symbol_ids.binary_search(&symbol_id).is_ok()
+      }
+      Predicate::And(predicates) => predicates.iter().all(|p| p.test(columns, row_index, min_ts)),
+      Predicate::Or(predicates) => predicates.iter().any(|p| p.test(columns, row_index, min_ts))
+    }
+  }
+
+  // The matching rows of `rows` as sorted ranges
+  pub fn select(
+    &self,
+    columns: &[TableColumn],
+    rows: &[Range<usize>],
+    min_ts: i64
+  ) -> Vec<Range<usize>> {
+    let mut res: Vec<Range<usize>> = Vec::new();
+    for row_index in rows.iter().flat_map(|range| range.clone()) {
+      if !self.test(columns, row_index, min_ts) {
+        continue;
+      }
+      match res.last_mut() {
+        Some(last) if last.end == row_index => last.end += 1,
+        _ => res.push(row_index..row_index + 1)
+      }
+    }
+    res
+  }
+}
+
+// Rows in either sorted list of ranges
+pub fn union(a: &[Range<usize>], b: &[Range<usize>]) -> Vec<Range<usize>> {
+  let mut ranges = a.iter().chain(b.iter()).cloned().collect::<Vec<_>>();
+  ranges.sort_unstable_by_key(|range| range.start);
+  let mut res: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+  for range in ranges {
+    match res.last_mut() {
+      Some(last) if last.end >= range.start => last.end = max(last.end, range.end),
+      _ => res.push(range)
+    }
+  }
+  res
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_union() {
+    assert_eq!(
+      union(&[0..10, 20..30], &[5..12, 30..40, 50..60]),
+      vec![0..12, 20..40, 50..60]
+    );
+    assert_eq!(union(&[], &[1..2]), vec![1..2]);
+  }
+
+  #[test]
+  fn builders() {
+    let filter = Filter::is_in("sym", &["AAPL"])
+      .and(Filter::compare("size", Compare::Gt, 100.0))
+      .and(Filter::compare("price", Compare::Lt, 5.0));
+    match &filter {
+      Filter::And(filters) => assert_eq!(filters.len(), 3),
+      filter => panic!("expected And but got {:?}", filter)
+    }
+    let json = serde_json::to_string(&filter).unwrap();
+    assert_eq!(serde_json::from_str::<Filter>(&json).unwrap(), filter);
+  }
+}
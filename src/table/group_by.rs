@@ -0,0 +1,440 @@
+use crate::{
+  error::Error,
+  schema::ColumnType,
+  table::{
+    index::is_symbol, resample::check_type, scan::PartitionColumn, zones::has_zones, ColumnBuffer,
+    Filter, OwnedColumn, Table
+  }
+};
+use fnv::FnvHashMap;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AggregateFn {
+  Sum,
+  // Non-null rows
+  Count,
+  Min,
+  Max,
+  Mean,
+  First,
+  Last,
+  // Weighted by another column
+  Vwap,
+  // Sample standard deviation
+  Stddev
+}
+
+// `function` of a number or Timestamp `column`. Decimals are divided by 10^scale. Null rows are
+// skipped.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Aggregation {
+  pub function: AggregateFn,
+  pub column:   String,
+  // Volume column for Vwap
+  pub weight:   Option<String>
+}
+
+impl Aggregation {
+  // Like "sum_size" or "vwap_price"
+  pub fn get_name(&self) -> String {
+    format!("{:?}_{}", self.function, self.column).to_lowercase()
+  }
+}
+
+// Groups rows by symbol columns and/or time buckets. Groups everything together if neither is
+// set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GroupBy {
+  // Symbol columns
+  pub columns:      Vec<String>,
+  // Buckets of this many nanoseconds since the epoch
+  pub bucket:       Option<i64>,
+  pub aggregations: Vec<Aggregation>,
+  pub filter:       Option<Filter>
+}
+
+impl GroupBy {
+  pub fn new() -> Self { Self::default() }
+
+  pub fn by(mut self, column: &str) -> Self {
+    self.columns.push(column.to_owned());
+    self
+  }
+
+  pub fn bucket(mut self, duration: i64) -> Self {
+    self.bucket = Some(duration);
+    self
+  }
+
+  pub fn aggregate(mut self, function: AggregateFn, column: &str) -> Self {
+    self.aggregations.push(Aggregation {
+      function,
+      column: column.to_owned(),
+      weight: None
+    });
+    self
+  }
+
+  pub fn vwap(mut self, price: &str, volume: &str) -> Self {
+    self.aggregations.push(Aggregation {
+      function: AggregateFn::Vwap,
+      column:   price.to_owned(),
+      weight:   Some(volume.to_owned())
+    });
+    self
+  }
+
+  pub fn filter(mut self, filter: Filter) -> Self {
+    self.filter = Some(filter);
+    self
+  }
+}
+
+// An aggregate of one group's rows so far that merges with the aggregate of later rows
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Partial {
+  Sum(f64),
+  Count(u64),
+  Min(Option<f64>),
+  Max(Option<f64>),
+  Mean { sum: f64, count: u64 },
+  First(Option<f64>),
+  Last(Option<f64>),
+  Vwap { value: f64, weight: f64 },
+  // Welford's running mean and sum of squared differences from it
+  Stddev { count: u64, mean: f64, m2: f64 }
+}
+
+impl Partial {
+  fn new(function: AggregateFn) -> Self {
+    match function {
+      AggregateFn::Sum => Partial::Sum(0.0),
+      AggregateFn::Count => Partial::Count(0),
+      AggregateFn::Min => Partial::Min(None),
+      AggregateFn::Max => Partial::Max(None),
+      AggregateFn::Mean => Partial::Mean { sum: 0.0, count: 0 },
+      AggregateFn::First => Partial::First(None),
+      AggregateFn::Last => Partial::Last(None),
+      AggregateFn::Vwap => Partial::Vwap { value: 0.0, weight: 0.0 },
+      AggregateFn::Stddev => Partial::Stddev { count: 0, mean: 0.0, m2: 0.0 }
+    }
+  }
+
+  fn add(&mut self, value: f64, weight: f64) {
+    match self {
+      Partial::Sum(sum) => *sum += value,
+      Partial::Count(count) => *count += 1,
+      Partial::Min(min) => *min = Some(min.map_or(value, |min| min.min(value))),
+      Partial::Max(max) => *max = Some(max.map_or(value, |max| max.max(value))),
+      Partial::Mean { sum, count } => {
+        *sum += value;
+        *count += 1;
+      }
+      Partial::First(first) => {
+        first.get_or_insert(value);
+      }
+      Partial::Last(last) => *last = Some(value),
+      Partial::Vwap { value: total, weight: total_weight } => {
+        *total += value * weight;
+        *total_weight += weight;
+      }
+      Partial::Stddev { count, mean, m2 } => {
+        *count += 1;
+        let delta = value - *mean;
+        *mean += delta / *count as f64;
+        *m2 += delta * (value - *mean);
+      }
+    }
+  }
+
+  // `other` aggregates later rows
+  fn merge(&mut self, other: Partial) {
+    match (self, other) {
+      (Partial::Sum(a), Partial::Sum(b)) => *a += b,
+      (Partial::Count(a), Partial::Count(b)) => *a += b,
+      (Partial::Min(a), Partial::Min(Some(b))) => *a = Some(a.map_or(b, |a| a.min(b))),
+      (Partial::Max(a), Partial::Max(Some(b))) => *a = Some(a.map_or(b, |a| a.max(b))),
+      (Partial::Mean { sum, count }, Partial::Mean { sum: b_sum, count: b_count }) => {
+        *sum += b_sum;
+        *count += b_count;
+      }
+      (Partial::First(a), Partial::First(b)) => *a = a.or(b),
+      (Partial::Last(a), Partial::Last(Some(b))) => *a = Some(b),
+      (
+        Partial::Vwap { value, weight },
+        Partial::Vwap { value: b_value, weight: b_weight }
+      ) => {
+        *value += b_value;
+        *weight += b_weight;
+      }
+      (
+        Partial::Stddev { count, mean, m2 },
+        Partial::Stddev { count: b_count, mean: b_mean, m2: b_m2 }
+      ) => {
+        if b_count == 0 {
+          return;
+        }
+        let total = *count + b_count;
+        let delta = b_mean - *mean;
+        *m2 += b_m2 + delta * delta * (*count as f64) * (b_count as f64) / total as f64;
+        *mean += delta * b_count as f64 / total as f64;
+        *count = total;
+      }
+      _ => {}
+    }
+  }
+
+  // None if there were no rows to aggregate
+  fn get_value(&self) -> Option<f64> {
+    match *self {
+      Partial::Sum(sum) => Some(sum),
+      Partial::Count(count) => Some(count as f64),
+      Partial::Min(value) | Partial::Max(value) | Partial::First(value) | Partial::Last(value) => {
+        value
+      }
+      Partial::Mean { sum, count } => Some(sum / count as f64).filter(|_| count > 0),
+      Partial::Vwap { value, weight } => Some(value / weight).filter(|_| weight != 0.0),
+      Partial::Stddev { count, m2, .. } if count > 1 => Some((m2 / (count - 1) as f64).sqrt()),
+      Partial::Stddev { .. } => None
+    }
+  }
+}
+
+fn get_number(column: &PartitionColumn, row_index: usize) -> f64 {
+  match column.column.r#type {
+    ColumnType::Timestamp => column.get_timestamp(row_index) as f64,
+    ColumnType::I8 => column.get_i8()[row_index] as f64,
+    ColumnType::U8 => column.get_u8()[row_index] as f64,
+    ColumnType::I16 => column.get_i16()[row_index] as f64,
+    ColumnType::U16 => column.get_u16()[row_index] as f64,
+    ColumnType::I32 => column.get_i32()[row_index] as f64,
+    ColumnType::U32 => column.get_u32()[row_index] as f64,
+    ColumnType::I64 => column.get_i64()[row_index] as f64,
+    ColumnType::U64 => column.get_u64()[row_index] as f64,
+    ColumnType::F32 => column.get_f32()[row_index] as f64,
+    ColumnType::F64 => column.get_f64()[row_index],
+    ColumnType::Decimal32 { scale } | ColumnType::Decimal64 { scale } => {
+      column.get_decimal(row_index) as f64 / 10_f64.powi(scale as i32)
+    }
+    ctype => panic!("ColumnType {:?} is not a number", ctype)
+  }
+}
+
+// Symbol ids of the group columns followed by the bucket
+type GroupKey = Vec<i64>;
+
+impl Table {
+  // Aggregates rows between `from_ts` and `to_ts` per group. Partitions are aggregated in
+  // parallel with symbol ids as keys and merged. Returns the group columns, then the bucket
+  // named after the timestamp column, then a column per aggregation sorted by bucket and symbol.
+  // Count columns are U64 and the rest are F64 and null for groups with no rows to aggregate.
+  pub fn group_by(
+    &self,
+    from_ts: i64,
+    to_ts: i64,
+    group_by: &GroupBy
+  ) -> Result<Vec<OwnedColumn>, Error> {
+    if let Some(bucket) = group_by.bucket.filter(|bucket| *bucket <= 0) {
+      return Err(Error::Query(format!("bucket must be positive but is {}", bucket)));
+    }
+    let mut columns = vec![self.schema.columns[0].name.as_str()];
+    columns.extend(group_by.columns.iter().map(|c| c.as_str()));
+    for aggregation in &group_by.aggregations {
+      for column in std::iter::once(&aggregation.column).chain(&aggregation.weight) {
+        if !columns.contains(&column.as_str()) {
+          columns.push(column);
+        }
+      }
+    }
+    let mut partitions = self.partition_iter(from_ts, to_ts, columns.clone())?;
+    let get_index = |name: &str| self.schema.columns.iter().position(|c| c.name == name).unwrap();
+    for name in &group_by.columns {
+      let column = &self.schema.columns[get_index(name)];
+      check_type(column, "a Symbol", is_symbol(column.r#type))?;
+    }
+    for aggregation in &group_by.aggregations {
+      for name in std::iter::once(&aggregation.column).chain(&aggregation.weight) {
+        let column = &self.schema.columns[get_index(name)];
+        check_type(column, "a number", has_zones(column.r#type))?;
+      }
+      if aggregation.function == AggregateFn::Vwap && aggregation.weight.is_none() {
+        return Err(Error::Query(format!(
+          "{} needs a volume column",
+          aggregation.get_name()
+        )));
+      }
+    }
+    if let Some(filter) = &group_by.filter {
+      partitions = partitions.filter(filter.clone())?;
+    }
+    // Indexes into `columns` of each aggregation's column and weight
+    let aggregations = group_by
+      .aggregations
+      .iter()
+      .map(|a| {
+        let get_index = |name: &str| columns.iter().position(|c| *c == name).unwrap();
+        (a.function, get_index(&a.column), a.weight.as_deref().map(get_index))
+      })
+      .collect::<Vec<_>>();
+    let group_columns = 1..1 + group_by.columns.len();
+
+    let groups = partitions.par_scan(
+      |partition| {
+        let mut res = FnvHashMap::<GroupKey, Vec<Partial>>::default();
+        let mut key = GroupKey::with_capacity(group_columns.len() + 1);
+        for row_index in 0..partition[0].row_count {
+          key.clear();
+          for column in &partition[group_columns.clone()] {
+            // Null symbols are 0
+            key.push(column.get_symbol_id(row_index) as i64);
+          }
+          if let Some(bucket) = group_by.bucket {
+            let ts = partition[0].get_timestamp(row_index);
+            key.push(ts - ts.rem_euclid(bucket));
+          }
+          let partials = match res.get_mut(&key) {
+            Some(partials) => partials,
+            None => res.entry(key.clone()).or_insert_with(|| {
+              aggregations.iter().map(|(function, ..)| Partial::new(*function)).collect()
+            })
+          };
+          for (partial, (_function, index, weight_index)) in partials.iter_mut().zip(&aggregations)
+          {
+            let column = &partition[*index];
+            if column.is_null(row_index) {
+              continue;
+            }
+            let weight = match weight_index {
+              Some(i) if partition[*i].is_null(row_index) => continue,
+              Some(i) => get_number(&partition[*i], row_index),
+              None => 1.0
+            };
+            partial.add(get_number(column, row_index), weight);
+          }
+        }
+        res
+      },
+      |mut a, b| {
+        for (key, partials) in b {
+          match a.get_mut(&key) {
+            Some(a_partials) => {
+              for (a_partial, partial) in a_partials.iter_mut().zip(partials) {
+                a_partial.merge(partial);
+              }
+            }
+            None => {
+              a.insert(key, partials);
+            }
+          }
+        }
+        a
+      }
+    )?;
+
+    let symbols = group_by
+      .columns
+      .iter()
+      .map(|name| &self.column_symbols[get_index(name)].symbols)
+      .collect::<Vec<_>>();
+    let get_symbol = |column: usize, symbol_id: i64| match symbol_id {
+      0 => "",
+      symbol_id => symbols[column][symbol_id as usize - 1].as_str()
+    };
+    let mut groups = groups.unwrap_or_default().into_iter().collect::<Vec<_>>();
+    groups.sort_unstable_by(|(a, _), (b, _)| {
+      let bucket_order = match group_by.bucket {
+        Some(_) => a[a.len() - 1].cmp(&b[b.len() - 1]),
+        None => Ordering::Equal
+      };
+      bucket_order.then_with(|| {
+        (0..symbols.len())
+          .map(|i| get_symbol(i, a[i]).cmp(get_symbol(i, b[i])))
+          .find(|order| *order != Ordering::Equal)
+          .unwrap_or(Ordering::Equal)
+      })
+    });
+
+    let mut res = Vec::new();
+    for (i, name) in group_by.columns.iter().enumerate() {
+      let column = &self.schema.columns[get_index(name)];
+      res.push(OwnedColumn {
+        name:   name.clone(),
+        r#type: column.r#type,
+        data:   ColumnBuffer::Symbol(
+          groups.iter().map(|(key, _)| get_symbol(i, key[i]).to_owned()).collect()
+        ),
+        nulls:  if column.nullable {
+          Some(groups.iter().map(|(key, _)| key[i] == 0).collect())
+        } else {
+          None
+        }
+      });
+    }
+    if group_by.bucket.is_some() {
+      res.push(OwnedColumn {
+        name:   self.schema.columns[0].name.clone(),
+        r#type: ColumnType::Timestamp,
+        data:   ColumnBuffer::Timestamp(groups.iter().map(|(key, _)| key[key.len() - 1]).collect()),
+        nulls:  None
+      });
+    }
+    for (i, aggregation) in group_by.aggregations.iter().enumerate() {
+      let values = groups.iter().map(|(_, partials)| partials[i].get_value());
+      res.push(match aggregation.function {
+        AggregateFn::Count => OwnedColumn {
+          name:   aggregation.get_name(),
+          r#type: ColumnType::U64,
+          data:   ColumnBuffer::U64(values.map(|v| v.unwrap() as u64).collect()),
+          nulls:  None
+        },
+        _ => OwnedColumn {
+          name:   aggregation.get_name(),
+          r#type: ColumnType::F64,
+          data:   ColumnBuffer::F64(values.clone().map(|v| v.unwrap_or(0.0)).collect()),
+          nulls:  Some(values.map(|v| v.is_none()).collect())
+        }
+      });
+    }
+
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn merge_partials() {
+    let values = [3.0, 1.5, 8.0, -2.0, 4.25, 7.0, 0.5];
+    for function in &[
+      AggregateFn::Sum,
+      AggregateFn::Count,
+      AggregateFn::Min,
+      AggregateFn::Max,
+      AggregateFn::Mean,
+      AggregateFn::First,
+      AggregateFn::Last,
+      AggregateFn::Vwap,
+      AggregateFn::Stddev
+    ] {
+      let mut all = Partial::new(*function);
+      values.iter().for_each(|v| all.add(*v, 2.0));
+      for split in 0..values.len() {
+        let mut a = Partial::new(*function);
+        let mut b = Partial::new(*function);
+        values[..split].iter().for_each(|v| a.add(*v, 2.0));
+        values[split..].iter().for_each(|v| b.add(*v, 2.0));
+        a.merge(b);
+        let (a, all) = (a.get_value().unwrap(), all.get_value().unwrap());
+        assert!((a - all).abs() < 1e-9, "{:?} {} != {}", function, a, all);
+      }
+    }
+    let mut stddev = Partial::new(AggregateFn::Stddev);
+    [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].iter().for_each(|v| stddev.add(*v, 1.0));
+    assert!((stddev.get_value().unwrap() - 2.138089935299395).abs() < 1e-12);
+    assert_eq!(Partial::new(AggregateFn::Mean).get_value(), None);
+  }
+}
@@ -0,0 +1,121 @@
+use crate::error::{Error, IoContext};
+use memmap::MmapMut;
+use std::{
+  cmp::max,
+  convert::TryInto,
+  fs::{File, OpenOptions},
+  ops::Range,
+  path::PathBuf
+};
+
+// Values of variable-length columns. The column's own file holds each row's end offset into this
+// one as a u64, so row `i` is `data[offsets[i - 1]..offsets[i]]`.
+#[derive(Debug)]
+pub struct TableHeap {
+  pub file: File,
+  pub data: MmapMut,
+  pub path: PathBuf
+}
+
+pub fn get_heap_path(col_path: &PathBuf) -> PathBuf {
+  let mut path = col_path.clone().into_os_string();
+  path.push(".data");
+  PathBuf::from(path)
+}
+
+// memmap can't map empty files
+fn get_map_len(len: usize) -> usize { max(len, 1) }
+
+impl TableHeap {
+  pub fn open(col_path: &PathBuf, min_len: usize) -> Result<TableHeap, Error> {
+    let path = get_heap_path(col_path);
+    let file = OpenOptions::new()
+      .read(true)
+      .write(true)
+      .create(true)
+      .open(&path)
+      .with_path(&path)?;
+    let len = file.metadata().with_path(&path)?.len() as usize;
+    // Never truncate here since we don't know how much is used
+    file
+      .set_len(get_map_len(max(len, min_len)) as u64)
+      .with_path(&path)?;
+    let data = unsafe { memmap::MmapOptions::new().map_mut(&file).with_path(&path)? };
+
+    Ok(TableHeap { file, data, path })
+  }
+
+  pub fn open_read_only(col_path: &PathBuf, len: usize) -> Result<TableHeap, Error> {
+    let path = get_heap_path(col_path);
+    let file = OpenOptions::new().read(true).open(&path).with_path(&path)?;
+    let file_len = file.metadata().with_path(&path)?.len();
+    if file_len < len as u64 {
+      return Err(Error::CorruptMeta {
+        path,
+        reason: format!("expected at least {} bytes but file has {}", len, file_len)
+      });
+    }
+    let data = unsafe {
+      memmap::MmapOptions::new()
+        .len(get_map_len(len))
+        .map_copy(&file)
+        .with_path(&path)?
+    };
+
+    Ok(TableHeap { file, data, path })
+  }
+
+  fn remap(&mut self, len: usize) -> Result<(), Error> {
+    self
+      .file
+      .set_len(get_map_len(len) as u64)
+      .with_path(&self.path)?;
+    unsafe {
+      self.data = memmap::MmapOptions::new()
+        .map_mut(&self.file)
+        .with_path(&self.path)?;
+    }
+    Ok(())
+  }
+
+  // Copies `bytes` to `offset`, growing the file if needed
+  pub fn put(&mut self, offset: usize, bytes: &[u8]) -> Result<(), Error> {
+    let end = offset + bytes.len();
+    if self.data.len() < end {
+      let mut len = max(self.data.len(), 1);
+      while len < end {
+        len *= 2;
+      }
+      self.remap(len)?;
+    }
+    self.data[offset..end].copy_from_slice(bytes);
+    Ok(())
+  }
+
+  pub fn flush(&mut self, len: usize) -> Result<(), Error> {
+    self.data.flush().with_path(&self.path)?;
+    self.remap(len)
+  }
+}
+
+fn get_offset(offsets: &[u8], row_index: usize) -> usize {
+  let bytes = &offsets[row_index * 8..row_index * 8 + 8];
+  u64::from_le_bytes(bytes.try_into().unwrap()) as usize
+}
+
+pub fn get_range(offsets: &[u8], row_index: usize) -> Range<usize> {
+  let start = if row_index == 0 {
+    0
+  } else {
+    get_offset(offsets, row_index - 1)
+  };
+  start..get_offset(offsets, row_index)
+}
+
+// Bytes used by the first `row_count` rows
+pub fn get_heap_len(offsets: &[u8], row_count: usize) -> usize {
+  match row_count {
+    0 => 0,
+    _ => get_offset(offsets, row_count - 1)
+  }
+}
@@ -0,0 +1,177 @@
+use crate::{
+  error::{Error, IoContext},
+  schema::ColumnType,
+  table::{Table, TableColumn}
+};
+use fnv::FnvHashMap;
+use std::{
+  convert::TryInto,
+  fs::{read, rename, File},
+  io::{ErrorKind, Write},
+  ops::Range,
+  path::PathBuf
+};
+
+// Symbol indexes map each symbol id of a partition's Symbol column to the sorted row ranges that
+// hold it so single-symbol scans only read those rows. The file is the row count it was built for,
+// then for each symbol its u64 id, u64 range count and u64 start and end of each range.
+pub type SymbolIndex = FnvHashMap<usize, Vec<Range<usize>>>;
+
+pub fn get_index_path(col_path: &PathBuf) -> PathBuf {
+  let mut path = col_path.clone().into_os_string();
+  path.push(".index");
+  PathBuf::from(path)
+}
+
+pub fn is_symbol(r#type: ColumnType) -> bool {
+  matches!(
+    r#type,
+    ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32
+  )
+}
+
+pub fn get_symbol_id(column: &TableColumn, row_index: usize) -> usize {
+  let bytes = &column.data[row_index * column.size..(row_index + 1) * column.size];
+  match column.size {
+    1 => bytes[0] as usize,
+    2 => u16::from_le_bytes(bytes.try_into().unwrap()) as usize,
+    4 => u32::from_le_bytes(bytes.try_into().unwrap()) as usize,
+    s => panic!("Invalid symbol size {}", s)
+  }
+}
+
+// Adds `rows` of `column` to `index`
+pub fn index_rows(column: &TableColumn, rows: Range<usize>, index: &mut SymbolIndex) {
+  for row_index in rows {
+    let ranges = index
+      .entry(get_symbol_id(column, row_index))
+      .or_insert_with(Vec::new);
+    match ranges.last_mut() {
+      Some(last) if last.end == row_index => last.end += 1,
+      _ => ranges.push(row_index..row_index + 1)
+    }
+  }
+}
+
+// Returns the row count the index was built for and the index
+fn read_index_file(path: &PathBuf) -> Result<Option<(usize, SymbolIndex)>, Error> {
+  let data = match read(path) {
+    Ok(data) => data,
+    Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+    Err(error) => {
+      return Err(Error::Io {
+        path: path.clone(),
+        error
+      })
+    }
+  };
+  let mut pos = 0;
+  let mut next = || -> Option<usize> {
+    let bytes = data.get(pos..pos + 8)?;
+    pos += 8;
+    Some(u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+  };
+  let mut read_index = || -> Option<(usize, SymbolIndex)> {
+    let row_count = next()?;
+    let mut index = SymbolIndex::default();
+    while let Some(symbol_id) = next() {
+      let range_count = next()?;
+      let mut ranges = Vec::with_capacity(range_count);
+      for _ in 0..range_count {
+        ranges.push(next()?..next()?);
+      }
+      index.insert(symbol_id, ranges);
+    }
+    Some((row_count, index))
+  };
+  // A truncated file is as good as a missing one
+  Ok(read_index())
+}
+
+// Indexes built for a different row count are stale and can't be used
+pub fn read_index(col_path: &PathBuf, row_count: usize) -> Result<Option<SymbolIndex>, Error> {
+  Ok(match read_index_file(&get_index_path(col_path))? {
+    Some((index_row_count, index)) if index_row_count == row_count => Some(index),
+    _ => None
+  })
+}
+
+fn write_index_file(path: &PathBuf, row_count: usize, index: &SymbolIndex) -> Result<(), Error> {
+  let mut data = Vec::new();
+  let mut push = |val: usize| data.extend_from_slice(&(val as u64).to_le_bytes());
+  push(row_count);
+  let mut symbol_ids = index.keys().collect::<Vec<_>>();
+  symbol_ids.sort_unstable();
+  for symbol_id in symbol_ids {
+    let ranges = &index[symbol_id];
+    push(*symbol_id);
+    push(ranges.len());
+    for range in ranges {
+      push(range.start);
+      push(range.end);
+    }
+  }
+  let mut tmp_path = path.clone().into_os_string();
+  tmp_path.push(".tmp");
+  let tmp_path = PathBuf::from(tmp_path);
+  File::create(&tmp_path)
+    .and_then(|mut f| f.write_all(&data))
+    .with_path(&tmp_path)?;
+  rename(&tmp_path, path).with_path(path)
+}
+
+impl Table {
+  fn write_symbol_indexes(&self, from_row: usize) -> Result<(), Error> {
+    let row_count = self.cur_partition_meta.row_count;
+    for (column, table_column) in self.schema.columns.iter().zip(self.columns.iter()) {
+      if !is_symbol(column.r#type) {
+        continue;
+      }
+      let path = get_index_path(&table_column.path);
+      let (index_row_count, mut index) = match read_index_file(&path)? {
+        Some((index_row_count, index)) if index_row_count <= row_count => (index_row_count, index),
+        _ => (0, SymbolIndex::default())
+      };
+      let from_row = from_row.min(index_row_count);
+      for ranges in index.values_mut() {
+        ranges.retain(|range| range.start < from_row);
+        if let Some(last) = ranges.last_mut() {
+          last.end = last.end.min(from_row);
+        }
+      }
+      index.retain(|_symbol_id, ranges| !ranges.is_empty());
+      index_rows(table_column, from_row..row_count, &mut index);
+      write_index_file(&path, row_count, &index)?;
+    }
+    Ok(())
+  }
+
+  // Brings the current partition's zones and symbol indexes up to date with its rows
+  pub(super) fn write_indexes(&mut self) -> Result<(), Error> {
+    let row_count = self.cur_partition_meta.row_count;
+    if self.columns.is_empty() || row_count == 0 {
+      return Ok(());
+    }
+    self.write_zones(self.index_from)?;
+    self.write_symbol_indexes(self.index_from)?;
+    self.index_from = row_count;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn write_read_index() {
+    let mut index = SymbolIndex::default();
+    index.insert(1, vec![0..3, 7..8]);
+    index.insert(2, vec![3..7]);
+    let path = std::env::temp_dir().join(format!("zdb_index_{}.index", std::process::id()));
+    write_index_file(&path, 8, &index).unwrap();
+    assert_eq!(read_index_file(&path).unwrap(), Some((8, index)));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(read_index_file(&path).unwrap(), None);
+  }
+}
@@ -0,0 +1,72 @@
+use crate::{
+  error::Error,
+  table::{index::is_symbol, resample::check_type, Filter, OwnedColumn, Table}
+};
+
+impl Table {
+  // The last row at or before `as_of_ts` of each symbol of `symbol_column` or of just `symbols`
+  // if it isn't empty. Walks partitions backwards and stops once every symbol has a row. Returns
+  // `symbol_column` followed by `columns` with the most recent row first.
+  pub fn latest_by(
+    &self,
+    symbol_column: &str,
+    as_of_ts: i64,
+    columns: &[&str],
+    symbols: &[&str]
+  ) -> Result<Vec<OwnedColumn>, Error> {
+    let mut scan_columns = vec![symbol_column];
+    scan_columns.extend(columns);
+    // Also checks the columns exist
+    let partitions = self.partition_iter(i64::MIN, as_of_ts, scan_columns.clone())?.partitions;
+    let get_index = |name: &str| self.schema.columns.iter().position(|c| c.name == name).unwrap();
+    let symbol_index = get_index(symbol_column);
+    let column = &self.schema.columns[symbol_index];
+    check_type(column, "a Symbol", is_symbol(column.r#type))?;
+
+    // Symbol ids start at 1
+    let column_symbols = &self.column_symbols[symbol_index];
+    let mut pending = vec![symbols.is_empty(); column_symbols.symbols.len() + 1];
+    pending[0] = false;
+    for symbol in symbols {
+      if let Some(symbol_id) = column_symbols.symbol_nums.get(*symbol) {
+        pending[*symbol_id] = true;
+      }
+    }
+    let mut pending_count = pending.iter().filter(|p| **p).count();
+    let mut res = scan_columns
+      .iter()
+      .map(|name| OwnedColumn::new(&self.schema.columns[get_index(name)], false))
+      .collect::<Vec<_>>();
+    for partition in partitions.into_iter().rev() {
+      if pending_count == 0 {
+        break;
+      }
+      let mut items = self.partition_iter(i64::MIN, as_of_ts, scan_columns.clone())?;
+      items.partitions = vec![partition];
+      if !symbols.is_empty() {
+        // Only read rows of symbols still missing
+        let missing = (1..pending.len())
+          .filter(|symbol_id| pending[*symbol_id])
+          .map(|symbol_id| column_symbols.symbols[symbol_id - 1].as_str())
+          .collect::<Vec<_>>();
+        items = items.filter(Filter::is_in(symbol_column, &missing))?;
+      }
+      let items = items.collect::<Result<Vec<_>, _>>()?;
+      for item in items.iter().rev() {
+        for row_index in (0..item[0].row_count).rev() {
+          let symbol_id = item[0].get_symbol_id(row_index);
+          if pending.get(symbol_id) != Some(&true) {
+            continue;
+          }
+          pending[symbol_id] = false;
+          pending_count -= 1;
+          for (res, column) in res.iter_mut().zip(item) {
+            res.push(column, row_index);
+          }
+        }
+      }
+    }
+
+    Ok(res)
+  }
+}
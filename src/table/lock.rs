@@ -0,0 +1,88 @@
+use crate::{
+  error::{Error, IoContext},
+  table::{meta::read_meta, read::read_column_symbols, wal::get_wal_path, Table}
+};
+use nix::{
+  errno::Errno,
+  fcntl::{flock, FlockArg}
+};
+use std::{fs::OpenOptions, io, os::unix::io::AsRawFd, path::PathBuf};
+
+pub fn get_lock_path(meta_path: &PathBuf) -> PathBuf { meta_path.with_file_name("_lock") }
+
+impl Table {
+  // Readers never take this lock. Since _meta and .symbols are swapped in atomically they always
+  // see the last flush.
+  pub fn lock(&mut self) -> Result<(), Error> {
+    if self.lock.is_some() {
+      return Ok(());
+    }
+    let path = get_lock_path(&self.meta_path);
+    let file = OpenOptions::new()
+      .write(true)
+      .create(true)
+      .open(&path)
+      .with_path(&path)?;
+    match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+      Ok(()) => {}
+      Err(nix::Error::Sys(Errno::EAGAIN)) => {
+        return Err(Error::TableLocked(self.schema.name.clone()))
+      }
+      Err(e) => {
+        return Err(Error::Io {
+          path,
+          error: io::Error::new(io::ErrorKind::Other, e)
+        })
+      }
+    }
+    self.lock = Some(file);
+
+    // Another writer may have flushed since this table was opened
+    let res = self.reload().and_then(|_| self.replay_wal());
+    if res.is_err() {
+      self.unlock();
+    }
+    res
+  }
+
+  pub fn unlock(&mut self) { self.lock = None; }
+
+  fn reload(&mut self) -> Result<(), Error> {
+    if !self.meta_path.exists() {
+      return Ok(());
+    }
+    let latest = read_meta(&self.meta_path, &self.schema.name)?;
+    if latest.version != self.version {
+      let data_path = self.meta_path.parent().unwrap().to_path_buf();
+      // Columns may have been altered. The name isn't stored.
+      let name = std::mem::take(&mut self.schema.name);
+      self.schema = latest.schema;
+      self.schema.name = name;
+      self.column_symbols = read_column_symbols(&data_path, &self.schema)?;
+      self.partition_meta = latest.partition_meta;
+      self.derived = latest.derived;
+      self.dir_index = latest.dir_index;
+      self.version = latest.version;
+    }
+    Ok(())
+  }
+
+  // Replay a crashed writer's WAL unless a live writer owns it
+  pub(super) fn recover(&mut self) -> Result<(), Error> {
+    let wal_len = get_wal_path(&self.meta_path)
+      .metadata()
+      .map(|m| m.len())
+      .unwrap_or(0);
+    if wal_len == 0 {
+      return Ok(());
+    }
+    match self.lock() {
+      Ok(()) => {
+        self.unlock();
+        Ok(())
+      }
+      Err(Error::TableLocked(_)) => Ok(()),
+      Err(e) => Err(e)
+    }
+  }
+}
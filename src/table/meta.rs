@@ -1,16 +1,27 @@
-use crate::table::Table;
+use crate::{
+  error::{Error, IoContext},
+  table::Table
+};
 use std::{
-  fs::{File, OpenOptions},
-  io::{BufReader, Write},
+  fs::{rename, File, OpenOptions},
+  io::{BufReader, ErrorKind, Write},
   path::PathBuf
 };
 
-pub fn read_meta(meta_path: &PathBuf) -> std::io::Result<Table> {
-  let f = File::open(meta_path)?;
+pub fn read_meta(meta_path: &PathBuf, name: &str) -> Result<Table, Error> {
+  let f = File::open(meta_path).map_err(|error| match error.kind() {
+    ErrorKind::NotFound => Error::TableNotFound(name.to_owned()),
+    _ => Error::Io {
+      path: meta_path.clone(),
+      error
+    }
+  })?;
   let reader = BufReader::new(f);
 
-  let res = serde_json::from_reader(reader)?;
-  Ok(res)
+  serde_json::from_reader(reader).map_err(|e| Error::CorruptMeta {
+    path:   meta_path.clone(),
+    reason: e.to_string()
+  })
 }
 
 impl Table {
@@ -22,17 +33,31 @@ impl Table {
     }
   }
 
-  pub fn write_meta(&self) -> std::io::Result<()> {
+  // Write the next version to a temporary file and rename it over _meta so that readers
+  // never see a partially written document
+  pub fn write_meta(&mut self) -> Result<(), Error> {
+    self.version += 1;
+    let tmp_path = self
+      .meta_path
+      .with_file_name(format!("_meta.{}", self.version));
     let mut f = OpenOptions::new()
       .write(true)
       .create(true)
-      .open(&self.meta_path)
-      .unwrap_or_else(|_| panic!("Could not open meta file {:?}", &self.meta_path));
+      .truncate(true)
+      .open(&tmp_path)
+      .with_path(&tmp_path)?;
 
-    serde_json::to_writer_pretty(&f, &self)
-      .unwrap_or_else(|_| panic!("Could not write to meta file {:?}", &self.meta_path));
-    f.flush()
-      .unwrap_or_else(|_| panic!("Could not flush to meta file {:?}", &self.meta_path));
+    serde_json::to_writer_pretty(&f, &self).map_err(|e| Error::Io {
+      path:  tmp_path.clone(),
+      error: e.into()
+    })?;
+    f.flush().and_then(|_| f.sync_all()).with_path(&tmp_path)?;
+    rename(&tmp_path, &self.meta_path).with_path(&self.meta_path)?;
+    // Persist the rename
+    if let Some(dir) = self.meta_path.parent() {
+      let dir = dir.to_path_buf();
+      File::open(&dir).and_then(|d| d.sync_all()).with_path(&dir)?;
+    }
     Ok(())
   }
 
@@ -66,3 +91,44 @@ impl Table {
     max_ts
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    schema::{Column, ColumnType, PartitionBy, Schema},
+    table::{get_data_path, Table}
+  };
+  use std::fs::{read_dir, remove_dir_all};
+
+  #[test]
+  fn write_meta_versions() {
+    let name = "meta_test";
+    let _ = remove_dir_all(get_data_path(name));
+    let schema = Schema::new(name)
+      .add_cols(vec![
+        Column::new("ts", ColumnType::Timestamp),
+        Column::new("volume", ColumnType::U32),
+      ])
+      .partition_by(PartitionBy::Day);
+    let mut table = Table::create(schema).unwrap();
+    assert_eq!(table.version, 1);
+    let day = 24 * 60 * 60 * 1_000_000_000;
+    for i in 0..3 {
+      table.put_timestamp(i * day).unwrap();
+      table.put_u32(i as u32).unwrap();
+      table.write().unwrap();
+    }
+    table.flush().unwrap();
+    assert_eq!(table.version, 2);
+
+    let table = Table::open(name).unwrap();
+    assert_eq!(table.version, 2);
+    assert_eq!(table.partition_meta.len(), 3);
+    let files = read_dir(get_data_path(name))
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+      .filter(|name| name.starts_with("_meta"))
+      .collect::<Vec<_>>();
+    assert_eq!(files, vec!["_meta"]);
+  }
+}
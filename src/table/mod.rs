@@ -1,20 +1,54 @@
+mod aggregate;
+mod alter;
+mod arrow;
+mod asof;
+mod batch;
+mod codec;
+mod filter;
+mod group_by;
+mod heap;
+mod index;
+mod latest;
+mod lock;
 mod meta;
+mod nulls;
+mod parquet;
 mod read;
+mod resample;
+mod row;
 pub mod scan;
+mod seal;
+mod wal;
 mod write;
+mod zones;
 use fnv::FnvHashMap;
 use serde::{Deserialize, Serialize};
 use std::env;
 
-use crate::schema::*;
+use crate::{
+  error::{Error, IoContext},
+  schema::*
+};
 // "meta" crate is reserved
 // https://internals.rust-lang.org/t/is-the-module-name-meta-forbidden/9587/3
 use crate::table::meta::*;
+pub use self::arrow::{get_data_type, get_field, to_record_batch, RecordBatches};
+pub use asof::AsOf;
+pub use batch::{ColumnBuffer, ColumnData, OwnedColumn};
+use codec::check_codec;
+pub use filter::Filter;
+pub use group_by::{AggregateFn, Aggregation, GroupBy};
+pub use heap::TableHeap;
+pub use nulls::TableNulls;
+pub use self::parquet::get_parquet_schema;
+pub use resample::{parse_duration, Alignment, Bars, Resample};
+pub use row::{RowIterator, Rows, ZdbRow};
+pub use zones::{Compare, Zone};
 use read::*;
 use std::{
   collections::HashMap,
   fs::{create_dir_all, File},
-  io::{Error, ErrorKind},
+  io::BufWriter,
   path::PathBuf
 };
 
@@ -35,7 +69,10 @@ pub struct TableColumn {
   pub path:       PathBuf,
   pub r#type:     ColumnType,
   pub size:       usize,
-  pub resolution: i64
+  pub resolution: i64,
+  pub nulls:      Option<TableNulls>,
+  // For String and Bytes columns
+  pub heap:       Option<TableHeap>
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -45,12 +82,18 @@ pub struct PartitionMeta {
   pub to_ts:     i64,
   pub min_ts:    i64,
   pub max_ts:    i64,
-  pub row_count: usize
+  pub row_count: usize,
+  // Columns with a codec are stored compressed in .blocks files
+  #[serde(default)]
+  pub sealed:    bool
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Table {
   pub schema: Schema,
+  // Incremented on every _meta write
+  #[serde(default)]
+  pub version: u64,
   // This file's existance means the Table exists
   #[serde(skip)]
   meta_path: PathBuf,
@@ -64,19 +107,41 @@ pub struct Table {
   pub column_symbols: Vec<TableColumnSymbols>,
   // Partition metadata
   pub partition_meta: HashMap<String, PartitionMeta>,
+  // Names of tables aggregated from this one
+  #[serde(default)]
+  pub derived: Vec<String>,
   // Helps better choose which next partition to write to
   dir_index: usize,
   #[serde(skip)]
   cur_partition: String,
   #[serde(skip)]
-  pub cur_partition_meta: PartitionMeta
+  pub cur_partition_meta: PartitionMeta,
+  // First row of the current partition written with a timestamp before `to_ts`
+  #[serde(skip)]
+  ooo_row_index: Option<usize>,
+  // First row of the current partition whose zones and symbol indexes need rebuilding
+  #[serde(skip)]
+  index_from: usize,
+  // Earliest timestamp written since the last flush
+  #[serde(skip)]
+  dirty_from: Option<i64>,
+  // Rows and symbols written since the last flush
+  #[serde(skip)]
+  wal: Option<BufWriter<File>>,
+  #[serde(skip)]
+  wal_row: Vec<u8>,
+  #[serde(skip)]
+  wal_replaying: bool,
+  // Held while this process is the table's writer
+  #[serde(skip)]
+  lock: Option<File>
 }
 
 pub fn get_home_path() -> PathBuf {
   PathBuf::from(env::var("ZDB_HOME").unwrap_or(String::from("")))
 }
 
-fn get_data_path(name: &str) -> PathBuf {
+pub(crate) fn get_data_path(name: &str) -> PathBuf {
   let mut path = get_home_path();
   path.push("data");
   path.push(name);
@@ -90,53 +155,68 @@ fn get_meta_path(data_path: &PathBuf) -> PathBuf {
 }
 
 impl Table {
-  pub fn create(schema: Schema) -> std::io::Result<Table> {
+  pub fn create(schema: Schema) -> Result<Table, Error> {
+    for column in &schema.columns {
+      check_codec(column)?;
+    }
     let data_path = get_data_path(&schema.name);
-    create_dir_all(&data_path).unwrap_or_else(|_| panic!("Cannot create dir {:?}", data_path));
+    create_dir_all(&data_path).with_path(&data_path)?;
     let meta_path = get_meta_path(&data_path);
 
     if meta_path.exists() {
-      return Err(Error::new(
-        ErrorKind::Other,
-        format!(
-          "Table {name:?} already exists. Try Table::open({name:?}) instead",
-          name = schema.name
-        )
-      ));
+      return Err(Error::TableExists(schema.name));
     }
 
-    let table = Table {
+    let mut table = Table {
       columns: Vec::new(),
-      column_symbols: read_column_symbols(&data_path, &schema),
+      column_symbols: read_column_symbols(&data_path, &schema)?,
       schema,
+      version: 0,
       dir_index: 0,
       column_index: 0,
       partition_meta: HashMap::new(),
+      derived: Vec::new(),
       cur_partition: String::new(),
       cur_partition_meta: PartitionMeta::default(),
+      ooo_row_index: None,
+      index_from: 0,
+      dirty_from: None,
+      wal: None,
+      wal_row: Vec::new(),
+      wal_replaying: false,
+      lock: None,
       meta_path
     };
+    table.lock()?;
     table.write_meta()?;
+    if table.schema.aggregate.is_some() {
+      table.backfill_aggregate()?;
+    }
 
     Ok(table)
   }
 
-  pub fn open<'b>(name: &'b str) -> std::io::Result<Table> {
+  pub fn open<'b>(name: &'b str) -> Result<Table, Error> {
     let data_path = get_data_path(&name);
     let meta_path = get_meta_path(&data_path);
-    let mut res = read_meta(&meta_path)?;
-    res.column_symbols = read_column_symbols(&data_path, &res.schema);
+    let mut res = read_meta(&meta_path, &name)?;
+    res.column_symbols = read_column_symbols(&data_path, &res.schema)?;
     res.meta_path = meta_path;
     res.schema.name = String::from(name);
+    res.recover()?;
 
     Ok(res)
   }
 
-  pub fn create_or_open(schema: Schema) -> std::io::Result<Table> {
+  pub fn create_or_open(schema: Schema) -> Result<Table, Error> {
     let name = schema.name.clone();
     match Self::create(schema) {
-      Ok(table) => Ok(table),
-      Err(_) => Self::open(&name)
+      Err(Error::TableExists(_)) => {
+        let mut table = Self::open(&name)?;
+        table.lock()?;
+        Ok(table)
+      }
+      res => res
     }
   }
 }
@@ -0,0 +1,59 @@
+use crate::error::{Error, IoContext};
+use memmap::MmapMut;
+use std::{cmp::max, fs::File, path::PathBuf};
+
+// Bitmap of missing rows for a nullable column. A set bit means the row is null. Files are zero
+// filled as they grow so rows start out present.
+#[derive(Debug)]
+pub struct TableNulls {
+  pub file: File,
+  pub data: MmapMut,
+  pub path: PathBuf
+}
+
+pub fn get_nulls_path(col_path: &PathBuf) -> PathBuf {
+  let mut path = col_path.clone().into_os_string();
+  path.push(".nulls");
+  PathBuf::from(path)
+}
+
+pub fn get_bitmap_len(row_count: usize) -> usize { (row_count + 7) / 8 }
+
+pub fn get_bit(bitmap: &[u8], index: usize) -> bool { bitmap[index / 8] & (1 << (index % 8)) != 0 }
+
+impl TableNulls {
+  pub fn set(&mut self, row_index: usize, is_null: bool) {
+    let byte = &mut self.data[row_index / 8];
+    if is_null {
+      *byte |= 1 << (row_index % 8);
+    } else {
+      *byte &= !(1 << (row_index % 8));
+    }
+  }
+
+  pub fn get(&self, row_index: usize) -> bool { get_bit(&self.data, row_index) }
+
+  fn remap(&mut self, len: usize) -> Result<(), Error> {
+    self.file.set_len(len as u64).with_path(&self.path)?;
+    unsafe {
+      self.data = memmap::MmapOptions::new()
+        .map_mut(&self.file)
+        .with_path(&self.path)?;
+    }
+    Ok(())
+  }
+
+  pub fn reserve(&mut self, row_count: usize) -> Result<(), Error> {
+    let len = get_bitmap_len(row_count);
+    if self.data.len() >= len {
+      return Ok(());
+    }
+    self.remap(max(len, self.data.len() * 2))
+  }
+
+  // Leaves room for one more row like the column files do
+  pub fn flush(&mut self, row_count: usize) -> Result<(), Error> {
+    self.data.flush().with_path(&self.path)?;
+    self.remap(get_bitmap_len(row_count + 1))
+  }
+}
@@ -0,0 +1,328 @@
+use crate::{
+  error::{Error, IoContext},
+  schema::{Column, ColumnType, PartitionBy, Schema},
+  table::{get_field, ColumnBuffer, Table}
+};
+use arrow_array::{cast::AsArray, types::*, Array, ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Schema as ArrowSchema, TimeUnit};
+use parquet::{
+  arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ArrowSchemaConverter, ArrowWriter},
+  basic::Compression,
+  file::properties::WriterProperties,
+  schema::types::SchemaDescriptor
+};
+use std::{
+  convert::TryFrom,
+  fs::{create_dir_all, File},
+  path::{Path, PathBuf}
+};
+
+// Parquet columns that `export_parquet` writes for `schema`. Symbols are dictionary encoded
+// strings, unsigned integers are annotated as such and decimals keep their scale.
+pub fn get_parquet_schema(schema: &Schema) -> Result<SchemaDescriptor, Error> {
+  let fields = schema.columns.iter().map(get_field).collect::<Vec<_>>();
+  Ok(ArrowSchemaConverter::new().convert(&ArrowSchema::new(fields))?)
+}
+
+fn is_string(data_type: &DataType) -> bool {
+  matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+}
+
+// Dictionaries of strings become symbols. Decimals with more than 9 digits might not fit in an
+// i32 so they're stored in an i64.
+fn get_column_type(data_type: &DataType) -> Option<ColumnType> {
+  Some(match data_type {
+    DataType::Timestamp(..) => ColumnType::Timestamp,
+    DataType::Dictionary(key, value) if is_string(value) => match **key {
+      DataType::Int8 | DataType::UInt8 => ColumnType::Symbol8,
+      DataType::Int16 | DataType::UInt16 => ColumnType::Symbol16,
+      _ => ColumnType::Symbol32
+    },
+    DataType::Int8 => ColumnType::I8,
+    DataType::UInt8 => ColumnType::U8,
+    DataType::Int16 => ColumnType::I16,
+    DataType::UInt16 => ColumnType::U16,
+    DataType::Int32 => ColumnType::I32,
+    DataType::UInt32 => ColumnType::U32,
+    DataType::Float32 => ColumnType::F32,
+    DataType::Int64 => ColumnType::I64,
+    DataType::UInt64 => ColumnType::U64,
+    DataType::Float64 => ColumnType::F64,
+    DataType::Utf8 | DataType::LargeUtf8 => ColumnType::String,
+    DataType::Binary | DataType::LargeBinary => ColumnType::Bytes,
+    DataType::Decimal128(precision, scale) if *scale >= 0 => match precision {
+      0..=9 => ColumnType::Decimal32 { scale: *scale as u32 },
+      _ => ColumnType::Decimal64 { scale: *scale as u32 }
+    },
+    _ => return None
+  })
+}
+
+// The first timestamp column becomes the table's timestamp
+fn get_schema(
+  name: &str,
+  file_schema: &ArrowSchema,
+  partition_by: PartitionBy
+) -> Result<Schema, Error> {
+  let mut columns = file_schema
+    .fields()
+    .iter()
+    .map(|field| {
+      let r#type = get_column_type(field.data_type()).ok_or_else(|| {
+        Error::Query(format!(
+          "column {} has unsupported type {}",
+          field.name(),
+          field.data_type()
+        ))
+      })?;
+      let column = Column::new(field.name(), r#type);
+      Ok(if field.is_nullable() { column.with_nulls() } else { column })
+    })
+    .collect::<Result<Vec<_>, Error>>()?;
+  let ts_index = columns
+    .iter()
+    .position(|c| c.r#type == ColumnType::Timestamp)
+    .ok_or_else(|| Error::Query(String::from("file has no timestamp column")))?;
+  let mut ts_column = columns.remove(ts_index);
+  // Rows need a timestamp to pick a partition
+  ts_column.nullable = false;
+  columns.insert(0, ts_column);
+
+  Ok(Schema::new(name).add_cols(columns).partition_by(partition_by))
+}
+
+fn get_strings(array: &dyn Array) -> Option<Vec<String>> {
+  let to_strings = |values: Vec<Option<&str>>| {
+    values.into_iter().map(|v| v.unwrap_or_default().to_owned()).collect::<Vec<_>>()
+  };
+  Some(match array.data_type() {
+    DataType::Utf8 => to_strings(array.as_string::<i32>().iter().collect()),
+    DataType::LargeUtf8 => to_strings(array.as_string::<i64>().iter().collect()),
+    DataType::Dictionary(..) => {
+      let dictionary = array.as_any_dictionary();
+      let values = get_strings(dictionary.values().as_ref())?;
+      // Null keys can be anything
+      let keys = dictionary.normalized_keys();
+      keys.iter().map(|key| values.get(*key).cloned().unwrap_or_default()).collect()
+    }
+    _ => return None
+  })
+}
+
+fn get_bytes(array: &dyn Array) -> Option<Vec<Vec<u8>>> {
+  let to_bytes = |values: Vec<Option<&[u8]>>| {
+    values.into_iter().map(|v| v.unwrap_or_default().to_vec()).collect::<Vec<_>>()
+  };
+  Some(match array.data_type() {
+    DataType::Binary => to_bytes(array.as_binary::<i32>().iter().collect()),
+    DataType::LargeBinary => to_bytes(array.as_binary::<i64>().iter().collect()),
+    _ => return None
+  })
+}
+
+fn get_decimals(column: &Column, array: &dyn Array) -> Result<Vec<i64>, Error> {
+  let array = array.as_primitive::<Decimal128Type>();
+  (0..array.len())
+    .map(|i| {
+      if array.is_null(i) {
+        return Ok(0);
+      }
+      i64::try_from(array.value(i)).map_err(|_| Error::DecimalOverflow {
+        column: column.name.clone(),
+        value:  array.value(i) as i64
+      })
+    })
+    .collect()
+}
+
+// Values of `array` as they're stored in `column`. Null rows are zero or empty.
+fn get_buffer(column: &Column, array: &ArrayRef) -> Result<ColumnBuffer, Error> {
+  let mismatch = || Error::TypeMismatch {
+    column:   column.name.clone(),
+    expected: array.data_type().to_string(),
+    actual:   column.r#type
+  };
+  macro_rules! primitive {
+    ($variant: ident, $type: ty) => {
+      ColumnBuffer::$variant(array.as_primitive::<$type>().values().to_vec())
+    };
+  }
+  Ok(match (column.r#type, array.data_type()) {
+    (ColumnType::Timestamp, DataType::Timestamp(unit, _)) => {
+      let values = match unit {
+        TimeUnit::Second => array.as_primitive::<TimestampSecondType>().values(),
+        TimeUnit::Millisecond => array.as_primitive::<TimestampMillisecondType>().values(),
+        TimeUnit::Microsecond => array.as_primitive::<TimestampMicrosecondType>().values(),
+        TimeUnit::Nanosecond => array.as_primitive::<TimestampNanosecondType>().values()
+      };
+      let scale = match unit {
+        TimeUnit::Second => 1_000_000_000,
+        TimeUnit::Millisecond => 1_000_000,
+        TimeUnit::Microsecond => 1_000,
+        TimeUnit::Nanosecond => 1
+      };
+      ColumnBuffer::Timestamp(values.iter().map(|v| v * scale).collect())
+    }
+    (ColumnType::Symbol8, _) | (ColumnType::Symbol16, _) | (ColumnType::Symbol32, _) => {
+      ColumnBuffer::Symbol(get_strings(array.as_ref()).ok_or_else(mismatch)?)
+    }
+    (ColumnType::String, _) => {
+      ColumnBuffer::String(get_strings(array.as_ref()).ok_or_else(mismatch)?)
+    }
+    (ColumnType::Bytes, _) => ColumnBuffer::Bytes(get_bytes(array.as_ref()).ok_or_else(mismatch)?),
+    (ColumnType::Decimal32 { scale }, DataType::Decimal128(_, file_scale))
+      if scale as i8 == *file_scale =>
+    {
+      let values = get_decimals(column, array.as_ref())?;
+      let values = values.into_iter().map(|v| {
+        i32::try_from(v).map_err(|_| Error::DecimalOverflow {
+          column: column.name.clone(),
+          value:  v
+        })
+      });
+      ColumnBuffer::I32(values.collect::<Result<_, _>>()?)
+    }
+    (ColumnType::Decimal64 { scale }, DataType::Decimal128(_, file_scale))
+      if scale as i8 == *file_scale =>
+    {
+      ColumnBuffer::I64(get_decimals(column, array.as_ref())?)
+    }
+    (ColumnType::I8, DataType::Int8) => primitive!(I8, Int8Type),
+    (ColumnType::U8, DataType::UInt8) => primitive!(U8, UInt8Type),
+    (ColumnType::I16, DataType::Int16) => primitive!(I16, Int16Type),
+    (ColumnType::U16, DataType::UInt16) => primitive!(U16, UInt16Type),
+    (ColumnType::I32, DataType::Int32) => primitive!(I32, Int32Type),
+    (ColumnType::U32, DataType::UInt32) => primitive!(U32, UInt32Type),
+    (ColumnType::F32, DataType::Float32) => primitive!(F32, Float32Type),
+    (ColumnType::I64, DataType::Int64) => primitive!(I64, Int64Type),
+    (ColumnType::U64, DataType::UInt64) => primitive!(U64, UInt64Type),
+    (ColumnType::F64, DataType::Float64) => primitive!(F64, Float64Type),
+    _ => return Err(mismatch())
+  })
+}
+
+impl Table {
+  // Writes rows between `from_ts` and `to_ts` to `<dir>/<partition>.parquet`, one file for each
+  // partition with rows in range. Returns the paths written.
+  pub fn export_parquet(
+    &self,
+    from_ts: i64,
+    to_ts: i64,
+    dir: &Path
+  ) -> Result<Vec<PathBuf>, Error> {
+    create_dir_all(dir).with_path(&dir.to_path_buf())?;
+    let columns = self.schema.columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+    let partitions = self.partition_iter(from_ts, to_ts, columns.clone())?.partitions;
+    let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut res = Vec::new();
+    for partition in partitions {
+      let mut items = self.partition_iter(from_ts, to_ts, columns.clone())?;
+      items.partitions = vec![partition];
+      let batches = items.into_arrow();
+      let schema = batches.schema();
+      let path = dir.join(format!("{}.parquet", partition.0));
+      let mut writer = None;
+      for batch in batches {
+        let batch = batch?;
+        if batch.num_rows() == 0 {
+          continue;
+        }
+        if writer.is_none() {
+          let file = File::create(&path).with_path(&path)?;
+          writer = Some(ArrowWriter::try_new(file, schema.clone(), Some(props.clone()))?);
+        }
+        writer.as_mut().unwrap().write(&batch)?;
+      }
+      if let Some(writer) = writer {
+        writer.close()?;
+        res.push(path);
+      }
+    }
+
+    Ok(res)
+  }
+
+  // Appends the rows of the Parquet file at `path` to table `name`. If the table doesn't exist
+  // it's created from the file's columns and partitioned by `partition_by`. Rows must be in
+  // timestamp order.
+  pub fn import_parquet(
+    name: &str,
+    path: &Path,
+    partition_by: PartitionBy
+  ) -> Result<Table, Error> {
+    let file = File::open(path).with_path(&path.to_path_buf())?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+    let mut table = match Table::open(name) {
+      Ok(table) => table,
+      Err(Error::TableNotFound(_)) => {
+        Table::create(get_schema(name, builder.schema(), partition_by)?)?
+      }
+      Err(err) => return Err(err)
+    };
+    for batch in builder.build()? {
+      table.import_batch(&batch?, path)?;
+    }
+    table.flush()?;
+
+    Ok(table)
+  }
+
+  fn import_batch(&mut self, batch: &RecordBatch, path: &Path) -> Result<(), Error> {
+    let arrays = self
+      .schema
+      .columns
+      .iter()
+      .map(|column| {
+        batch.column_by_name(&column.name).ok_or_else(|| Error::ColumnNotFound {
+          table:  path.display().to_string(),
+          column: column.name.clone()
+        })
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    let buffers = self
+      .schema
+      .columns
+      .iter()
+      .zip(&arrays)
+      .map(|(column, array)| get_buffer(column, array))
+      .collect::<Result<Vec<_>, _>>()?;
+    if arrays.iter().all(|array| array.null_count() == 0) {
+      let data = buffers.iter().map(|b| b.as_column_data()).collect::<Vec<_>>();
+      return self.append_batch(&data);
+    }
+
+    // Nulls have to be written a row at a time
+    for row_index in 0..batch.num_rows() {
+      for (buffer, array) in buffers.iter().zip(&arrays) {
+        if array.is_null(row_index) {
+          self.put_null()?;
+          continue;
+        }
+        let is_decimal = matches!(
+          self.schema.columns[self.column_index].r#type,
+          ColumnType::Decimal32 { .. } | ColumnType::Decimal64 { .. }
+        );
+        match buffer {
+          ColumnBuffer::Timestamp(v) => self.put_timestamp(v[row_index])?,
+          ColumnBuffer::Symbol(v) => self.put_symbol(&v[row_index])?,
+          ColumnBuffer::I8(v) => self.put_i8(v[row_index])?,
+          ColumnBuffer::U8(v) => self.put_u8(v[row_index])?,
+          ColumnBuffer::I16(v) => self.put_i16(v[row_index])?,
+          ColumnBuffer::U16(v) => self.put_u16(v[row_index])?,
+          ColumnBuffer::I32(v) if is_decimal => self.put_decimal(v[row_index] as i64)?,
+          ColumnBuffer::I32(v) => self.put_i32(v[row_index])?,
+          ColumnBuffer::U32(v) => self.put_u32(v[row_index])?,
+          ColumnBuffer::F32(v) => self.put_f32(v[row_index])?,
+          ColumnBuffer::I64(v) if is_decimal => self.put_decimal(v[row_index])?,
+          ColumnBuffer::I64(v) => self.put_i64(v[row_index])?,
+          ColumnBuffer::U64(v) => self.put_u64(v[row_index])?,
+          ColumnBuffer::F64(v) => self.put_f64(v[row_index])?,
+          ColumnBuffer::String(v) => self.put_string(&v[row_index])?,
+          ColumnBuffer::Bytes(v) => self.put_binary(&v[row_index])?
+        }
+      }
+      self.write()?;
+    }
+    Ok(())
+  }
+}
@@ -1,12 +1,20 @@
 use crate::{
+  error::{Error, IoContext},
   schema::{Column, ColumnType, Schema},
-  table::{get_home_path, Table, TableColumn, TableColumnSymbols}
+  table::{
+    get_home_path,
+    codec::{decompress_column, get_blocks_path},
+    heap::{get_heap_len, TableHeap},
+    nulls::{get_bitmap_len, get_nulls_path, TableNulls},
+    PartitionMeta, Table, TableColumn, TableColumnSymbols
+  }
 };
 use fnv::FnvHashMap;
 use memmap::MmapMut;
 use std::{
   fs::{File, OpenOptions},
   io::{BufRead, BufReader, ErrorKind},
+  ops::Range,
   path::PathBuf
 };
 
@@ -31,10 +39,14 @@ pub fn get_capacity(column: &Column) -> usize {
   }
 }
 
-fn get_column_symbols(symbols_path: &PathBuf, column: &Column) -> Vec<String> {
+pub fn is_variable_length(r#type: ColumnType) -> bool {
+  r#type == ColumnType::String || r#type == ColumnType::Bytes
+}
+
+fn get_column_symbols(symbols_path: &PathBuf, column: &Column) -> Result<Vec<String>, Error> {
   let capacity = get_capacity(&column);
   if capacity == 0 {
-    return Vec::new();
+    return Ok(Vec::new());
   }
   let mut symbols = Vec::<String>::with_capacity(capacity);
   let file = OpenOptions::new().read(true).open(&symbols_path);
@@ -42,30 +54,31 @@ fn get_column_symbols(symbols_path: &PathBuf, column: &Column) -> Vec<String> {
     Ok(file) => {
       let f = BufReader::new(&file);
       for line in f.lines() {
-        let my_line = line
-          .unwrap_or_else(|_| panic!("Could not read line from symbol file {:?}", symbols_path));
-        symbols.push(my_line);
+        symbols.push(line.with_path(symbols_path)?);
       }
     }
     Err(error) => {
       if error.kind() != ErrorKind::NotFound {
-        panic!(
-          "Problem opening symbol file {:?}: {:?}",
-          symbols_path, error
-        )
+        return Err(Error::Io {
+          path: symbols_path.clone(),
+          error
+        });
       }
     }
   };
 
-  symbols
+  Ok(symbols)
 }
 
-pub fn read_column_symbols(data_path: &PathBuf, schema: &Schema) -> Vec<TableColumnSymbols> {
+pub fn read_column_symbols(
+  data_path: &PathBuf,
+  schema: &Schema
+) -> Result<Vec<TableColumnSymbols>, Error> {
   let mut res = Vec::new();
 
   for column in &schema.columns {
     let path = get_symbols_path(&data_path, &column);
-    let symbols = get_column_symbols(&path, &column);
+    let symbols = get_column_symbols(&path, &column)?;
     let mut symbol_nums =
       FnvHashMap::with_capacity_and_hasher(get_capacity(&column), Default::default());
     for (i, symbol) in symbols.iter().enumerate() {
@@ -79,7 +92,7 @@ pub fn read_column_symbols(data_path: &PathBuf, schema: &Schema) -> Vec<TableCol
     res.push(col_syms);
   }
 
-  res
+  Ok(res)
 }
 
 pub fn get_col_dir(table_name: &str, partition_dir: &PathBuf, partition: &str) -> PathBuf {
@@ -102,54 +115,135 @@ pub fn get_col_path(
 ) -> PathBuf {
   let mut path = get_col_dir(&table_name, &partition_dir, &partition);
   path.push(&column.name);
-  path.set_extension(String::from(format!("{:?}", column.r#type).to_lowercase()));
+  let extension = match column.r#type {
+    ColumnType::Decimal32 { .. } => String::from("decimal32"),
+    ColumnType::Decimal64 { .. } => String::from("decimal64"),
+    r#type => format!("{:?}", r#type).to_lowercase()
+  };
+  path.set_extension(extension);
   path
 }
 
-fn get_column_data(path: &PathBuf, row_count: usize, column_size: usize) -> (File, MmapMut) {
+fn get_column_data(path: &PathBuf, init_size: usize) -> Result<(File, MmapMut), Error> {
   let file = OpenOptions::new()
     .read(true)
     .write(true)
     .create(true)
     .open(&path)
-    .unwrap_or_else(|_| panic!("Unable to open file {:?}", path));
+    .with_path(path)?;
 
-  let init_size = row_count * column_size;
-  file
-    .set_len(init_size as u64)
-    .unwrap_or_else(|_| panic!("Could not truncate {:?} to {}", path, init_size));
+  file.set_len(init_size as u64).with_path(path)?;
+  unsafe {
+    let data = memmap::MmapOptions::new().map_mut(&file).with_path(path)?;
+
+    Ok((file, data))
+  }
+}
+
+// A private copy-on-write mapping of exactly `size` bytes. The file is opened read-only and never
+// resized, so scans work on read-only mounts and can't clobber rows a writer is appending.
+pub fn get_column_data_read_only(path: &PathBuf, size: usize) -> Result<(File, MmapMut), Error> {
+  let file = OpenOptions::new().read(true).open(&path).with_path(path)?;
+
+  let file_size = file.metadata().with_path(path)?.len();
+  if file_size < size as u64 {
+    return Err(Error::CorruptMeta {
+      path:   path.clone(),
+      reason: format!("expected at least {} bytes but file has {}", size, file_size)
+    });
+  }
   unsafe {
     let data = memmap::MmapOptions::new()
-      .map_mut(&file)
-      .unwrap_or_else(|_| panic!("Could not mmapp {:?}", path));
+      .len(size)
+      .map_copy(&file)
+      .with_path(path)?;
 
-    (file, data)
+    Ok((file, data))
   }
 }
 
 impl Table {
+  // Only `rows` are guaranteed to be read from sealed columns
+  pub fn open_column_read_only(
+    meta: &PartitionMeta,
+    table_name: &str,
+    partition: &str,
+    column: &Column,
+    rows: Range<usize>
+  ) -> Result<TableColumn, Error> {
+    let row_count = meta.row_count;
+    let path = get_col_path(&meta.dir, &table_name, &partition, &column);
+    let (file, data) = if meta.sealed && get_blocks_path(&path).exists() {
+      decompress_column(&path, column, row_count, rows)?
+    } else {
+      get_column_data_read_only(&path, row_count * column.size)?
+    };
+    let nulls = if column.nullable {
+      let path = get_nulls_path(&path);
+      let (file, data) = get_column_data_read_only(&path, get_bitmap_len(row_count))?;
+      Some(TableNulls { file, data, path })
+    } else {
+      None
+    };
+    let heap = if is_variable_length(column.r#type) {
+      Some(TableHeap::open_read_only(&path, get_heap_len(&data, row_count))?)
+    } else {
+      None
+    };
+
+    Ok(TableColumn {
+      name: column.name.clone(),
+      file,
+      data,
+      path,
+      r#type: column.r#type.clone(),
+      size: column.size,
+      resolution: column.resolution,
+      nulls,
+      heap
+    })
+  }
+
   pub fn open_column(
     partition_dir: &PathBuf,
     table_name: &str,
     partition: &str,
     row_count: usize,
     column: &Column
-  ) -> TableColumn {
+  ) -> Result<TableColumn, Error> {
     let path = get_col_path(&partition_dir, &table_name, &partition, &column);
-    let (file, data) = get_column_data(&path, row_count, column.size);
+    let (file, data) = get_column_data(&path, row_count * column.size)?;
+    let nulls = if column.nullable {
+      let path = get_nulls_path(&path);
+      let (file, data) = get_column_data(&path, get_bitmap_len(row_count))?;
+      Some(TableNulls { file, data, path })
+    } else {
+      None
+    };
+    let heap = if is_variable_length(column.r#type) {
+      Some(TableHeap::open(&path, 0)?)
+    } else {
+      None
+    };
 
-    TableColumn {
+    Ok(TableColumn {
       name: column.name.clone(),
       file,
       data,
       path,
       r#type: column.r#type.clone(),
       size: column.size,
-      resolution: column.resolution
-    }
+      resolution: column.resolution,
+      nulls,
+      heap
+    })
   }
 
-  pub fn open_columns(&self, partition_dir: &PathBuf, extra_row_count: usize) -> Vec<TableColumn> {
+  pub fn open_columns(
+    &self,
+    partition_dir: &PathBuf,
+    extra_row_count: usize
+  ) -> Result<Vec<TableColumn>, Error> {
     let row_count = match self.partition_meta.get(&self.cur_partition) {
       Some(meta) => meta.row_count,
       None => 0
@@ -168,6 +262,6 @@ impl Table {
           column
         )
       })
-      .collect::<Vec<_>>()
+      .collect::<Result<Vec<_>, _>>()
   }
 }
@@ -0,0 +1,332 @@
+use crate::{
+  calendar::us_equity::get_session,
+  error::Error,
+  schema::{Column, ColumnType},
+  table::{index::is_symbol, scan::PartitionColumn, Table}
+};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::{cmp::min, collections::HashMap, ops::Range};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Alignment {
+  // Bars start at multiples of the duration since the epoch
+  Epoch,
+  // Bars start at multiples of the duration since the session open. Rows outside of regular US
+  // equity sessions are dropped and bars don't span sessions.
+  UsEquity
+}
+
+// Which columns to read and how to align bars. Defaults to resampling a bars table with "sym",
+// "open", "high", "low", "close" and "volume" columns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Resample {
+  pub duration:  i64,
+  pub alignment: Alignment,
+  pub symbol:    String,
+  // Trades tables have a price column instead of open, high, low and close
+  pub price:     Option<String>,
+  pub volume:    String,
+  // All symbols if empty
+  pub symbols:   Vec<String>
+}
+
+impl Resample {
+  pub fn new(duration: i64) -> Self {
+    Self {
+      duration,
+      alignment: Alignment::Epoch,
+      symbol: String::from("sym"),
+      price: None,
+      volume: String::from("volume"),
+      symbols: Vec::new()
+    }
+  }
+
+  pub fn align(mut self, alignment: Alignment) -> Self {
+    self.alignment = alignment;
+    self
+  }
+
+  pub fn symbol_column(mut self, column: &str) -> Self {
+    self.symbol = column.to_owned();
+    self
+  }
+
+  // Resample trades using `price` for open, high, low and close and summing `size` for volume
+  pub fn trades(mut self, price: &str, size: &str) -> Self {
+    self.price = Some(price.to_owned());
+    self.volume = size.to_owned();
+    self
+  }
+
+  pub fn symbols(mut self, symbols: &[&str]) -> Self {
+    self.symbols = symbols.iter().map(|s| s.to_string()).collect();
+    self
+  }
+
+  fn get_columns(&self) -> Vec<&str> {
+    let mut res = vec![self.symbol.as_str()];
+    match &self.price {
+      Some(price) => res.push(price),
+      None => res.extend(&["open", "high", "low", "close"])
+    }
+    res.push(&self.volume);
+    res
+  }
+}
+
+// Parses durations like "1s", "5m", "1h", "1d" or "5minutes" into nanoseconds
+pub fn parse_duration(duration: &str) -> Result<i64, Error> {
+  let unit_start = duration
+    .find(|c: char| !c.is_ascii_digit())
+    .unwrap_or_else(|| duration.len());
+  let (count, unit) = duration.split_at(unit_start);
+  let count = if count.is_empty() { Ok(1) } else { count.parse::<i64>() };
+  let unit = match unit.to_lowercase().as_str() {
+    "ns" | "nanosecond" | "nanoseconds" => Some(1),
+    "us" | "microsecond" | "microseconds" => Some(1_000),
+    "ms" | "millisecond" | "milliseconds" => Some(1_000_000),
+    "s" | "second" | "seconds" => Some(1_000_000_000),
+    "m" | "minute" | "minutes" => Some(60 * 1_000_000_000),
+    "h" | "hour" | "hours" => Some(60 * 60 * 1_000_000_000),
+    "d" | "day" | "days" => Some(24 * 60 * 60 * 1_000_000_000),
+    _ => None
+  };
+  match (count, unit) {
+    (Ok(count), Some(unit)) if count > 0 => Ok(count * unit),
+    _ => Err(Error::Query(format!(
+      "Could not parse duration {} like 1s, 5m, 1h or 1d",
+      duration
+    )))
+  }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Bars {
+  pub t: Vec<i64>,
+  pub o: Vec<f64>,
+  pub h: Vec<f64>,
+  pub l: Vec<f64>,
+  pub c: Vec<f64>,
+  pub v: Vec<u64>
+}
+
+impl Bars {
+  fn push(&mut self, ts: i64, open: f64, high: f64, low: f64, close: f64, volume: u64) {
+    match self.t.last() {
+      Some(last_ts) if *last_ts == ts => {
+        let i = self.t.len() - 1;
+        self.h[i] = self.h[i].max(high);
+        self.l[i] = self.l[i].min(low);
+        self.c[i] = close;
+        self.v[i] += volume;
+      }
+      _ => {
+        self.t.push(ts);
+        self.o.push(open);
+        self.h.push(high);
+        self.l.push(low);
+        self.c.push(close);
+        self.v.push(volume);
+      }
+    }
+  }
+
+  // Appends later `bars`, merging a bar that spans both
+  fn extend(&mut self, bars: Bars) {
+    for i in 0..bars.t.len() {
+      self.push(bars.t[i], bars.o[i], bars.h[i], bars.l[i], bars.c[i], bars.v[i]);
+    }
+  }
+}
+
+pub(super) fn check_type(column: &Column, expected: &str, ok: bool) -> Result<(), Error> {
+  if ok {
+    Ok(())
+  } else {
+    Err(Error::TypeMismatch {
+      column:   column.name.clone(),
+      expected: String::from(expected),
+      actual:   column.r#type
+    })
+  }
+}
+
+pub(super) fn is_price(r#type: ColumnType) -> bool {
+  matches!(
+    r#type,
+    ColumnType::F32 | ColumnType::F64 | ColumnType::Decimal32 { .. } | ColumnType::Decimal64 { .. }
+  )
+}
+
+pub(super) fn is_volume(r#type: ColumnType) -> bool {
+  matches!(
+    r#type,
+    ColumnType::U8 | ColumnType::U16 | ColumnType::U32 | ColumnType::U64
+  )
+}
+
+fn get_price(column: &PartitionColumn, row_index: usize) -> f64 {
+  match column.column.r#type {
+    ColumnType::F32 => column.get_f32()[row_index] as f64,
+    ColumnType::F64 => column.get_f64()[row_index],
+    _ => column.get_decimal(row_index) as f64 / 10_f64.powi(column.get_scale() as i32)
+  }
+}
+
+fn get_volume(column: &PartitionColumn, row_index: usize) -> u64 {
+  if column.is_null(row_index) {
+    return 0;
+  }
+  match column.column.r#type {
+    ColumnType::U64 => column.get_u64()[row_index],
+    ColumnType::U32 => column.get_u32()[row_index] as u64,
+    ColumnType::U16 => column.get_u16()[row_index] as u64,
+    _ => column.get_u8()[row_index] as u64
+  }
+}
+
+// Finds the bar a timestamp falls in
+struct Aligner {
+  duration:  i64,
+  alignment: Alignment,
+  // The last session looked up
+  session:   Option<Range<i64>>
+}
+
+impl Aligner {
+  fn get_bar_ts(&mut self, ts: i64) -> Option<i64> {
+    match self.alignment {
+      Alignment::Epoch => Some(ts - ts.rem_euclid(self.duration)),
+      Alignment::UsEquity => {
+        if !self.session.as_ref().map_or(false, |session| session.contains(&ts)) {
+          // Sessions are during the day in UTC
+          let seconds = ts.div_euclid(1_000_000_000);
+          let date = DateTime::from_timestamp(seconds, 0).unwrap().date_naive();
+          self.session = get_session(&date).filter(|session| session.contains(&ts));
+        }
+        let open = self.session.as_ref()?.start;
+        Some(open + (ts - open) / self.duration * self.duration)
+      }
+    }
+  }
+}
+
+impl Table {
+  // OHLCV bars of `resample.duration` for each symbol with rows between `from_ts` and `to_ts`.
+  // Partitions are resampled in parallel. Rows with a null price are skipped.
+  pub fn resample(
+    &self,
+    from_ts: i64,
+    to_ts: i64,
+    resample: &Resample
+  ) -> Result<HashMap<String, Bars>, Error> {
+    if resample.duration <= 0 {
+      return Err(Error::Query(format!(
+        "duration must be positive but is {}",
+        resample.duration
+      )));
+    }
+    let mut columns = vec![self.schema.columns[0].name.as_str()];
+    columns.extend(resample.get_columns());
+    let mut partitions = self.partition_iter(from_ts, to_ts, columns.clone())?;
+    let get_column = |name: &str| self.schema.columns.iter().find(|c| c.name == name).unwrap();
+    let symbol = get_column(&resample.symbol);
+    check_type(symbol, "a Symbol", is_symbol(symbol.r#type))?;
+    for name in &columns[2..columns.len() - 1] {
+      let price = get_column(name);
+      check_type(price, "F32, F64 or Decimal", is_price(price.r#type))?;
+    }
+    let volume = get_column(&resample.volume);
+    check_type(volume, "unsigned integer", is_volume(volume.r#type))?;
+    if !resample.symbols.is_empty() {
+      let symbols = resample.symbols.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+      partitions = partitions.filter_symbols(&resample.symbol, &symbols)?;
+    }
+
+    let res = partitions.par_scan(
+      |partition| {
+        let mut aligner = Aligner {
+          duration:  resample.duration,
+          alignment: resample.alignment,
+          session:   None
+        };
+        let mut res = HashMap::<String, Bars>::new();
+        let volume = &partition[partition.len() - 1];
+        let prices = &partition[2..partition.len() - 1];
+        for row_index in 0..partition[0].row_count {
+          if prices.iter().any(|price| price.is_null(row_index)) {
+            continue;
+          }
+          let bar_ts = match aligner.get_bar_ts(partition[0].get_timestamp(row_index)) {
+            Some(bar_ts) => bar_ts,
+            None => continue
+          };
+          let symbol = partition[1].get_symbol(row_index);
+          let bars = match res.get_mut(symbol) {
+            Some(bars) => bars,
+            None => res.entry(symbol.to_owned()).or_default()
+          };
+          let price = |i: usize| get_price(&prices[min(i, prices.len() - 1)], row_index);
+          bars.push(
+            bar_ts,
+            price(0),
+            price(1),
+            price(2),
+            price(3),
+            get_volume(volume, row_index)
+          );
+        }
+        res
+      },
+      |mut a, b| {
+        for (symbol, bars) in b {
+          match a.get_mut(&symbol) {
+            Some(a_bars) => a_bars.extend(bars),
+            None => {
+              a.insert(symbol, bars);
+            }
+          }
+        }
+        a
+      }
+    )?;
+
+    Ok(res.unwrap_or_default())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn durations() {
+    assert_eq!(parse_duration("1s").unwrap(), 1_000_000_000);
+    assert_eq!(parse_duration("5m").unwrap(), 5 * 60 * 1_000_000_000);
+    assert_eq!(parse_duration("5Minutes").unwrap(), 5 * 60 * 1_000_000_000);
+    assert_eq!(parse_duration("1h").unwrap(), 60 * 60 * 1_000_000_000);
+    assert_eq!(parse_duration("day").unwrap(), 24 * 60 * 60 * 1_000_000_000);
+    assert!(parse_duration("0s").is_err());
+    assert!(parse_duration("5x").is_err());
+    assert!(parse_duration("").is_err());
+  }
+
+  #[test]
+  fn merge_bars() {
+    let mut a = Bars::default();
+    a.push(0, 1.0, 2.0, 0.5, 1.5, 10);
+    a.push(60, 1.5, 1.5, 1.0, 1.0, 5);
+    let mut b = Bars::default();
+    b.push(60, 1.1, 3.0, 1.1, 2.0, 1);
+    b.push(120, 2.0, 2.0, 2.0, 2.0, 1);
+    a.extend(b);
+    assert_eq!(a.t, vec![0, 60, 120]);
+    assert_eq!(a.o, vec![1.0, 1.5, 2.0]);
+    assert_eq!(a.h, vec![2.0, 3.0, 2.0]);
+    assert_eq!(a.l, vec![0.5, 1.0, 2.0]);
+    assert_eq!(a.c, vec![1.5, 2.0, 2.0]);
+    assert_eq!(a.v, vec![10, 6, 1]);
+  }
+}
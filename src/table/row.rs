@@ -0,0 +1,99 @@
+use crate::{
+  error::Error,
+  schema::{Column, Schema},
+  table::{
+    scan::{PartitionColumn, PartitionIterator},
+    Table
+  }
+};
+use std::marker::PhantomData;
+
+// Usually implemented with #[derive(ZdbRow)]
+pub trait ZdbRow: Sized {
+  fn columns() -> Vec<Column>;
+
+  fn put(&self, table: &mut Table) -> Result<(), Error>;
+
+  // `columns` are in the same order as `Self::columns()`
+  fn get(columns: &[PartitionColumn], row_index: usize) -> Self;
+
+  fn schema(name: &str) -> Schema { Schema::new(name).add_cols(Self::columns()) }
+}
+
+// One partition's worth of rows
+#[derive(Debug)]
+pub struct Rows<'a, R> {
+  pub columns: Vec<PartitionColumn<'a>>,
+  row:         PhantomData<R>
+}
+
+impl<'a, R: ZdbRow> Rows<'a, R> {
+  pub fn len(&self) -> usize { self.columns.first().map(|c| c.row_count).unwrap_or(0) }
+
+  pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+  pub fn get(&self, row_index: usize) -> Option<R> {
+    if row_index >= self.len() {
+      return None;
+    }
+    Some(R::get(&self.columns, row_index))
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = R> + '_ {
+    (0..self.len()).map(move |i| R::get(&self.columns, i))
+  }
+}
+
+#[derive(Debug)]
+pub struct RowIterator<'a, R> {
+  partitions: PartitionIterator<'a>,
+  row:        PhantomData<R>
+}
+
+impl<'a, R: ZdbRow> Iterator for RowIterator<'a, R> {
+  type Item = Result<Rows<'a, R>, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let columns = self.partitions.next()?;
+    Some(columns.map(|columns| Rows {
+      columns,
+      row: PhantomData
+    }))
+  }
+}
+
+impl Table {
+  pub fn insert<R: ZdbRow>(&mut self, row: &R) -> Result<(), Error> {
+    row.put(self)?;
+    self.write()
+  }
+
+  /* Inclusive of from and to */
+  pub fn row_iter<R: ZdbRow>(&self, from_ts: i64, to_ts: i64) -> Result<RowIterator<'_, R>, Error> {
+    let columns = R::columns();
+    for column in &columns {
+      let table_column = self
+        .schema
+        .columns
+        .iter()
+        .find(|c| c.name == column.name)
+        .ok_or_else(|| Error::ColumnNotFound {
+          table:  self.schema.name.clone(),
+          column: column.name.clone()
+        })?;
+      if table_column.r#type != column.r#type {
+        return Err(Error::TypeMismatch {
+          column:   column.name.clone(),
+          expected: format!("{:?}", column.r#type),
+          actual:   table_column.r#type
+        });
+      }
+    }
+    let names = columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+
+    Ok(RowIterator {
+      partitions: self.partition_iter(from_ts, to_ts, names)?,
+      row:        PhantomData
+    })
+  }
+}
@@ -1,8 +1,28 @@
 use crate::{
+  error::{Error, IoContext},
   schema::{Column, ColumnType},
-  table::{PartitionMeta, Table, TableColumn}
+  table::{
+    filter::{union, Predicate},
+    get_col_path,
+    heap::{get_heap_len, get_range, TableHeap},
+    index::{index_rows, read_index, SymbolIndex},
+    nulls::{get_bitmap_len, TableNulls},
+    zones::{get_matching_rows, has_zones, read_zones},
+    Compare, Filter, PartitionMeta, Table, TableColumn
+  }
+};
+use memmap::MmapMut;
+use std::{
+  cmp::{max, min},
+  fmt::Debug,
+  ops::Range,
+  slice::from_raw_parts_mut,
+  sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Mutex
+  },
+  thread
 };
-use std::{cmp::max, fmt::Debug, slice::from_raw_parts_mut};
 
 pub trait FormatCurrency {
   fn format_currency(self, sig_figs: usize) -> String;
@@ -33,14 +53,30 @@ impl FormatCurrency for f32 {
   }
 }
 
+// Exact decimal string for a value stored with `scale` digits after the point
+pub fn format_decimal(val: i64, scale: u32) -> String {
+  if scale == 0 {
+    return val.to_string();
+  }
+  let divisor = 10_u64.pow(scale);
+  let abs = val.unsigned_abs();
+  format!(
+    "{}{}.{:0width$}",
+    if val < 0 { "-" } else { "" },
+    abs / divisor,
+    abs % divisor,
+    width = scale as usize
+  )
+}
+
 #[derive(Debug)]
-struct TableColumnMeta<'a> {
-  column:  Column,
-  symbols: &'a Vec<String>
+pub(super) struct TableColumnMeta<'a> {
+  pub(super) column:  Column,
+  pub(super) symbols: &'a Vec<String>
 }
 
 impl Table {
-  fn get_union<'a>(&'a self, columns: &Vec<&str>) -> Vec<TableColumnMeta<'a>> {
+  fn get_union<'a>(&'a self, columns: &Vec<&str>) -> Result<Vec<TableColumnMeta<'a>>, Error> {
     columns
       .iter()
       .map(|col_name| {
@@ -49,18 +85,28 @@ impl Table {
           .columns
           .iter()
           .position(|col| &col.name == col_name)
-          .unwrap_or_else(|| panic!("Column {} does not exist", col_name));
-        TableColumnMeta {
+          .ok_or_else(|| Error::ColumnNotFound {
+            table:  self.schema.name.clone(),
+            column: col_name.to_string()
+          })?;
+        Ok(TableColumnMeta {
           column:  self.schema.columns[index].clone(),
           symbols: &self.column_symbols[index].symbols
-        }
+        })
       })
-      .collect::<Vec<_>>()
+      .collect::<Result<Vec<_>, _>>()
   }
 
   /* Inclusive of from and to */
-  pub fn partition_iter(&self, from_ts: i64, to_ts: i64, columns: Vec<&str>) -> PartitionIterator {
-    assert!(to_ts >= from_ts);
+  pub fn partition_iter(
+    &self,
+    from_ts: i64,
+    to_ts: i64,
+    columns: Vec<&str>
+  ) -> Result<PartitionIterator, Error> {
+    if to_ts < from_ts {
+      return Err(Error::OutOfOrder { from_ts, to_ts });
+    }
     let mut partitions = self
       .partition_meta
       .iter()
@@ -76,15 +122,37 @@ impl Table {
     partitions.sort_by_key(|(_partition_dir, partition_meta)| partition_meta.from_ts);
     let ts_column = self.schema.columns[0].clone();
 
-    PartitionIterator {
+    Ok(PartitionIterator {
       from_ts,
       to_ts,
       ts_column,
-      columns: self.get_union(&columns),
+      columns: self.get_union(&columns)?,
       partitions,
       partition_index: 0,
-      table_name: self.schema.name.clone()
-    }
+      table_name: self.schema.name.clone(),
+      table: self,
+      block_filters: Vec::new(),
+      filter_columns: Vec::new(),
+      predicates: Vec::new(),
+      rows: Vec::new()
+    })
+  }
+
+  // Scans partitions in parallel. See PartitionIterator::par_scan.
+  pub fn par_scan<'a, T, M, R>(
+    &'a self,
+    from_ts: i64,
+    to_ts: i64,
+    columns: Vec<&str>,
+    map: M,
+    reduce: R
+  ) -> Result<Option<T>, Error>
+  where
+    T: Send,
+    M: Fn(Vec<PartitionColumn<'a>>) -> T + Sync,
+    R: Fn(T, T) -> T + Sync
+  {
+    self.partition_iter(from_ts, to_ts, columns)?.par_scan(map, reduce)
   }
 }
 
@@ -94,7 +162,9 @@ pub struct PartitionColumn<'a> {
   pub slice:     &'a mut [u8],
   pub symbols:   &'a Vec<String>,
   pub meta:      &'a PartitionMeta,
-  pub row_count: usize
+  pub row_count: usize,
+  // Index of this slice's first row in the partition. 0 if rows were gathered from several ranges.
+  pub start_row: usize
 }
 
 macro_rules! get_partition_slice {
@@ -131,17 +201,80 @@ impl<'a> PartitionColumn<'_> {
 
   pub fn get_symbol(&self, row_index: usize) -> &str {
     match self.column.r#type {
-      ColumnType::Symbol8 => &self.symbols[self.get_u8()[row_index] as usize],
-      ColumnType::Symbol16 => &self.symbols[self.get_u16()[row_index] as usize],
-      ColumnType::Symbol32 => &self.symbols[self.get_u32()[row_index] as usize],
+      // Symbol ids start at 1
+      ColumnType::Symbol8 => &self.symbols[self.get_u8()[row_index] as usize - 1],
+      ColumnType::Symbol16 => &self.symbols[self.get_u16()[row_index] as usize - 1],
+      ColumnType::Symbol32 => &self.symbols[self.get_u32()[row_index] as usize - 1],
+      ctype => panic!("ColumnType {:?} is not a Symbol", ctype)
+    }
+  }
+
+  pub fn get_symbol_id(&self, row_index: usize) -> usize {
+    match self.column.r#type {
+      ColumnType::Symbol8 => self.get_u8()[row_index] as usize,
+      ColumnType::Symbol16 => self.get_u16()[row_index] as usize,
+      ColumnType::Symbol32 => self.get_u32()[row_index] as usize,
       ctype => panic!("ColumnType {:?} is not a Symbol", ctype)
     }
   }
 
+  pub fn get_scale(&self) -> u32 {
+    match self.column.r#type {
+      ColumnType::Decimal32 { scale } | ColumnType::Decimal64 { scale } => scale,
+      ctype => panic!("ColumnType {:?} is not a Decimal", ctype)
+    }
+  }
+
+  // The scaled integer
+  pub fn get_decimal(&self, row_index: usize) -> i64 {
+    match self.column.r#type {
+      ColumnType::Decimal32 { .. } => self.get_i32()[row_index] as i64,
+      ColumnType::Decimal64 { .. } => self.get_i64()[row_index],
+      ctype => panic!("ColumnType {:?} is not a Decimal", ctype)
+    }
+  }
+
+  pub fn format_decimal(&self, row_index: usize) -> String {
+    format_decimal(self.get_decimal(row_index), self.get_scale())
+  }
+
+  // For String and Bytes columns
+  pub fn get_binary(&self, row_index: usize) -> &[u8] {
+    match &self.column.heap {
+      Some(heap) => &heap.data[get_range(&self.column.data, self.start_row + row_index)],
+      None => panic!("ColumnType {:?} is not variable length", self.column.r#type)
+    }
+  }
+
+  pub fn get_string(&self, row_index: usize) -> &str {
+    std::str::from_utf8(self.get_binary(row_index)).expect("String column has invalid UTF-8")
+  }
+
+  // Offset into the heap that this slice's first value starts at
+  pub fn get_heap_start(&self) -> usize { get_heap_len(&self.column.data, self.start_row) }
+
+  pub fn is_nullable(&self) -> bool { self.column.nulls.is_some() }
+
+  pub fn is_null(&self, row_index: usize) -> bool {
+    match &self.column.nulls {
+      Some(nulls) => nulls.get(self.start_row + row_index),
+      None => false
+    }
+  }
+
+  // The whole partition's bitmap. Row `i` of this slice is bit `start_row + i`.
+  pub fn get_null_bitmap(&self) -> Option<&[u8]> {
+    self.column.nulls.as_ref().map(|nulls| &nulls.data[..])
+  }
+
+  pub fn get_null_mask(&self) -> Vec<bool> { (0..self.row_count).map(|i| self.is_null(i)).collect() }
+
+  pub fn null_count(&self) -> usize { (0..self.row_count).filter(|i| self.is_null(*i)).count() }
+
   pub fn to_timestamp(&self, v: i64) -> i64 {
     match self.column.size {
       8 => v,
-      4 | 2 => v * self.column.resolution + self.meta.min_ts,
+      4 | 2 | 1 => v * self.column.resolution + self.meta.min_ts,
       csize => panic!("Size {:?} is not a supported Timestamp size", csize)
     }
   }
@@ -155,6 +288,7 @@ impl<'a> PartitionColumn<'_> {
       8 => self.get_i64()[row_index],
       4 => self.to_timestamp(self.get_u32()[row_index] as i64),
       2 => self.to_timestamp(self.get_u16()[row_index] as i64),
+      1 => self.to_timestamp(self.get_u8()[row_index] as i64),
       csize => panic!("Size {:?} is not a supported Timestamp size", csize)
     }
   }
@@ -165,10 +299,19 @@ pub struct PartitionIterator<'a> {
   from_ts: i64,
   to_ts: i64,
   ts_column: Column,
-  columns: Vec<TableColumnMeta<'a>>,
+  pub(super) columns: Vec<TableColumnMeta<'a>>,
   table_name: String,
   pub partitions: Vec<(&'a String, &'a PartitionMeta)>,
-  partition_index: usize
+  partition_index: usize,
+  table: &'a Table,
+  // Blocks whose zones can't match one of these are skipped
+  block_filters: Vec<(Column, Compare, f64)>,
+  // Columns the predicates test
+  filter_columns: Vec<Column>,
+  // Only rows matching all of these are yielded
+  predicates: Vec<Predicate>,
+  // Row ranges of the last partition left to yield in reverse order
+  rows: Vec<Range<usize>>
 }
 
 macro_rules! binary_search_seek {
@@ -212,51 +355,298 @@ fn find_ts(ts_column: &TableColumn, ts: i64, seek_start: bool) -> usize {
   }
 }
 
-impl<'a> Iterator for PartitionIterator<'a> {
-  type Item = Vec<PartitionColumn<'a>>;
+// Copies `rows` of `column` into anonymous maps so they're contiguous
+fn gather(column: TableColumn, rows: &[Range<usize>]) -> Result<TableColumn, Error> {
+  let row_count = rows.iter().map(|range| range.len()).sum::<usize>();
+  let size = column.size;
+  let mut data = MmapMut::map_anon(max(row_count * size, 1)).with_path(&column.path)?;
+  let heap = match &column.heap {
+    Some(heap) => {
+      let ranges = rows
+        .iter()
+        .flat_map(|range| range.clone())
+        .map(|row_index| get_range(&column.data, row_index))
+        .collect::<Vec<_>>();
+      let len = ranges.iter().map(|range| range.len()).sum::<usize>();
+      let mut heap_data = MmapMut::map_anon(max(len, 1)).with_path(&heap.path)?;
+      let mut end = 0;
+      for (dest_index, range) in ranges.into_iter().enumerate() {
+        let value = &heap.data[range];
+        heap_data[end..end + value.len()].copy_from_slice(value);
+        end += value.len();
+        data[dest_index * size..(dest_index + 1) * size]
+          .copy_from_slice(&(end as u64).to_le_bytes());
+      }
+      Some(heap_data)
+    }
+    None => {
+      let mut dest = 0;
+      for range in rows {
+        let bytes = &column.data[range.start * size..range.end * size];
+        data[dest..dest + bytes.len()].copy_from_slice(bytes);
+        dest += bytes.len();
+      }
+      None
+    }
+  };
+  let nulls = match &column.nulls {
+    Some(nulls) => {
+      let bitmap_len = max(get_bitmap_len(row_count), 1);
+      let mut bitmap = MmapMut::map_anon(bitmap_len).with_path(&nulls.path)?;
+      let rows = rows.iter().flat_map(|range| range.clone());
+      for (dest_index, row_index) in rows.enumerate() {
+        if nulls.get(row_index) {
+          bitmap[dest_index / 8] |= 1 << (dest_index % 8);
+        }
+      }
+      Some(bitmap)
+    }
+    None => None
+  };
 
-  fn next(&mut self) -> Option<Self::Item> {
-    if self.partition_index == self.partitions.len() {
-      return None;
+  Ok(TableColumn {
+    data,
+    nulls: column.nulls.zip(nulls).map(|(nulls, data)| TableNulls { data, ..nulls }),
+    heap: column.heap.zip(heap).map(|(heap, data)| TableHeap { data, ..heap }),
+    ..column
+  })
+}
+
+// Rows in both sorted lists of ranges
+fn intersect(a: &[Range<usize>], b: &[Range<usize>]) -> Vec<Range<usize>> {
+  let mut res = Vec::new();
+  let mut i = 0;
+  let mut j = 0;
+  while i < a.len() && j < b.len() {
+    let start = max(a[i].start, b[j].start);
+    let end = min(a[i].end, b[j].end);
+    if start < end {
+      res.push(start..end);
+    }
+    if a[i].end < b[j].end {
+      i += 1;
+    } else {
+      j += 1;
+    }
+  }
+  res
+}
+
+impl<'a> PartitionIterator<'a> {
+  // Skips 64KB blocks of `column` with no values `v` where `v op value`. Yields each run of
+  // remaining blocks separately so partitions may be split up.
+  pub fn filter_blocks(mut self, column: &str, op: Compare, value: f64) -> Result<Self, Error> {
+    let column = &self.table.schema.columns[self.get_column_index(column)?];
+    if !has_zones(column.r#type) {
+      return Err(Error::TypeMismatch {
+        column:   column.name.clone(),
+        expected: String::from("a number"),
+        actual:   column.r#type
+      });
     }
-    let (partition_dir, partition_meta) = self.partitions.get(self.partition_index)?;
-    let start_row = if self.partition_index == 0 {
-      let ts_column = Table::open_column(
-        &partition_meta.dir,
+    self.block_filters.push((column.clone(), op, value));
+    Ok(self)
+  }
+
+  fn get_column_index(&self, column: &str) -> Result<usize, Error> {
+    self
+      .table
+      .schema
+      .columns
+      .iter()
+      .position(|c| c.name == column)
+      .ok_or_else(|| Error::ColumnNotFound {
+        table:  self.table_name.clone(),
+        column: column.to_owned()
+      })
+  }
+
+  // Only yields rows matching `filter`. Zone maps and symbol indexes narrow down which rows are
+  // read and tested. Matching rows are gathered into one contiguous item per partition.
+  pub fn filter(mut self, filter: Filter) -> Result<Self, Error> {
+    let predicate = Predicate::new(self.table, &filter, &mut self.filter_columns)?;
+    self.predicates.push(predicate);
+    Ok(self)
+  }
+
+  // Only yields rows whose `column` is one of `symbols`
+  pub fn filter_symbols(self, column: &str, symbols: &[&str]) -> Result<Self, Error> {
+    self.filter(Filter::is_in(column, symbols))
+  }
+
+  // Sorted row ranges of a partition's `column` that hold one of `symbol_ids`
+  fn get_symbol_rows(
+    &self,
+    partition_dir: &str,
+    partition_meta: &PartitionMeta,
+    column: &Column,
+    symbol_ids: &[usize]
+  ) -> Result<Vec<Range<usize>>, Error> {
+    let row_count = partition_meta.row_count;
+    let path = get_col_path(&partition_meta.dir, &self.table_name, partition_dir, column);
+    let index = match read_index(&path, row_count)? {
+      Some(index) => index,
+      None => {
+        // Missing or stale indexes are built on the fly
+        let table_column = Table::open_column_read_only(
+          partition_meta,
+          &self.table_name,
+          partition_dir,
+          column,
+          0..row_count
+        )?;
+        let mut index = SymbolIndex::default();
+        index_rows(&table_column, 0..row_count, &mut index);
+        index
+      }
+    };
+    let mut res = symbol_ids
+      .iter()
+      .filter_map(|symbol_id| index.get(symbol_id))
+      .flatten()
+      .cloned()
+      .collect::<Vec<_>>();
+    res.sort_unstable_by_key(|range| range.start);
+    Ok(res)
+  }
+
+  // Rows of the partition at `partition_index` to yield
+  fn get_rows(&self, partition_index: usize) -> Result<Vec<Range<usize>>, Error> {
+    let (partition_dir, partition_meta) = self.partitions[partition_index];
+    let start_row = if partition_index == 0 {
+      let ts_column = Table::open_column_read_only(
+        partition_meta,
         &self.table_name,
         &partition_dir,
-        partition_meta.row_count,
-        &self.ts_column
-      );
+        &self.ts_column,
+        0..partition_meta.row_count
+      )?;
       let needle = if ts_column.resolution == 1 { self.from_ts } else { self.from_ts - partition_meta.min_ts };
       find_ts(&ts_column, needle, true)
     } else {
       0
     };
-    let end_row = if self.partition_index == self.partitions.len() - 1 {
-      let ts_column = Table::open_column(
-        &partition_meta.dir,
+    let end_row = if partition_index == self.partitions.len() - 1 {
+      let ts_column = Table::open_column_read_only(
+        partition_meta,
         &self.table_name,
         &partition_dir,
-        partition_meta.row_count,
-        &self.ts_column
-      );
+        &self.ts_column,
+        0..partition_meta.row_count
+      )?;
       let needle = if ts_column.resolution == 1 { self.to_ts } else { self.to_ts - partition_meta.min_ts };
       find_ts(&ts_column, needle, false)
     } else {
       partition_meta.row_count
     };
+    let mut rows = vec![start_row..end_row];
+    for (column, op, value) in &self.block_filters {
+      let path = get_col_path(&partition_meta.dir, &self.table_name, partition_dir, column);
+      // Missing or stale zones can't skip anything
+      if let Some(zones) = read_zones(&path, partition_meta.row_count)? {
+        let matching = get_matching_rows(column, &zones, *op, *value, partition_meta.row_count);
+        rows = intersect(&rows, &matching);
+      }
+    }
+    for predicate in &self.predicates {
+      rows = self.get_candidate_rows(partition_dir, partition_meta, predicate, &rows)?;
+    }
+    if self.predicates.iter().all(Predicate::is_exact) || rows.is_empty() {
+      return Ok(rows);
+    }
+    let span = rows[0].start..rows[rows.len() - 1].end;
+    let columns = self
+      .filter_columns
+      .iter()
+      .map(|column| {
+        Table::open_column_read_only(
+          partition_meta,
+          &self.table_name,
+          partition_dir,
+          column,
+          span.clone()
+        )
+      })
+      .collect::<Result<Vec<_>, _>>()?;
+    for predicate in &self.predicates {
+      rows = predicate.select(&columns, &rows, partition_meta.min_ts);
+    }
+    Ok(rows)
+  }
+
+  // Rows of `rows` that `predicate` may match according to zone maps and symbol indexes
+  fn get_candidate_rows(
+    &self,
+    partition_dir: &str,
+    partition_meta: &PartitionMeta,
+    predicate: &Predicate,
+    rows: &[Range<usize>]
+  ) -> Result<Vec<Range<usize>>, Error> {
+    let row_count = partition_meta.row_count;
+    Ok(match predicate {
+      Predicate::Compare(index, op, value) => {
+        let column = &self.filter_columns[*index];
+        let path = get_col_path(&partition_meta.dir, &self.table_name, partition_dir, column);
+        match read_zones(&path, row_count)? {
+          Some(zones) => {
+            intersect(rows, &get_matching_rows(column, &zones, *op, *value, row_count))
+          }
+          None => rows.to_vec()
+        }
+      }
+      Predicate::In(index, symbol_ids) => {
+        let column = &self.filter_columns[*index];
+        let symbol_rows = self.get_symbol_rows(partition_dir, partition_meta, column, symbol_ids)?;
+        intersect(rows, &symbol_rows)
+      }
+      Predicate::And(predicates) => {
+        let mut res = rows.to_vec();
+        for predicate in predicates {
+          res = self.get_candidate_rows(partition_dir, partition_meta, predicate, &res)?;
+        }
+        res
+      }
+      Predicate::Or(predicates) => {
+        let mut res = Vec::new();
+        for predicate in predicates {
+          let rows = self.get_candidate_rows(partition_dir, partition_meta, predicate, rows)?;
+          res = union(&res, &rows);
+        }
+        res
+      }
+    })
+  }
+
+  // Yields `rows` as is if they're one range and gathers them otherwise
+  fn read_rows(
+    &self,
+    partition_dir: &str,
+    partition_meta: &'a PartitionMeta,
+    rows: &[Range<usize>]
+  ) -> Result<Vec<PartitionColumn<'a>>, Error> {
+    let (start_row, end_row) = match rows {
+      [] => (0, 0),
+      [range] => (range.start, range.end),
+      _ => (0, rows.iter().map(|range| range.len()).sum())
+    };
+    let span = match rows {
+      [] => 0..0,
+      _ => rows[0].start..rows[rows.len() - 1].end
+    };
     let data_columns = self
       .columns
       .iter()
       .map(|column| {
-        let table_column = Table::open_column(
-          &partition_meta.dir,
+        let mut table_column = Table::open_column_read_only(
+          partition_meta,
           &self.table_name,
           &partition_dir,
-          partition_meta.row_count,
-          &column.column
-        );
+          &column.column,
+          span.clone()
+        )?;
+        if rows.len() > 1 {
+          table_column = gather(table_column, rows)?;
+        }
         let slice = unsafe {
           from_raw_parts_mut(
             table_column
@@ -267,18 +657,107 @@ impl<'a> Iterator for PartitionIterator<'a> {
           )
         };
 
-        PartitionColumn {
+        Ok(PartitionColumn {
           slice,
           column: table_column,
           symbols: column.symbols,
           meta: partition_meta,
-          row_count: end_row - start_row
-        }
+          row_count: end_row - start_row,
+          start_row
+        })
       })
-      .collect::<Vec<_>>();
+      .collect::<Result<Vec<_>, _>>();
+
+    data_columns
+  }
+}
+
+impl<'a> Iterator for PartitionIterator<'a> {
+  type Item = Result<Vec<PartitionColumn<'a>>, Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    while self.rows.is_empty() {
+      self.partitions.get(self.partition_index)?;
+      let rows = self.get_rows(self.partition_index);
+      self.partition_index += 1;
+      match rows {
+        Ok(rows) => self.rows = rows.into_iter().rev().collect(),
+        Err(e) => return Some(Err(e))
+      }
+    }
+    let (partition_dir, partition_meta) = self.partitions[self.partition_index - 1];
+    let rows = if self.predicates.is_empty() {
+      vec![self.rows.pop().unwrap()]
+    } else {
+      self.rows.drain(..).rev().collect()
+    };
+    Some(self.read_rows(partition_dir, partition_meta, &rows))
+  }
+}
 
-    self.partition_index += 1;
-    return Some(data_columns);
+impl<'a> PartitionIterator<'a> {
+  // Calls `map` on the same items as iterating would across a thread per core and merges the
+  // results with `reduce` in partition order. Returns None if there are no items.
+  pub fn par_scan<T, M, R>(self, map: M, reduce: R) -> Result<Option<T>, Error>
+  where
+    T: Send,
+    M: Fn(Vec<PartitionColumn<'a>>) -> T + Sync,
+    R: Fn(T, T) -> T + Sync
+  {
+    let num_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let num_threads = min(num_threads, self.partitions.len());
+    let next_partition = AtomicUsize::new(self.partition_index);
+    let failed = AtomicBool::new(false);
+    let results = Mutex::new(Vec::new());
+    let scan_partition = |partition_index: usize| -> Result<Option<T>, Error> {
+      let (partition_dir, partition_meta) = self.partitions[partition_index];
+      let rows = self.get_rows(partition_index)?;
+      let items = if self.predicates.is_empty() {
+        rows.into_iter().map(|range| vec![range]).collect::<Vec<_>>()
+      } else if rows.is_empty() {
+        Vec::new()
+      } else {
+        vec![rows]
+      };
+      let mut res = None;
+      for rows in items {
+        let item = map(self.read_rows(partition_dir, partition_meta, &rows)?);
+        res = Some(match res {
+          Some(res) => reduce(res, item),
+          None => item
+        });
+      }
+      Ok(res)
+    };
+    thread::scope(|scope| {
+      for _ in 0..num_threads {
+        scope.spawn(|| {
+          while !failed.load(Ordering::Relaxed) {
+            let partition_index = next_partition.fetch_add(1, Ordering::Relaxed);
+            if partition_index >= self.partitions.len() {
+              break;
+            }
+            let res = scan_partition(partition_index);
+            if res.is_err() {
+              failed.store(true, Ordering::Relaxed);
+            }
+            results.lock().unwrap().push((partition_index, res));
+          }
+        });
+      }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_unstable_by_key(|(partition_index, _res)| *partition_index);
+    let mut res = None;
+    for (_partition_index, partition_res) in results {
+      if let Some(partition_res) = partition_res? {
+        res = Some(match res {
+          Some(res) => reduce(res, partition_res),
+          None => partition_res
+        });
+      }
+    }
+    Ok(res)
   }
 }
 
@@ -288,6 +767,26 @@ mod tests {
   struct TestColumn<'a> {
     data: &'a [i64]
   }
+  #[test]
+  fn test_intersect() {
+    use super::intersect;
+    assert_eq!(
+      intersect(&[0..10, 20..30], &[5..25, 28..40]),
+      vec![5..10, 20..25, 28..30]
+    );
+    assert!(intersect(&[0..10], &[10..20]).is_empty());
+    assert!(intersect(&[], &[0..10]).is_empty());
+  }
+
+  #[test]
+  fn test_format_decimal() {
+    use super::format_decimal;
+    assert_eq!(format_decimal(12345, 2), "123.45");
+    assert_eq!(format_decimal(-5, 3), "-0.005");
+    assert_eq!(format_decimal(100, 0), "100");
+    assert_eq!(format_decimal(i64::MIN, 4), "-922337203685477.5808");
+  }
+
   #[test]
   fn test_binary_search_seek() {
     let data = TestColumn {
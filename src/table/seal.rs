@@ -0,0 +1,86 @@
+use crate::{
+  error::{Error, IoContext},
+  schema::{Codec, Column},
+  table::{
+    codec::{compress_column, decompress_column, get_blocks_path},
+    get_col_path,
+    read::get_column_data_read_only,
+    Table
+  }
+};
+use std::{
+  fs::{remove_file, OpenOptions},
+  io::Write
+};
+
+impl Table {
+  fn get_codec_columns(&self) -> Vec<Column> {
+    self
+      .schema
+      .columns
+      .iter()
+      .filter(|column| column.codec != Codec::None)
+      .cloned()
+      .collect()
+  }
+
+  // Compresses columns that have a codec in every partition that ends before `to_ts`. Sealed
+  // partitions are unsealed if they're written to again.
+  pub fn seal(&mut self, to_ts: i64) -> Result<(), Error> {
+    self.close_partition()?;
+    let columns = self.get_codec_columns();
+    let partitions = self
+      .partition_meta
+      .iter()
+      .filter(|(_partition, meta)| !meta.sealed && meta.max_ts < to_ts)
+      .map(|(partition, meta)| (partition.clone(), meta.clone()))
+      .collect::<Vec<_>>();
+
+    let mut raw_paths = Vec::new();
+    for (partition, meta) in &partitions {
+      for column in &columns {
+        let path = get_col_path(&meta.dir, &self.schema.name, partition, column);
+        let (_file, data) = get_column_data_read_only(&path, meta.row_count * column.size)?;
+        compress_column(&path, column, &data, meta.row_count)?;
+        raw_paths.push(path);
+      }
+      self.partition_meta.get_mut(partition).unwrap().sealed = true;
+    }
+    if partitions.is_empty() {
+      return Ok(());
+    }
+    // Readers only use .blocks files once _meta says the partition is sealed
+    self.write_meta()?;
+    for path in raw_paths {
+      remove_file(&path).with_path(&path)?;
+    }
+    Ok(())
+  }
+
+  // Writes the current partition's compressed columns back out raw so they can be appended to.
+  // Readers keep using a column's .blocks file until it's removed.
+  pub(super) fn unseal(&mut self) -> Result<(), Error> {
+    let meta = &self.cur_partition_meta;
+    for column in self.get_codec_columns() {
+      let path = get_col_path(&meta.dir, &self.schema.name, &self.cur_partition, &column);
+      let blocks_path = get_blocks_path(&path);
+      if !blocks_path.exists() {
+        continue;
+      }
+      let (_file, data) = decompress_column(&path, &column, meta.row_count, 0..meta.row_count)?;
+      let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .with_path(&path)?;
+      file
+        .write_all(&data[..meta.row_count * column.size])
+        .and_then(|_| file.sync_all())
+        .with_path(&path)?;
+      remove_file(&blocks_path).with_path(&blocks_path)?;
+    }
+    self.cur_partition_meta.sealed = false;
+    Ok(())
+  }
+}
@@ -0,0 +1,478 @@
+use crate::{
+  error::{Error, IoContext},
+  schema::ColumnType,
+  table::{read::is_variable_length, ColumnBuffer, Table}
+};
+use std::{
+  convert::TryInto,
+  mem::size_of,
+  fs::{read, File, OpenOptions},
+  io::{BufWriter, Write},
+  path::PathBuf
+};
+
+// Each entry is a 1 byte tag, a 4 byte payload length and then the payload.
+// A batch starts with BEGIN (committed row count) and is applied once COMMIT is synced.
+const BEGIN: u8 = 0;
+const SYMBOL: u8 = 1;
+const ROW: u8 = 2;
+const COMMIT: u8 = 3;
+// Row count and then each column's rows
+const BATCH: u8 = 4;
+
+pub fn get_wal_path(meta_path: &PathBuf) -> PathBuf { meta_path.with_file_name("_wal") }
+
+fn decode<T: Copy>(bytes: &[u8]) -> Vec<T> {
+  bytes
+    .chunks_exact(size_of::<T>())
+    .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const T) })
+    .collect::<Vec<_>>()
+}
+
+fn get_entry_row_count(tag: u8, payload: &[u8]) -> u64 {
+  match tag {
+    ROW => 1,
+    BATCH => u32::from_le_bytes(payload[..4].try_into().unwrap()) as u64,
+    _ => 0
+  }
+}
+
+fn read_entries(data: &[u8]) -> Vec<(u8, &[u8])> {
+  let mut res = Vec::new();
+  let mut offset = 0;
+  while offset + 5 <= data.len() {
+    let tag = data[offset];
+    let len = u32::from_le_bytes(data[offset + 1..offset + 5].try_into().unwrap()) as usize;
+    offset += 5;
+    // Torn write from a crash
+    if offset + len > data.len() {
+      break;
+    }
+    res.push((tag, &data[offset..offset + len]));
+    offset += len;
+  }
+
+  res
+}
+
+impl Table {
+  fn get_row_count(&self) -> u64 {
+    self
+      .partition_meta
+      .values()
+      .map(|partition_meta| partition_meta.row_count as u64)
+      .sum::<u64>()
+  }
+
+  fn wal_append(&mut self, tag: u8, payload: &[u8]) -> Result<(), Error> {
+    if self.wal_replaying {
+      return Ok(());
+    }
+    if self.wal.is_none() {
+      let path = get_wal_path(&self.meta_path);
+      let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .with_path(&path)?;
+      self.wal = Some(BufWriter::new(file));
+      let row_count = self.get_row_count();
+      self.wal_append(BEGIN, &row_count.to_le_bytes())?;
+    }
+    let wal = self.wal.as_mut().unwrap();
+    wal
+      .write_all(&[tag])
+      .and_then(|_| wal.write_all(&(payload.len() as u32).to_le_bytes()))
+      .and_then(|_| wal.write_all(payload))
+      .with_path(&get_wal_path(&self.meta_path))
+  }
+
+  // Values of nullable columns are prefixed with a byte that's 1 if the value is null
+  pub(super) fn wal_put(&mut self, bytes: &[u8]) {
+    if !self.wal_replaying {
+      if self.schema.columns[self.column_index].nullable {
+        self.wal_row.push(0);
+      }
+      self.wal_row.extend_from_slice(bytes);
+    }
+  }
+
+  pub(super) fn wal_put_null(&mut self) {
+    if !self.wal_replaying {
+      self.wal_row.push(1);
+    }
+  }
+
+  pub(super) fn wal_add_symbol(&mut self, column_index: usize, symbol: &str) -> Result<(), Error> {
+    let mut payload = (column_index as u32).to_le_bytes().to_vec();
+    payload.extend_from_slice(symbol.as_bytes());
+    self.wal_append(SYMBOL, &payload)
+  }
+
+  pub(super) fn wal_write_row(&mut self) -> Result<(), Error> {
+    let row = std::mem::take(&mut self.wal_row);
+    let res = self.wal_append(ROW, &row);
+    self.wal_row = row;
+    self.wal_row.clear();
+    res
+  }
+
+  pub(super) fn wal_write_batch(&mut self, payload: &[u8]) -> Result<(), Error> {
+    self.wal_append(BATCH, payload)
+  }
+
+  pub(super) fn wal_commit(&mut self) -> Result<(), Error> {
+    if self.wal.is_none() {
+      return Ok(());
+    }
+    self.wal_append(COMMIT, &[])?;
+    let wal = self.wal.as_mut().unwrap();
+    wal
+      .flush()
+      .and_then(|_| wal.get_ref().sync_data())
+      .with_path(&get_wal_path(&self.meta_path))
+  }
+
+  pub(super) fn wal_truncate(&mut self) -> Result<(), Error> {
+    let path = get_wal_path(&self.meta_path);
+    self.wal = None;
+    if path.exists() {
+      File::create(&path).with_path(&path)?;
+    }
+    Ok(())
+  }
+
+  fn replay_row(&mut self, row: &[u8]) -> Result<(), Error> {
+    let mut offset = 0;
+    for i in 0..self.schema.columns.len() {
+      let column = &self.schema.columns[i];
+      if column.nullable {
+        offset += 1;
+        if row[offset - 1] == 1 {
+          self.put_null()?;
+          continue;
+        }
+      }
+      if is_variable_length(column.r#type) {
+        let len = u32::from_le_bytes(row[offset..offset + 4].try_into().unwrap()) as usize;
+        let bytes = &row[offset + 4..offset + 4 + len];
+        offset += 4 + len;
+        match column.r#type {
+          ColumnType::String => self.put_string(&self.decode_string(bytes.to_vec())?)?,
+          _ => self.put_binary(bytes)?
+        }
+        continue;
+      }
+      let size = match column.r#type {
+        ColumnType::Timestamp => 8,
+        ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32 => 4,
+        _ => column.size
+      };
+      let bytes = &row[offset..offset + size];
+      offset += size;
+      match column.r#type {
+        ColumnType::Timestamp => {
+          self.put_timestamp(i64::from_le_bytes(bytes.try_into().unwrap()))?
+        }
+        ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32 => {
+          let index = u32::from_le_bytes(bytes.try_into().unwrap()) as usize;
+          let symbol = self.column_symbols[i].symbols[index - 1].clone();
+          self.put_symbol(symbol)?;
+        }
+        ColumnType::I8 => self.put_i8(i8::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::U8 => self.put_u8(bytes[0])?,
+        ColumnType::I16 => self.put_i16(i16::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::U16 => self.put_u16(u16::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::I32 => self.put_i32(i32::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::U32 => self.put_u32(u32::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::F32 => self.put_f32(f32::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::I64 => self.put_i64(i64::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::U64 => self.put_u64(u64::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::F64 => self.put_f64(f64::from_le_bytes(bytes.try_into().unwrap()))?,
+        ColumnType::Decimal32 { .. } => {
+          self.put_decimal(i32::from_le_bytes(bytes.try_into().unwrap()) as i64)?
+        }
+        ColumnType::Decimal64 { .. } => {
+          self.put_decimal(i64::from_le_bytes(bytes.try_into().unwrap()))?
+        }
+        ColumnType::String | ColumnType::Bytes => unreachable!()
+      }
+    }
+    self.write()
+  }
+
+  fn replay_batch(&mut self, payload: &[u8]) -> Result<(), Error> {
+    let row_count = u32::from_le_bytes(payload[..4].try_into().unwrap()) as usize;
+    let mut offset = 4;
+    let mut buffers = Vec::with_capacity(self.schema.columns.len());
+    for i in 0..self.schema.columns.len() {
+      let column = &self.schema.columns[i];
+      if is_variable_length(column.r#type) {
+        let mut values = Vec::with_capacity(row_count);
+        for _ in 0..row_count {
+          let len = u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap()) as usize;
+          values.push(payload[offset + 4..offset + 4 + len].to_vec());
+          offset += 4 + len;
+        }
+        buffers.push(match column.r#type {
+          ColumnType::String => ColumnBuffer::String(
+            values
+              .into_iter()
+              .map(|value| self.decode_string(value))
+              .collect::<Result<Vec<_>, _>>()?
+          ),
+          _ => ColumnBuffer::Bytes(values)
+        });
+        continue;
+      }
+      let size = match column.r#type {
+        ColumnType::Timestamp => 8,
+        ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32 => 4,
+        _ => column.size
+      };
+      let bytes = &payload[offset..offset + size * row_count];
+      offset += size * row_count;
+      buffers.push(match column.r#type {
+        ColumnType::Timestamp => ColumnBuffer::Timestamp(decode(bytes)),
+        ColumnType::Symbol8 | ColumnType::Symbol16 | ColumnType::Symbol32 => ColumnBuffer::Symbol(
+          decode::<u32>(bytes)
+            .iter()
+            .map(|index| self.column_symbols[i].symbols[*index as usize - 1].clone())
+            .collect::<Vec<_>>()
+        ),
+        ColumnType::I8 => ColumnBuffer::I8(decode(bytes)),
+        ColumnType::U8 => ColumnBuffer::U8(bytes.to_vec()),
+        ColumnType::I16 => ColumnBuffer::I16(decode(bytes)),
+        ColumnType::U16 => ColumnBuffer::U16(decode(bytes)),
+        ColumnType::I32 => ColumnBuffer::I32(decode(bytes)),
+        ColumnType::U32 => ColumnBuffer::U32(decode(bytes)),
+        ColumnType::F32 => ColumnBuffer::F32(decode(bytes)),
+        ColumnType::I64 => ColumnBuffer::I64(decode(bytes)),
+        ColumnType::U64 => ColumnBuffer::U64(decode(bytes)),
+        ColumnType::F64 => ColumnBuffer::F64(decode(bytes)),
+        ColumnType::Decimal32 { .. } => ColumnBuffer::I32(decode(bytes)),
+        ColumnType::Decimal64 { .. } => ColumnBuffer::I64(decode(bytes)),
+        ColumnType::String | ColumnType::Bytes => unreachable!()
+      });
+    }
+    let batch = buffers
+      .iter()
+      .map(|buffer| buffer.as_column_data())
+      .collect::<Vec<_>>();
+
+    self.append_batch(&batch)
+  }
+
+  fn decode_string(&self, bytes: Vec<u8>) -> Result<String, Error> {
+    String::from_utf8(bytes).map_err(|_| Error::CorruptMeta {
+      path:   get_wal_path(&self.meta_path),
+      reason: String::from("invalid UTF-8 in String column")
+    })
+  }
+
+  fn replay_symbol(&mut self, payload: &[u8]) -> Result<(), Error> {
+    let column_index = u32::from_le_bytes(payload[..4].try_into().unwrap()) as usize;
+    let symbol = String::from_utf8(payload[4..].to_vec()).map_err(|_| Error::CorruptMeta {
+      path:   get_wal_path(&self.meta_path),
+      reason: format!("invalid symbol for column {}", column_index)
+    })?;
+    let column_symbols = &mut self.column_symbols[column_index];
+    // .symbols may have been written before the crash
+    if !column_symbols.symbol_nums.contains_key(&symbol) {
+      column_symbols.symbols.push(symbol.clone());
+      column_symbols
+        .symbol_nums
+        .insert(symbol, column_symbols.symbols.len());
+    }
+    Ok(())
+  }
+
+  // Brings the table to the state of the last synced COMMIT. Uncommitted rows are dropped.
+  pub(super) fn replay_wal(&mut self) -> Result<(), Error> {
+    let path = get_wal_path(&self.meta_path);
+    let data = match read(&path) {
+      Ok(data) => data,
+      Err(_) => return Ok(())
+    };
+    if data.is_empty() {
+      return Ok(());
+    }
+    let entries = read_entries(&data);
+    let is_committed = entries.last().map(|(tag, _)| *tag) == Some(COMMIT);
+    if is_committed && entries[0].0 == BEGIN {
+      let begin_row_count = u64::from_le_bytes(entries[0].1.try_into().unwrap());
+      let wal_row_count = entries
+        .iter()
+        .map(|(tag, payload)| get_entry_row_count(*tag, payload))
+        .sum::<u64>();
+      let row_count = self.get_row_count();
+      if row_count == begin_row_count {
+        // Crashed before _meta was written
+        self.wal_replaying = true;
+        for (tag, payload) in &entries[1..] {
+          match *tag {
+            SYMBOL => self.replay_symbol(payload)?,
+            ROW => self.replay_row(payload)?,
+            BATCH => self.replay_batch(payload)?,
+            _ => {}
+          }
+        }
+        self.flush()?;
+        self.wal_replaying = false;
+      } else if row_count != begin_row_count + wal_row_count {
+        return Err(Error::CorruptMeta {
+          path,
+          reason: format!(
+            "expected {} or {} rows but _meta has {}",
+            begin_row_count,
+            begin_row_count + wal_row_count,
+            row_count
+          )
+        });
+      }
+    }
+    self.wal_truncate()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    schema::{Column, ColumnType, PartitionBy, Schema},
+    table::{get_data_path, ColumnData, Table}
+  };
+  use std::fs::remove_dir_all;
+
+  fn write_rows(table: &mut Table, from_ts: i64, row_count: i64) {
+    for ts in from_ts..from_ts + row_count {
+      table.put_timestamp(ts).unwrap();
+      table.put_symbol(format!("S{}", ts % 3)).unwrap();
+      table.put_u32(ts as u32).unwrap();
+      table.write().unwrap();
+    }
+  }
+
+  fn get_sum(table: &Table) -> (usize, u32) {
+    let mut res = (0, 0);
+    for partition in table.partition_iter(0, i64::MAX, vec!["ts", "volume"]).unwrap() {
+      let partition = partition.unwrap();
+      res.0 += partition[1].row_count;
+      res.1 += partition[1].get_u32().iter().sum::<u32>();
+    }
+    res
+  }
+
+  #[test]
+  fn replay_wal() {
+    let name = "wal_test";
+    let _ = remove_dir_all(get_data_path(name));
+    let schema = Schema::new(name)
+      .add_cols(vec![
+        Column::new("ts", ColumnType::Timestamp),
+        Column::new("sym", ColumnType::Symbol8),
+        Column::new("volume", ColumnType::U32),
+      ])
+      .partition_by(PartitionBy::Day);
+    let mut table = Table::create(schema).unwrap();
+    write_rows(&mut table, 0, 10);
+    table.flush().unwrap();
+
+    // Crash before commit
+    write_rows(&mut table, 10, 10);
+    drop(table);
+    let mut table = Table::open(name).unwrap();
+    assert_eq!(get_sum(&table), (10, 45));
+
+    // Crash after commit but before _meta is written
+    write_rows(&mut table, 10, 10);
+    table.wal_commit().unwrap();
+    drop(table);
+    let mut table = Table::open(name).unwrap();
+    assert_eq!(get_sum(&table), (20, 190));
+    assert_eq!(table.column_symbols[1].symbols, vec!["S0", "S1", "S2"]);
+
+    // Batches
+    let symbols = vec![String::from("S3"), String::from("S0")];
+    table
+      .append_batch(&[
+        ColumnData::Timestamp(&[20, 21]),
+        ColumnData::Symbol(&symbols),
+        ColumnData::U32(&[20, 21]),
+      ])
+      .unwrap();
+    table.wal_commit().unwrap();
+    drop(table);
+    let table = Table::open(name).unwrap();
+    assert_eq!(get_sum(&table), (22, 231));
+    assert_eq!(table.column_symbols[1].symbols, vec!["S0", "S1", "S2", "S3"]);
+
+    // Replaying is idempotent
+    let table = Table::open(name).unwrap();
+    assert_eq!(get_sum(&table), (22, 231));
+  }
+
+  #[test]
+  fn replay_nulls() {
+    let name = "wal_nulls_test";
+    let _ = remove_dir_all(get_data_path(name));
+    let schema = Schema::new(name)
+      .add_cols(vec![
+        Column::new("ts", ColumnType::Timestamp),
+        Column::new("volume", ColumnType::U32).with_nulls(),
+      ])
+      .partition_by(PartitionBy::Day);
+    let mut table = Table::create(schema).unwrap();
+    for ts in 0..10 {
+      table.put_timestamp(ts).unwrap();
+      if ts % 2 == 0 {
+        table.put_null().unwrap();
+      } else {
+        table.put_u32(ts as u32).unwrap();
+      }
+      table.write().unwrap();
+    }
+    table.wal_commit().unwrap();
+    drop(table);
+
+    let table = Table::open(name).unwrap();
+    assert_eq!(get_sum(&table), (10, 25));
+    for partition in table.partition_iter(0, i64::MAX, vec!["volume"]).unwrap() {
+      let partition = partition.unwrap();
+      assert_eq!(partition[0].get_null_mask(), (0..10).map(|i| i % 2 == 0).collect::<Vec<_>>());
+    }
+  }
+
+  #[test]
+  fn replay_strings() {
+    let name = "wal_strings_test";
+    let _ = remove_dir_all(get_data_path(name));
+    let schema = Schema::new(name)
+      .add_cols(vec![
+        Column::new("ts", ColumnType::Timestamp),
+        Column::new("id", ColumnType::String).with_nulls(),
+      ])
+      .partition_by(PartitionBy::Day);
+    let mut table = Table::create(schema).unwrap();
+    table.put_timestamp(0).unwrap();
+    table.put_string("a").unwrap();
+    table.write().unwrap();
+    table.put_timestamp(1).unwrap();
+    table.put_null().unwrap();
+    table.write().unwrap();
+    let ids = vec![String::from("bc"), String::new()];
+    table
+      .append_batch(&[ColumnData::Timestamp(&[2, 3]), ColumnData::String(&ids)])
+      .unwrap();
+    table.wal_commit().unwrap();
+    drop(table);
+
+    let table = Table::open(name).unwrap();
+    for partition in table.partition_iter(0, i64::MAX, vec!["id"]).unwrap() {
+      let partition = partition.unwrap();
+      let ids = (0..4).map(|i| partition[0].get_string(i)).collect::<Vec<_>>();
+      assert_eq!(ids, vec!["a", "", "bc", ""]);
+      assert_eq!(partition[0].get_null_mask(), vec![false, true, false, false]);
+    }
+  }
+}
@@ -1,25 +1,69 @@
 use crate::{
   calendar::ToNaiveDateTime,
+  error::{Error, IoContext},
   schema::{ColumnType, PartitionBy},
-  table::{get_col_dir, Table}
+  table::{
+    get_col_dir,
+    heap::{get_heap_len, get_range},
+    read::{get_capacity, is_variable_length},
+    Table, TableColumn
+  }
 };
-use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, MAX_DATETIME, MIN_DATETIME};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
 use memmap;
 use std::{
-  fs::{create_dir_all, OpenOptions},
+  cmp::{max, min},
+  convert::TryInto,
+  fs::{create_dir_all, rename, OpenOptions},
   io::Write
 };
 
 use super::PartitionMeta;
 
+// Timestamps are stored relative to the partition's min_ts, so this is only good for ordering
+fn get_stored_ts(column: &TableColumn, row_index: usize) -> i64 {
+  let offset = row_index * column.size;
+  let bytes = &column.data[offset..offset + column.size];
+  match column.size {
+    8 => i64::from_le_bytes(bytes.try_into().unwrap()),
+    4 => u32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+    2 => u16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+    1 => bytes[0] as i64,
+    s => panic!("Invalid column size {}", s)
+  }
+}
+
 impl Table {
+  fn check_type<F>(&self, is_type: F, expected: &str) -> Result<(), Error>
+  where
+    F: Fn(ColumnType) -> bool
+  {
+    match self.schema.columns.get(self.column_index) {
+      Some(column) if is_type(column.r#type) => Ok(()),
+      Some(column) => Err(Error::TypeMismatch {
+        column:   column.name.clone(),
+        expected: expected.to_owned(),
+        actual:   column.r#type
+      }),
+      None => Err(Error::ColumnNotFound {
+        table:  self.schema.name.clone(),
+        column: format!("#{}", self.column_index)
+      })
+    }
+  }
+
   // TODO: Use const generics once stable.
   // https://github.com/rust-lang/rust/issues/44580
   fn put_bytes(&mut self, bytes: &[u8]) {
     let size = bytes.len();
     let offset = self.cur_partition_meta.row_count * size;
     // println!("put {} bytes {} {}", size, self.column_index, self.cur_partition_meta.row_count);
-    self.columns[self.column_index].data[offset..offset + size].copy_from_slice(bytes);
+    let column = &mut self.columns[self.column_index];
+    column.data[offset..offset + size].copy_from_slice(bytes);
+    // Could be left over from an uncommitted row
+    if let Some(nulls) = &mut column.nulls {
+      nulls.set(self.cur_partition_meta.row_count, false);
+    }
     self.column_index += 1;
   }
 
@@ -35,16 +79,19 @@ impl Table {
     }
   }
 
-  fn get_partition_ts(&self, date: NaiveDateTime, offset: i32) -> i64 {
+  pub(super) fn get_partition_ts(&self, date: NaiveDateTime, offset: i32) -> i64 {
     match self.schema.partition_by {
       PartitionBy::None => {
         if offset == 0 {
-          MIN_DATETIME.naive_utc()
+          NaiveDateTime::MIN
         } else {
-          MAX_DATETIME.naive_utc()
+          NaiveDateTime::MAX
         }
       }
-      PartitionBy::Year => NaiveDate::from_ymd(date.year() + offset, 1, 1).and_hms(0, 0, 0),
+      PartitionBy::Year => NaiveDate::from_ymd_opt(date.year() + offset, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap(),
       PartitionBy::Month => {
         let mut year = date.year();
         let mut month = date.month() + offset as u32;
@@ -52,184 +99,420 @@ impl Table {
           month = month % 12;
           year += offset;
         }
-        NaiveDate::from_ymd(year, month, 1).and_hms(0, 0, 0)
+        NaiveDate::from_ymd_opt(year, month, 1).unwrap().and_hms_opt(0, 0, 0).unwrap()
+      }
+      PartitionBy::Day => (date.date() + Duration::days(offset as i64))
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+    }
+    .and_utc()
+    .timestamp_nanos_opt()
+    .unwrap()
+  }
+
+  // Opens the partition `val` belongs to if it's not the current one
+  pub(super) fn seek_partition(&mut self, val: i64) -> Result<(), Error> {
+    if val <= self.cur_partition_meta.max_ts
+      && val >= self.cur_partition_meta.min_ts
+      && self.cur_partition_meta.row_count != 0
+    {
+      return Ok(());
+    }
+    // Save old partition meta
+    self.merge_out_of_order()?;
+    self.write_indexes()?;
+    self.save_cur_partition_meta();
+    // Load new partition meta
+    let is_first_partition = self.cur_partition.is_empty();
+    self.cur_partition = self.get_partition_dir(val);
+    self.cur_partition_meta = match self.partition_meta.get_mut(&self.cur_partition) {
+      Some(meta) => meta.clone(),
+      None => {
+        self.dir_index = if is_first_partition {
+          0
+        } else {
+          (self.dir_index + 1) % self.schema.partition_dirs.len()
+        };
+        let dir = self.schema.partition_dirs[self.dir_index].clone();
+        let col_dir = get_col_dir(&self.schema.name, &dir, &self.cur_partition);
+        create_dir_all(&col_dir).with_path(&col_dir)?;
+        let date = val.to_naive_date_time();
+        let min_ts = self.get_partition_ts(date, 0);
+        let max_ts = self.get_partition_ts(date, 1) - 1;
+        PartitionMeta {
+          dir,
+          from_ts: val,
+          to_ts: val,
+          min_ts,
+          max_ts,
+          row_count: 0,
+          sealed: false
+        }
       }
-      PartitionBy::Day => (date.date() + Duration::days(offset as i64)).and_hms(0, 0, 0)
+    };
+    if self.cur_partition_meta.sealed {
+      self.unseal()?;
+    }
+    self.index_from = self.cur_partition_meta.row_count;
+    // Expect 10m more rows in partition
+    self.columns = self.open_columns(&self.cur_partition_meta.dir, 10_000_000)?;
+    Ok(())
+  }
+
+  // Must be called in row order
+  pub(super) fn track_ts(&mut self, val: i64, row_index: usize) {
+    if val < self.cur_partition_meta.to_ts && self.ooo_row_index.is_none() {
+      // Rows before this one are sorted. Merge the rest in on flush or partition change.
+      self.ooo_row_index = Some(row_index);
     }
-    .timestamp_nanos()
+    self.dirty_from = Some(self.dirty_from.map_or(val, |ts| min(ts, val)));
+    self.cur_partition_meta.from_ts = min(self.cur_partition_meta.from_ts, val);
+    self.cur_partition_meta.to_ts = max(self.cur_partition_meta.to_ts, val);
   }
 
-  pub fn put_timestamp(&mut self, mut val: i64) {
+  // Timestamps smaller than 8 bytes are stored relative to the partition's min_ts
+  pub(super) fn encode_ts(&self, column_index: usize, val: i64) -> i64 {
+    let column = &self.schema.columns[column_index];
+    match column.size {
+      8 => val,
+      _ => (val - self.cur_partition_meta.min_ts) / column.resolution
+    }
+  }
+
+  pub fn put_timestamp(&mut self, mut val: i64) -> Result<(), Error> {
+    self.check_type(|t| t == ColumnType::Timestamp, "Timestamp")?;
+    self.wal_put(&val.to_le_bytes());
     let resolution = self.schema.columns[self.column_index].resolution;
     // Round off for partition calculation
     val = val / resolution * resolution;
     if self.column_index == 0 {
-      // New partition?
-      if val > self.cur_partition_meta.max_ts
-        || val < self.cur_partition_meta.min_ts
-        || self.cur_partition_meta.row_count == 0
-      {
-        // Save old partition meta
-        self.save_cur_partition_meta();
-        // Load new partition meta
-        let is_first_partition = self.cur_partition.is_empty();
-        self.cur_partition = self.get_partition_dir(val);
-        self.cur_partition_meta = match self.partition_meta.get_mut(&self.cur_partition) {
-          Some(meta) => {
-            if val < meta.to_ts {
-              panic!(
-                "Timestamp {} is out of order (previous {})",
-                val, meta.to_ts
-              );
-            }
-            meta.clone()
-          }
-          None => {
-            self.dir_index = if is_first_partition {
-              0
-            } else {
-              (self.dir_index + 1) % self.schema.partition_dirs.len()
-            };
-            let dir = self.schema.partition_dirs[self.dir_index].clone();
-            create_dir_all(get_col_dir(&self.schema.name, &dir, &self.cur_partition))
-              .unwrap_or_else(|_| panic!("Cannot create dir {:?}", &dir));
-            let date = val.to_naive_date_time();
-            let min_ts = self.get_partition_ts(date, 0);
-            let max_ts = self.get_partition_ts(date, 1) - 1;
-            PartitionMeta {
-              dir,
-              from_ts: val,
-              to_ts: val,
-              min_ts,
-              max_ts,
-              row_count: 0
-            }
-          }
-        };
-        // Expect 10m more rows in partition
-        self.columns = self.open_columns(&self.cur_partition_meta.dir, 10_000_000);
-      }
-      self.cur_partition_meta.to_ts = val;
+      self.lock()?;
+      self.seek_partition(val)?;
+      self.track_ts(val, self.cur_partition_meta.row_count);
     }
+    let rel_val = self.encode_ts(self.column_index, val);
     match self.schema.columns[self.column_index].size {
-      8 => self.put_i64(val),
-      4 => self.put_u32(((val - self.cur_partition_meta.min_ts) / resolution) as u32),
-      2 => self.put_u16(((val - self.cur_partition_meta.min_ts) / resolution) as u16),
-      1 => self.put_u8(((val - self.cur_partition_meta.min_ts) / resolution) as u8),
+      8 => self.put_bytes(&val.to_le_bytes()),
+      4 => self.put_bytes(&(rel_val as u32).to_le_bytes()),
+      2 => self.put_bytes(&(rel_val as u16).to_le_bytes()),
+      1 => self.put_bytes(&(rel_val as u8).to_le_bytes()),
       s => panic!("Invalid column size {}", s)
     };
+    Ok(())
   }
 
-  pub fn put_symbol(&mut self, val: String) {
-    let column_symbols = &mut self.column_symbols[self.column_index];
-    let symbol_nums = &mut column_symbols.symbol_nums;
-    let index = match symbol_nums.get(&val) {
-      Some(i) => *i,
-      None => {
-        let symbols = &mut column_symbols.symbols;
-        symbols.push(val.clone());
-        symbol_nums.insert(val, symbols.len());
-        symbols.len()
-      }
-    };
+  // Symbol ids start at 1
+  pub(super) fn get_symbol_index(&mut self, column_index: usize, val: &str) -> Result<usize, Error> {
+    let capacity = get_capacity(&self.schema.columns[column_index]);
+    let column_symbols = &mut self.column_symbols[column_index];
+    if let Some(i) = column_symbols.symbol_nums.get(val) {
+      return Ok(*i);
+    }
+    let symbols = &mut column_symbols.symbols;
+    if symbols.len() + 1 >= capacity {
+      return Err(Error::SymbolOverflow {
+        column: self.schema.columns[column_index].name.clone(),
+        capacity: capacity - 1
+      });
+    }
+    symbols.push(val.to_owned());
+    column_symbols
+      .symbol_nums
+      .insert(val.to_owned(), symbols.len());
+    let index = symbols.len();
+    self.wal_add_symbol(column_index, val)?;
+    Ok(index)
+  }
+
+  pub fn put_symbol<S: AsRef<str>>(&mut self, val: S) -> Result<(), Error> {
+    self.check_type(
+      |t| t == ColumnType::Symbol8 || t == ColumnType::Symbol16 || t == ColumnType::Symbol32,
+      "Symbol"
+    )?;
+    let index = self.get_symbol_index(self.column_index, val.as_ref())?;
+    self.wal_put(&(index as u32).to_le_bytes());
     let column = &self.columns[self.column_index];
     match column.r#type {
-      ColumnType::Symbol8 => self.put_u8(index as u8),
-      ColumnType::Symbol16 => self.put_u16(index as u16),
-      ColumnType::Symbol32 => self.put_u32(index as u32),
+      ColumnType::Symbol8 => self.put_bytes(&(index as u8).to_le_bytes()),
+      ColumnType::Symbol16 => self.put_bytes(&(index as u16).to_le_bytes()),
+      ColumnType::Symbol32 => self.put_bytes(&(index as u32).to_le_bytes()),
       bad_type => panic!("Unsupported column type {:?}", bad_type)
     }
+    Ok(())
+  }
+
+  pub fn put_null(&mut self) -> Result<(), Error> {
+    let column = self
+      .schema
+      .columns
+      .get(self.column_index)
+      .ok_or_else(|| Error::ColumnNotFound {
+        table:  self.schema.name.clone(),
+        column: format!("#{}", self.column_index)
+      })?;
+    // Rows need a timestamp to pick a partition
+    if !column.nullable || self.column_index == 0 {
+      return Err(Error::NotNullable(column.name.clone()));
+    }
+    let size = column.size;
+    let r#type = column.r#type;
+    self.wal_put_null();
+    if is_variable_length(r#type) {
+      // An empty value so the next row's start offset stays right
+      let row_index = self.cur_partition_meta.row_count;
+      let end = get_heap_len(&self.columns[self.column_index].data, row_index);
+      self.put_bytes(&(end as u64).to_le_bytes());
+    } else {
+      self.put_bytes(&[0; 8][..size]);
+    }
+    let row_index = self.cur_partition_meta.row_count;
+    if let Some(nulls) = &mut self.columns[self.column_index - 1].nulls {
+      nulls.set(row_index, true);
+    }
+    Ok(())
+  }
+
+  fn put_value(&mut self, r#type: ColumnType, bytes: &[u8]) -> Result<(), Error> {
+    self.check_type(|t| t == r#type, &format!("{:?}", r#type))?;
+    self.wal_put(bytes);
+    self.put_bytes(bytes);
+    Ok(())
+  }
+
+  fn put_variable_length(&mut self, r#type: ColumnType, bytes: &[u8]) -> Result<(), Error> {
+    self.check_type(|t| t == r#type, &format!("{:?}", r#type))?;
+    let mut wal_bytes = (bytes.len() as u32).to_le_bytes().to_vec();
+    wal_bytes.extend_from_slice(bytes);
+    self.wal_put(&wal_bytes);
+    let row_index = self.cur_partition_meta.row_count;
+    let column = &mut self.columns[self.column_index];
+    let start = get_heap_len(&column.data, row_index);
+    column.heap.as_mut().unwrap().put(start, bytes)?;
+    self.put_bytes(&((start + bytes.len()) as u64).to_le_bytes());
+    Ok(())
+  }
+
+  pub fn put_string(&mut self, val: &str) -> Result<(), Error> {
+    self.put_variable_length(ColumnType::String, val.as_bytes())
+  }
+
+  pub fn put_binary(&mut self, val: &[u8]) -> Result<(), Error> {
+    self.put_variable_length(ColumnType::Bytes, val)
+  }
+
+  // `val` is already scaled, so 1.23 in a column with scale 2 is 123
+  pub fn put_decimal(&mut self, val: i64) -> Result<(), Error> {
+    self.check_type(
+      |t| matches!(t, ColumnType::Decimal32 { .. } | ColumnType::Decimal64 { .. }),
+      "Decimal"
+    )?;
+    let column = &self.schema.columns[self.column_index];
+    match column.r#type {
+      ColumnType::Decimal32 { .. } => {
+        let val: i32 = val.try_into().map_err(|_| Error::DecimalOverflow {
+          column: column.name.clone(),
+          value:  val
+        })?;
+        self.wal_put(&val.to_le_bytes());
+        self.put_bytes(&val.to_le_bytes());
+      }
+      _ => {
+        self.wal_put(&val.to_le_bytes());
+        self.put_bytes(&val.to_le_bytes());
+      }
+    }
+    Ok(())
   }
 
-  pub fn put_i8(&mut self, val: i8) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_i8(&mut self, val: i8) -> Result<(), Error> {
+    self.put_value(ColumnType::I8, &val.to_le_bytes())
+  }
 
-  pub fn put_u8(&mut self, val: u8) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_u8(&mut self, val: u8) -> Result<(), Error> {
+    self.put_value(ColumnType::U8, &val.to_le_bytes())
+  }
 
-  pub fn put_i16(&mut self, val: i16) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_i16(&mut self, val: i16) -> Result<(), Error> {
+    self.put_value(ColumnType::I16, &val.to_le_bytes())
+  }
 
-  pub fn put_u16(&mut self, val: u16) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_u16(&mut self, val: u16) -> Result<(), Error> {
+    self.put_value(ColumnType::U16, &val.to_le_bytes())
+  }
 
-  pub fn put_i32(&mut self, val: i32) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_i32(&mut self, val: i32) -> Result<(), Error> {
+    self.put_value(ColumnType::I32, &val.to_le_bytes())
+  }
 
-  pub fn put_u32(&mut self, val: u32) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_u32(&mut self, val: u32) -> Result<(), Error> {
+    self.put_value(ColumnType::U32, &val.to_le_bytes())
+  }
 
-  pub fn put_f32(&mut self, val: f32) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_f32(&mut self, val: f32) -> Result<(), Error> {
+    self.put_value(ColumnType::F32, &val.to_le_bytes())
+  }
 
-  pub fn put_i64(&mut self, val: i64) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_i64(&mut self, val: i64) -> Result<(), Error> {
+    self.put_value(ColumnType::I64, &val.to_le_bytes())
+  }
 
-  pub fn put_u64(&mut self, val: u64) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_u64(&mut self, val: u64) -> Result<(), Error> {
+    self.put_value(ColumnType::U64, &val.to_le_bytes())
+  }
 
-  pub fn put_f64(&mut self, val: f64) { self.put_bytes(&val.to_le_bytes()) }
+  pub fn put_f64(&mut self, val: f64) -> Result<(), Error> {
+    self.put_value(ColumnType::F64, &val.to_le_bytes())
+  }
 
-  fn write_symbols(&self) {
+  fn write_symbols(&self) -> Result<(), Error> {
     for table_col_symbols in &self.column_symbols {
       if table_col_symbols.symbols.len() == 0 {
         continue;
       }
       let symbols_text = table_col_symbols.symbols.join("\n");
       let path = &table_col_symbols.path;
+      // Swap in atomically for readers
+      let tmp_path = path.with_extension("symbols.tmp");
 
-      // TODO: lock file
       let mut f = OpenOptions::new()
         .write(true)
         .create(true)
-        .open(path)
-        .unwrap_or_else(|_| panic!("Could not open symbols file {:?}", path));
+        .truncate(true)
+        .open(&tmp_path)
+        .with_path(&tmp_path)?;
       f.write_all(symbols_text.as_bytes())
-        .unwrap_or_else(|_| panic!("Could not write to symbols file {:?}", path));
-      f.flush()
-        .unwrap_or_else(|_| panic!("Could not flush to symbols file {:?}", path));
+        .and_then(|_| f.flush())
+        .with_path(&tmp_path)?;
+      rename(&tmp_path, path).with_path(path)?;
     }
+    Ok(())
   }
 
-  pub fn write(&mut self) {
+  // Grows column files so they can hold `row_count` rows
+  pub(super) fn reserve(&mut self, row_count: usize) -> Result<(), Error> {
+    for c in &mut self.columns {
+      if c.data.len() >= c.size * row_count {
+        continue;
+      }
+      let mut size = max(c.data.len(), c.size);
+      while size < c.size * row_count {
+        size *= 2;
+      }
+      // println!("Grow {} from {} to {}", c.name, c.data.len(), size);
+      // Grow file
+      c.file.set_len(size as u64).with_path(&c.path)?;
+      // Map file again
+      unsafe {
+        c.data = memmap::MmapOptions::new()
+          .map_mut(&c.file)
+          .with_path(&c.path)?;
+      }
+      // TODO: remove memmap dep and use mremap on *nix
+      // https://man7.org/linux/man-pages/man2/mremap.2.html
+    }
+    for c in &mut self.columns {
+      if let Some(nulls) = &mut c.nulls {
+        nulls.reserve(row_count)?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn write(&mut self) -> Result<(), Error> {
+    self.wal_write_row()?;
     self.column_index = 0;
     self.cur_partition_meta.row_count += 1;
     // Check if next write will be larger than file
-    for c in &mut self.columns {
-      let size = c.data.len();
-      if size <= c.size * (self.cur_partition_meta.row_count + 1) {
-        let size = c.data.len() as u64;
-        // println!("Grow {} from {} to {}", c.name, size, size * 2);
-        // Unmap by dropping c.data
-        drop(&c.data);
-        // Grow file
-        c.file
-          .set_len(size * 2)
-          .unwrap_or_else(|_| panic!("Could not truncate {:?} to {}", c.file, size * 2));
-        // Map file again
-        unsafe {
-          c.data = memmap::MmapOptions::new()
-            .map_mut(&c.file)
-            .unwrap_or_else(|_| panic!("Could not mmapp after grow {:?}", c.file));
+    self.reserve(self.cur_partition_meta.row_count + 1)
+  }
+
+  // Sort late rows by timestamp and merge them with the sorted rows before them so that
+  // `find_ts` can keep binary searching the partition
+  fn merge_out_of_order(&mut self) -> Result<(), Error> {
+    let sorted_rows = match self.ooo_row_index.take() {
+      Some(i) => i,
+      None => return Ok(())
+    };
+    let row_count = self.cur_partition_meta.row_count;
+    let ts_column = &self.columns[0];
+    let mut late_rows = (sorted_rows..row_count).collect::<Vec<_>>();
+    late_rows.sort_by_key(|row_index| get_stored_ts(ts_column, *row_index));
+
+    let mut order = Vec::with_capacity(row_count);
+    let mut i = 0;
+    let mut j = 0;
+    while i < sorted_rows || j < late_rows.len() {
+      if j == late_rows.len()
+        || (i < sorted_rows
+          && get_stored_ts(ts_column, i) <= get_stored_ts(ts_column, late_rows[j]))
+      {
+        order.push(i);
+        i += 1;
+      } else {
+        order.push(late_rows[j]);
+        j += 1;
+      }
+    }
+    if let Some(moved) = order.iter().enumerate().position(|(i, src_index)| i != *src_index) {
+      self.index_from = min(self.index_from, moved);
+    }
+
+    for column in &mut self.columns {
+      let size = column.size;
+      let old_data = column.data[..row_count * size].to_vec();
+      if let Some(heap) = &mut column.heap {
+        // Offsets change with the order so rewrite values too
+        let old_heap = heap.data[..get_heap_len(&old_data, row_count)].to_vec();
+        let mut end = 0;
+        for (dest_index, src_index) in order.iter().enumerate() {
+          let value = &old_heap[get_range(&old_data, *src_index)];
+          heap.data[end..end + value.len()].copy_from_slice(value);
+          end += value.len();
+          column.data[dest_index * size..(dest_index + 1) * size]
+            .copy_from_slice(&(end as u64).to_le_bytes());
+        }
+      } else {
+        for (dest_index, src_index) in order.iter().enumerate() {
+          column.data[dest_index * size..(dest_index + 1) * size]
+            .copy_from_slice(&old_data[src_index * size..(src_index + 1) * size]);
+        }
+      }
+      if let Some(nulls) = &mut column.nulls {
+        let old_nulls = (0..row_count).map(|i| nulls.get(i)).collect::<Vec<_>>();
+        for (dest_index, src_index) in order.iter().enumerate() {
+          nulls.set(dest_index, old_nulls[*src_index]);
         }
-        // TODO: remove memmap dep and use mremap on *nix
-        // https://man7.org/linux/man-pages/man2/mremap.2.html
       }
     }
+    Ok(())
   }
 
-  pub fn flush(&mut self) {
+  pub fn flush(&mut self) -> Result<(), Error> {
+    self.wal_commit()?;
+    self.merge_out_of_order()?;
     for column in &mut self.columns {
-      column
-        .data
-        .flush()
-        .unwrap_or_else(|_| panic!("Could not flush {:?}", column.path));
+      if let Some(heap) = &mut column.heap {
+        heap.flush(get_heap_len(&column.data, self.cur_partition_meta.row_count))?;
+      }
+      column.data.flush().with_path(&column.path)?;
       // Leave a spot for the next insert
       let size = column.size * (self.cur_partition_meta.row_count + 1);
-      column.file.set_len(size as u64).unwrap_or_else(|_| {
-        panic!(
-          "Could not truncate {:?} to {} to save {} bytes on disk",
-          column.file,
-          size,
-          column.data.len() - size
-        )
-      });
+      column.file.set_len(size as u64).with_path(&column.path)?;
+      // Don't leave pages past the end of the file mapped
+      unsafe {
+        column.data = memmap::MmapOptions::new()
+          .map_mut(&column.file)
+          .with_path(&column.path)?;
+      }
+      if let Some(nulls) = &mut column.nulls {
+        nulls.flush(self.cur_partition_meta.row_count)?;
+      }
     }
-    self.write_symbols();
+    self.write_indexes()?;
+    self.write_symbols()?;
     self.save_cur_partition_meta();
-    self
-      .write_meta()
-      .expect("Could not write meta file with row_count");
+    self.write_meta()?;
+    self.wal_truncate()?;
+    self.update_aggregates()
   }
 }
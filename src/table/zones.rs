@@ -0,0 +1,291 @@
+use crate::{
+  error::{Error, IoContext},
+  schema::{Column, ColumnType},
+  table::{codec::get_block_rows, Table, TableColumn}
+};
+use serde::{Deserialize, Serialize};
+use std::{
+  cmp::min,
+  convert::TryInto,
+  fs::{read, rename, File},
+  io::{ErrorKind, Write},
+  ops::Range,
+  path::PathBuf
+};
+
+// Zone maps hold the min, max and non-null count of each 64KB block of a numeric column so scans
+// can skip blocks a predicate can't match. The file is the row count it was built for followed by
+// each block's f64 min, f64 max and u64 count.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zone {
+  pub min:   f64,
+  pub max:   f64,
+  pub count: usize
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Compare {
+  Lt,
+  Le,
+  Eq,
+  Ne,
+  Ge,
+  Gt
+}
+
+impl Compare {
+  pub fn test(self, a: f64, b: f64) -> bool {
+    match self {
+      Compare::Lt => a < b,
+      Compare::Le => a <= b,
+      Compare::Eq => a == b,
+      Compare::Ne => a != b,
+      Compare::Ge => a >= b,
+      Compare::Gt => a > b
+    }
+  }
+}
+
+impl Zone {
+  // Whether any value `v` in the block could satisfy `v op value`
+  pub fn may_match(&self, op: Compare, value: f64) -> bool {
+    if self.count == 0 {
+      return false;
+    }
+    match op {
+      Compare::Lt => self.min < value,
+      Compare::Le => self.min <= value,
+      Compare::Eq => self.min <= value && value <= self.max,
+      Compare::Ne => !(self.min == value && self.max == value),
+      Compare::Ge => self.max >= value,
+      Compare::Gt => self.max > value
+    }
+  }
+}
+
+const ZONE_LEN: usize = 24;
+
+pub fn get_zones_path(col_path: &PathBuf) -> PathBuf {
+  let mut path = col_path.clone().into_os_string();
+  path.push(".zones");
+  PathBuf::from(path)
+}
+
+pub fn has_zones(r#type: ColumnType) -> bool {
+  !matches!(
+    r#type,
+    ColumnType::Symbol8
+      | ColumnType::Symbol16
+      | ColumnType::Symbol32
+      | ColumnType::String
+      | ColumnType::Bytes
+  )
+}
+
+// The value predicates compare against. Decimals are divided by 10^scale and timestamps are
+// nanoseconds.
+pub(super) fn get_value(column: &TableColumn, row_index: usize, min_ts: i64) -> f64 {
+  let bytes = &column.data[row_index * column.size..(row_index + 1) * column.size];
+  match column.r#type {
+    ColumnType::I8 => bytes[0] as i8 as f64,
+    ColumnType::U8 => bytes[0] as f64,
+    ColumnType::I16 => i16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    ColumnType::U16 => u16::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    ColumnType::I32 => i32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    ColumnType::U32 => u32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    ColumnType::I64 => i64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    ColumnType::U64 => u64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    ColumnType::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+    ColumnType::F64 => f64::from_le_bytes(bytes.try_into().unwrap()),
+    ColumnType::Decimal32 { scale } => {
+      i32::from_le_bytes(bytes.try_into().unwrap()) as f64 / 10_f64.powi(scale as i32)
+    }
+    ColumnType::Decimal64 { scale } => {
+      i64::from_le_bytes(bytes.try_into().unwrap()) as f64 / 10_f64.powi(scale as i32)
+    }
+    ColumnType::Timestamp => {
+      let rel_val = match column.size {
+        8 => return i64::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        4 => u32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+        2 => u16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+        1 => bytes[0] as i64,
+        s => panic!("Invalid column size {}", s)
+      };
+      (rel_val * column.resolution + min_ts) as f64
+    }
+    r#type => panic!("ColumnType {:?} has no zones", r#type)
+  }
+}
+
+fn get_zone(column: &TableColumn, rows: Range<usize>, min_ts: i64) -> Zone {
+  let mut res = Zone {
+    min:   f64::INFINITY,
+    max:   f64::NEG_INFINITY,
+    count: 0
+  };
+  for row_index in rows {
+    if column.nulls.as_ref().map_or(false, |nulls| nulls.get(row_index)) {
+      continue;
+    }
+    let val = get_value(column, row_index, min_ts);
+    res.count += 1;
+    if val.is_nan() {
+      // NaN != anything so it can match any Ne
+      res.min = f64::NEG_INFINITY;
+      res.max = f64::INFINITY;
+    } else {
+      res.min = res.min.min(val);
+      res.max = res.max.max(val);
+    }
+  }
+  res
+}
+
+// Returns the row count the zones were built for and the zones
+fn read_zones_file(path: &PathBuf) -> Result<Option<(usize, Vec<Zone>)>, Error> {
+  let data = match read(path) {
+    Ok(data) => data,
+    Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+    Err(error) => {
+      return Err(Error::Io {
+        path: path.clone(),
+        error
+      })
+    }
+  };
+  if data.len() < 8 || (data.len() - 8) % ZONE_LEN != 0 {
+    return Ok(None);
+  }
+  let get_u64 = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+  let zones = (8..data.len())
+    .step_by(ZONE_LEN)
+    .map(|offset| Zone {
+      min:   f64::from_bits(get_u64(offset)),
+      max:   f64::from_bits(get_u64(offset + 8)),
+      count: get_u64(offset + 16) as usize
+    })
+    .collect();
+  Ok(Some((get_u64(0) as usize, zones)))
+}
+
+// Zones built for a different row count are stale and can't be used
+pub fn read_zones(col_path: &PathBuf, row_count: usize) -> Result<Option<Vec<Zone>>, Error> {
+  Ok(match read_zones_file(&get_zones_path(col_path))? {
+    Some((zones_row_count, zones)) if zones_row_count == row_count => Some(zones),
+    _ => None
+  })
+}
+
+fn write_zones_file(path: &PathBuf, row_count: usize, zones: &[Zone]) -> Result<(), Error> {
+  let mut data = Vec::with_capacity(8 + zones.len() * ZONE_LEN);
+  data.extend_from_slice(&(row_count as u64).to_le_bytes());
+  for zone in zones {
+    data.extend_from_slice(&zone.min.to_le_bytes());
+    data.extend_from_slice(&zone.max.to_le_bytes());
+    data.extend_from_slice(&(zone.count as u64).to_le_bytes());
+  }
+  let mut tmp_path = path.clone().into_os_string();
+  tmp_path.push(".tmp");
+  let tmp_path = PathBuf::from(tmp_path);
+  File::create(&tmp_path)
+    .and_then(|mut f| f.write_all(&data))
+    .with_path(&tmp_path)?;
+  rename(&tmp_path, path).with_path(path)
+}
+
+// Row ranges of the blocks that may have values matching `v op value`
+pub fn get_matching_rows(
+  column: &Column,
+  zones: &[Zone],
+  op: Compare,
+  value: f64,
+  row_count: usize
+) -> Vec<Range<usize>> {
+  let block_rows = get_block_rows(column);
+  let mut res: Vec<Range<usize>> = Vec::new();
+  for (block_index, zone) in zones.iter().enumerate() {
+    if !zone.may_match(op, value) {
+      continue;
+    }
+    let start = block_index * block_rows;
+    let end = min(start + block_rows, row_count);
+    match res.last_mut() {
+      Some(last) if last.end == start => last.end = end,
+      _ => res.push(start..end)
+    }
+  }
+  res
+}
+
+impl Table {
+  // Rebuilds the current partition's zones for blocks with rows from `from_row` on or added since
+  // they were last built
+  pub(super) fn write_zones(&self, from_row: usize) -> Result<(), Error> {
+    let row_count = self.cur_partition_meta.row_count;
+    let min_ts = self.cur_partition_meta.min_ts;
+    for (column, table_column) in self.schema.columns.iter().zip(self.columns.iter()) {
+      if !has_zones(column.r#type) {
+        continue;
+      }
+      let path = get_zones_path(&table_column.path);
+      let (zones_row_count, mut zones) = match read_zones_file(&path)? {
+        Some((zones_row_count, zones)) if zones_row_count <= row_count => (zones_row_count, zones),
+        _ => (0, Vec::new())
+      };
+      let block_rows = get_block_rows(column);
+      let from_block = min(from_row, zones_row_count) / block_rows;
+      zones.truncate(from_block);
+      for block_index in from_block..row_count.div_ceil(block_rows) {
+        let start = block_index * block_rows;
+        let end = min(start + block_rows, row_count);
+        zones.push(get_zone(table_column, start..end, min_ts));
+      }
+      write_zones_file(&path, row_count, &zones)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn may_match() {
+    let zone = Zone {
+      min:   10.0,
+      max:   20.0,
+      count: 5
+    };
+    assert!(zone.may_match(Compare::Gt, 15.0));
+    assert!(!zone.may_match(Compare::Gt, 20.0));
+    assert!(zone.may_match(Compare::Ge, 20.0));
+    assert!(!zone.may_match(Compare::Lt, 10.0));
+    assert!(zone.may_match(Compare::Le, 10.0));
+    assert!(zone.may_match(Compare::Eq, 12.5));
+    assert!(!zone.may_match(Compare::Eq, 21.0));
+    assert!(zone.may_match(Compare::Ne, 10.0));
+    let constant = Zone {
+      min:   1.0,
+      max:   1.0,
+      count: 5
+    };
+    assert!(!constant.may_match(Compare::Ne, 1.0));
+    let empty = Zone { count: 0, ..zone };
+    assert!(!empty.may_match(Compare::Ge, 0.0));
+  }
+
+  #[test]
+  fn matching_rows() {
+    let column = Column::new("volume", ColumnType::U64);
+    let block_rows = get_block_rows(&column);
+    let zone = |min, max| Zone { min, max, count: 1 };
+    let zones = [zone(0.0, 5.0), zone(6.0, 9.0), zone(0.0, 9.0), zone(0.0, 1.0), zone(8.0, 8.0)];
+    let row_count = 4 * block_rows + 10;
+    assert_eq!(
+      get_matching_rows(&column, &zones, Compare::Gt, 7.0, row_count),
+      vec![block_rows..3 * block_rows, 4 * block_rows..row_count]
+    );
+    assert!(get_matching_rows(&column, &zones, Compare::Gt, 9.0, row_count).is_empty());
+  }
+}
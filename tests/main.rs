@@ -1,13 +1,31 @@
+use arrow_array::{
+  cast::AsArray,
+  types::{Decimal128Type, Float64Type, TimestampNanosecondType, UInt8Type},
+  Array
+};
+use arrow_ipc::{reader::StreamReader, writer::StreamWriter};
 use fastrand;
-use std::slice::from_raw_parts;
+use std::{
+  fs::{metadata, remove_dir_all, remove_file},
+  slice::from_raw_parts
+};
 use zdb::{
   schema::*,
   server::{
+    asof::asof,
     julia::{init_julia, jl_array_t, jl_get_nth_field, jl_unbox_int64},
+    latest::latest,
+    ohlcv::ohlcv,
     query::{run_query, Query}
   },
-  table::Table,
-  test_symbols::SYMBOLS
+  table::{
+    get_home_path, get_parquet_schema, parse_duration, scan::PartitionColumn, to_record_batch,
+    AggregateFn, Alignment, AsOf, Bars, ColumnBuffer, ColumnData, Compare, Filter, GroupBy,
+    OwnedColumn, Resample, Table
+  },
+  sql::run_sql,
+  test_symbols::SYMBOLS,
+  Error, ZdbRow
 };
 
 pub fn initialize_agg1m() -> Table {
@@ -24,8 +42,11 @@ pub fn initialize_trades() -> Table {
   }
 }
 
+#[derive(ZdbRow, Debug, PartialEq)]
 struct OHLCV {
+  #[zdb(timestamp)]
   ts:       i64,
+  #[zdb(rename = "ticker")]
   sym:      String,
   open:     f32,
   high:     f32,
@@ -65,23 +86,14 @@ fn generate_rows(from_ts: i64, row_count: usize, freq: usize) -> Vec<OHLCV> {
   res
 }
 
-fn write_rows(table: &mut Table, rows: Vec<OHLCV>) {
-  // Maybe one day we can do this dynamically...
+fn write_rows(table: &mut Table, rows: Vec<OHLCV>) -> Result<(), Error> {
   for r in rows {
-    table.put_timestamp(r.ts);
-    table.put_symbol(r.sym);
-    table.put_f32(r.open);
-    table.put_f32(r.high);
-    table.put_f32(r.low);
-    table.put_f32(r.close);
-    table.put_f32(r.close_un);
-    table.put_u64(r.volume);
-    table.write();
+    table.insert(&r)?;
   }
-  table.flush();
+  table.flush()
 }
 
-fn write_ohlcv(table_name: &str, freq: usize, row_count: usize) -> std::io::Result<Table> {
+fn write_ohlcv(table_name: &str, freq: usize, row_count: usize) -> Result<Table, Error> {
   fastrand::seed(0);
 
   let schema = Schema::new(table_name)
@@ -96,20 +108,18 @@ fn write_ohlcv(table_name: &str, freq: usize, row_count: usize) -> std::io::Resu
       Column::new("volume", ColumnType::U64),
     ])
     .partition_by(PartitionBy::Day);
-  let mut table = Table::create(schema);
-  if let Ok(ref mut t) = table {
-    println!("Generating {} rows", row_count);
-    let ts = match t.get_last_ts() {
-      Some(ts) => ts,
-      None => 0
-    };
-    let rows = generate_rows(ts, row_count, freq);
+  let mut table = Table::create(schema)?;
+  println!("Generating {} rows", row_count);
+  let ts = match table.get_last_ts() {
+    Some(ts) => ts,
+    None => 0
+  };
+  let rows = generate_rows(ts, row_count, freq);
 
-    println!("Writing rows");
-    write_rows(t, rows);
-  }
+  println!("Writing rows");
+  write_rows(&mut table, rows)?;
 
-  table
+  Ok(table)
 }
 
 fn get_f64_sum(slice: &[f32]) -> f64 { slice.iter().map(|v| *v as f64).sum::<f64>() }
@@ -127,8 +137,10 @@ fn sum_ohlcv_rust() {
   let mut total = 0;
   let partitions = table.partition_iter(FROM_TS, TO_TS, vec![
     "ts", "open", "high", "low", "close", "volume",
-  ]);
+  ])
+  .unwrap();
   for partition in partitions {
+    let partition = partition.unwrap();
     sums.0 += partition[0]
       .get_u16()
       .iter()
@@ -150,6 +162,59 @@ fn sum_ohlcv_rust() {
   assert_eq!(total, ROW_COUNT);
 }
 
+#[test]
+fn par_scan() {
+  let table = initialize_agg1m();
+
+  let columns = vec!["ts", "ticker", "volume"];
+  let (total, volume) = table
+    .par_scan(
+      FROM_TS,
+      TO_TS,
+      columns.clone(),
+      |partition| (partition[0].row_count, partition[2].get_u64().iter().sum::<u64>()),
+      |a, b| (a.0 + b.0, a.1 + b.1)
+    )
+    .unwrap()
+    .unwrap();
+  assert_eq!(total, ROW_COUNT);
+  assert_eq!(volume, 43414679816093);
+
+  // Results are merged in partition order
+  let get_timestamps = |partition: Vec<PartitionColumn>| {
+    (0..partition[0].row_count)
+      .map(|i| partition[0].get_timestamp(i))
+      .collect::<Vec<_>>()
+  };
+  let concat = |mut a: Vec<i64>, b: Vec<i64>| {
+    a.extend(b);
+    a
+  };
+  let to_ts = 10 * 24 * 60 * 60 * 1_000_000_000;
+  let partitions = table.partition_iter(FROM_TS, to_ts, columns.clone()).unwrap();
+  let expected = partitions.map(|p| get_timestamps(p.unwrap())).fold(Vec::new(), concat);
+  let partitions = table.partition_iter(FROM_TS, to_ts, columns.clone()).unwrap();
+  assert_eq!(partitions.par_scan(get_timestamps, concat).unwrap().unwrap(), expected);
+
+  // Filters apply
+  let symbol = table.column_symbols[1].symbols[0].clone();
+  let partitions = table.partition_iter(FROM_TS, TO_TS, columns.clone()).unwrap();
+  let partitions = partitions.filter_symbols("ticker", &[&symbol]).unwrap();
+  let count_symbol = |partition: Vec<PartitionColumn>| {
+    (0..partition[1].row_count)
+      .filter(|i| partition[1].get_symbol(*i) == symbol)
+      .count()
+  };
+  let count = partitions.par_scan(count_symbol, |a, b| a + b);
+  let partitions = table.partition_iter(FROM_TS, TO_TS, columns.clone()).unwrap();
+  let expected = partitions.map(|p| count_symbol(p.unwrap())).sum::<usize>();
+  assert_ne!(expected, 0);
+  assert_eq!(count.unwrap(), Some(expected));
+
+  let empty = table.par_scan(-10, -1, columns, |partition| partition.len(), |a, b| a + b);
+  assert_eq!(empty.unwrap(), None);
+}
+
 #[test]
 fn sum_ohlcv_julia() {
   init_julia();
@@ -173,10 +238,11 @@ fn sum_ohlcv_julia() {
     end";
 
   let mut query = Query {
-    table: TABLE_NAME.to_string(),
-    from:  FROM_TS,
-    to:    TO_TS,
-    query: query.to_string()
+    table:  TABLE_NAME.to_string(),
+    from:   FROM_TS,
+    to:     TO_TS,
+    query:  query.to_string(),
+    filter: None
   };
 
   let ans = run_query(&mut query);
@@ -203,8 +269,9 @@ fn sum_ticks_rust() {
 
   let mut sum = 0.0;
   let mut total = 0;
-  let partitions = table.partition_iter(FROM_TS, TO_TS, vec!["open"]);
+  let partitions = table.partition_iter(FROM_TS, TO_TS, vec!["open"]).unwrap();
   for partition in partitions {
+    let partition = partition.unwrap();
     sum += get_f64_sum(partition[0].get_f32());
     total += partition[0].get_f32().iter().len();
   }
@@ -236,10 +303,11 @@ fn sum_ticks_julia() {
       (total, sums)
     end";
   let mut query = Query {
-    table: TICKS_NAME.to_string(),
-    from:  FROM_TS,
-    to:    TO_TS,
-    query: query.to_string()
+    table:  TICKS_NAME.to_string(),
+    from:   FROM_TS,
+    to:     TO_TS,
+    query:  query.to_string(),
+    filter: None
   };
 
   let ans = run_query(&mut query);
@@ -257,3 +325,1889 @@ fn sum_ticks_julia() {
     assert_eq!(sums[4], 4.32761664812548e14);
   }
 }
+
+static OOO_NAME: &str = "ooo_test";
+
+#[test]
+fn write_out_of_order() {
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(OOO_NAME);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(OOO_NAME)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("price", ColumnType::F32),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).expect("Could not create table");
+
+  let day = 24 * 60 * 60 * 1_000_000_000;
+  // Late prints both in the current partition and in a previous one
+  let timestamps = vec![10, 20, 30, day + 10, day + 5, 15, day + 20, 5, day + 1];
+  for ts in &timestamps {
+    table.put_timestamp(*ts).unwrap();
+    table.put_f32(*ts as f32).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  let mut expected = timestamps.clone();
+  expected.sort();
+  let mut read = Vec::new();
+  let partitions = table.partition_iter(0, 2 * day, vec!["ts", "price"]).unwrap();
+  for partition in partitions {
+    let partition = partition.unwrap();
+    for i in 0..partition[0].row_count {
+      let ts = partition[0].get_timestamp(i);
+      assert_eq!(partition[1].get_f32()[i], ts as f32);
+      read.push(ts);
+    }
+  }
+  assert_eq!(read, expected);
+
+  let table = Table::open(OOO_NAME).expect("Could not open table");
+  let mut partition_meta = table.partition_meta.values().collect::<Vec<_>>();
+  partition_meta.sort_by_key(|meta| meta.from_ts);
+  assert_eq!(partition_meta.len(), 2);
+  assert_eq!((partition_meta[0].from_ts, partition_meta[0].to_ts), (5, 30));
+  assert_eq!(partition_meta[0].row_count, 5);
+  assert_eq!((partition_meta[1].from_ts, partition_meta[1].to_ts), (day + 1, day + 20));
+  assert_eq!(partition_meta[1].row_count, 4);
+}
+
+#[test]
+fn typed_errors() {
+  assert!(matches!(
+    Table::open("does_not_exist"),
+    Err(Error::TableNotFound(_))
+  ));
+
+  let name = "errors_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("price", ColumnType::F32),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+  table.put_timestamp(1).unwrap();
+  assert!(matches!(
+    table.put_u64(1),
+    Err(Error::TypeMismatch { .. })
+  ));
+  table.put_f32(1.0).unwrap();
+  table.write().unwrap();
+  table.flush().unwrap();
+
+  assert!(matches!(
+    table.partition_iter(0, 10, vec!["volume"]),
+    Err(Error::ColumnNotFound { .. })
+  ));
+  assert!(matches!(
+    table.partition_iter(10, 0, vec!["price"]),
+    Err(Error::OutOfOrder { .. })
+  ));
+}
+
+#[test]
+fn single_writer() {
+  let name = "lock_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = || {
+    Schema::new(name)
+      .add_cols(vec![
+        Column::new("ts", ColumnType::Timestamp),
+        Column::new("price", ColumnType::F32),
+      ])
+      .partition_by(PartitionBy::Day)
+  };
+  let mut writer = Table::create_or_open(schema()).unwrap();
+  assert!(matches!(
+    Table::create_or_open(schema()),
+    Err(Error::TableLocked(_))
+  ));
+
+  // Readers open without blocking the writer
+  let mut reader = Table::open(name).unwrap();
+  writer.put_timestamp(1).unwrap();
+  writer.put_f32(1.0).unwrap();
+  writer.write().unwrap();
+  writer.flush().unwrap();
+  assert!(matches!(reader.put_timestamp(2), Err(Error::TableLocked(_))));
+
+  drop(writer);
+  let writer = Table::create_or_open(schema()).unwrap();
+  assert_eq!(writer.partition_meta.len(), 1);
+}
+
+#[test]
+fn scan_read_only() {
+  let name = "read_only_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("price", ColumnType::F32),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+  for ts in 0..10 {
+    table.put_timestamp(ts).unwrap();
+    table.put_f32(ts as f32).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+  // Appended after the flush so not visible to readers yet
+  table.put_timestamp(10).unwrap();
+  table.put_f32(10.0).unwrap();
+  table.write().unwrap();
+
+  let mut price_path = data_path.clone();
+  price_path.push("1970-01-01");
+  price_path.push("price.f32");
+  let file_len = metadata(&price_path).unwrap().len();
+
+  let reader = Table::open(name).unwrap();
+  let mut prices = Vec::new();
+  for partition in reader.partition_iter(0, 100, vec!["ts", "price"]).unwrap() {
+    let partition = partition.unwrap();
+    prices.extend_from_slice(partition[1].get_f32());
+  }
+  assert_eq!(prices.len(), 10);
+  assert_eq!(metadata(&price_path).unwrap().len(), file_len);
+
+  table.flush().unwrap();
+  let reader = Table::open(name).unwrap();
+  let mut prices = Vec::new();
+  for partition in reader.partition_iter(0, 100, vec!["ts", "price"]).unwrap() {
+    let partition = partition.unwrap();
+    prices.extend_from_slice(partition[1].get_f32());
+  }
+  assert_eq!(prices, (0..11).map(|p| p as f32).collect::<Vec<_>>());
+}
+
+#[test]
+fn append_batch() {
+  let name = "batch_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("ticker", ColumnType::Symbol16),
+      Column::new("close", ColumnType::F32),
+      Column::new("volume", ColumnType::U64),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  let row_count = 10_000;
+  let hour = 60 * 60 * 1_000_000_000;
+  let ts = (0..row_count).map(|i| i * hour).collect::<Vec<_>>();
+  let ticker = (0..row_count)
+    .map(|i| String::from(SYMBOLS[i as usize % 100]))
+    .collect::<Vec<_>>();
+  let close = (0..row_count).map(|i| i as f32 / 2.0).collect::<Vec<_>>();
+  let volume = (0..row_count).map(|i| i as u64 * 100).collect::<Vec<_>>();
+  assert!(matches!(
+    table.append_batch(&[
+      ColumnData::Timestamp(&ts),
+      ColumnData::Symbol(&ticker),
+      ColumnData::F64(&[]),
+      ColumnData::U64(&volume),
+    ]),
+    Err(Error::TypeMismatch { .. })
+  ));
+  table
+    .append_batch(&[
+      ColumnData::Timestamp(&ts),
+      ColumnData::Symbol(&ticker),
+      ColumnData::F32(&close),
+      ColumnData::U64(&volume),
+    ])
+    .unwrap();
+  // Mix in a regular row
+  table.put_timestamp(row_count * hour).unwrap();
+  table.put_symbol(String::from("AAPL")).unwrap();
+  table.put_f32(0.5).unwrap();
+  table.put_u64(1).unwrap();
+  table.write().unwrap();
+  table.flush().unwrap();
+
+  let table = Table::open(name).unwrap();
+  assert_eq!(table.partition_meta.len(), row_count as usize / 24 + 1);
+  let mut i = 0;
+  let partitions = table
+    .partition_iter(0, i64::MAX, vec!["ts", "ticker", "close", "volume"])
+    .unwrap();
+  for partition in partitions {
+    let partition = partition.unwrap();
+    for j in 0..partition[0].row_count {
+      if i < row_count as usize {
+        assert_eq!(partition[0].get_timestamp(j), ts[i]);
+        assert_eq!(partition[1].get_u16()[j] as usize, i % 100 + 1);
+        assert_eq!(partition[2].get_f32()[j], close[i]);
+        assert_eq!(partition[3].get_u64()[j], volume[i]);
+      }
+      i += 1;
+    }
+  }
+  assert_eq!(i, row_count as usize + 1);
+}
+
+#[test]
+fn derive_row() {
+  let name = "derive_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = OHLCV::schema(name).partition_by(PartitionBy::Day);
+  assert_eq!(schema.columns[1].name, "ticker");
+  assert_eq!(schema.columns[1].r#type, ColumnType::Symbol16);
+  assert_eq!(schema.columns[7].r#type, ColumnType::U64);
+  let mut table = Table::create(schema).unwrap();
+  fastrand::seed(0);
+  let rows = generate_rows(0, 1_000, 60 * 60);
+  for r in &rows {
+    table.insert(r).unwrap();
+  }
+  table.flush().unwrap();
+
+  let mut i = 0;
+  for partition in table.row_iter::<OHLCV>(0, i64::MAX).unwrap() {
+    let partition = partition.unwrap();
+    assert_eq!(partition.get(partition.len()), None);
+    for row in partition.iter() {
+      assert_eq!(row, rows[i]);
+      i += 1;
+    }
+  }
+  assert_eq!(i, rows.len());
+
+  #[derive(ZdbRow)]
+  struct WrongType {
+    #[zdb(timestamp)]
+    ts:     i64,
+    #[zdb(symbol8)]
+    ticker: String
+  }
+  assert!(matches!(
+    table.row_iter::<WrongType>(0, i64::MAX),
+    Err(Error::TypeMismatch { .. })
+  ));
+}
+
+#[test]
+fn nullable_columns() {
+  let name = "nulls_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("bid", ColumnType::F32).with_nulls(),
+      Column::new("volume", ColumnType::U64),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Every third bid is missing. Writes 99 late so it gets merged.
+  for i in (0..99).chain(100..200).chain(99..100) {
+    table.put_timestamp(i).unwrap();
+    if i % 3 == 0 {
+      table.put_null().unwrap();
+    } else {
+      table.put_f32(i as f32).unwrap();
+    }
+    assert!(matches!(table.put_null(), Err(Error::NotNullable(_))));
+    table.put_u64(i as u64).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  let table = Table::open(name).unwrap();
+  let partitions = table.partition_iter(50, 150, vec!["ts", "bid"]).unwrap();
+  for partition in partitions {
+    let partition = partition.unwrap();
+    assert!(!partition[0].is_nullable());
+    assert!(partition[1].is_nullable());
+    assert_eq!(partition[1].row_count, 101);
+    assert_eq!(partition[1].null_count(), 34);
+    for i in 0..partition[1].row_count {
+      let ts = partition[0].get_timestamp(i);
+      assert_eq!(partition[1].is_null(i), ts % 3 == 0);
+      if ts % 3 != 0 {
+        assert_eq!(partition[1].get_f32()[i], ts as f32);
+      }
+    }
+    let bitmap = partition[1].get_null_bitmap().unwrap();
+    assert_eq!(bitmap.len(), 25);
+  }
+}
+
+#[derive(ZdbRow, Debug, PartialEq, Clone)]
+struct Trade {
+  #[zdb(timestamp)]
+  ts:         i64,
+  #[zdb(string)]
+  id:         String,
+  conditions: Vec<u8>,
+  price:      f32
+}
+
+#[test]
+fn string_columns() {
+  let name = "strings_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Trade::schema(name).partition_by(PartitionBy::Day);
+  assert_eq!(schema.columns[1].r#type, ColumnType::String);
+  assert_eq!(schema.columns[2].r#type, ColumnType::Bytes);
+  let mut table = Table::create(schema).unwrap();
+
+  let hour = 60 * 60 * 1_000_000_000;
+  let mut trades = (0..100)
+    .map(|i| Trade {
+      ts:         i * hour,
+      id:         "x".repeat(i as usize % 7),
+      conditions: (0..i as u8 % 5).collect(),
+      price:      i as f32
+    })
+    .collect::<Vec<_>>();
+  // Late rows get merged with their values
+  for trade in trades[..40].iter().chain(trades[50..60].iter()).chain(trades[40..50].iter()) {
+    table.insert(trade).unwrap();
+  }
+  assert!(matches!(table.put_u64(0), Err(Error::TypeMismatch { .. })));
+  let batch = &trades[60..];
+  let ts = batch.iter().map(|t| t.ts).collect::<Vec<_>>();
+  let ids = batch.iter().map(|t| t.id.clone()).collect::<Vec<_>>();
+  let conditions = batch.iter().map(|t| t.conditions.clone()).collect::<Vec<_>>();
+  let prices = batch.iter().map(|t| t.price).collect::<Vec<_>>();
+  table
+    .append_batch(&[
+      ColumnData::Timestamp(&ts),
+      ColumnData::String(&ids),
+      ColumnData::Bytes(&conditions),
+      ColumnData::F32(&prices),
+    ])
+    .unwrap();
+  table.flush().unwrap();
+  drop(table);
+
+  // Appending after reopening keeps the old values
+  let trade = Trade {
+    ts:         100 * hour,
+    id:         String::from("last"),
+    conditions: vec![9],
+    price:      100.0
+  };
+  let mut table = Table::open(name).unwrap();
+  table.insert(&trade).unwrap();
+  table.flush().unwrap();
+  trades.push(trade);
+
+  let table = Table::open(name).unwrap();
+  let mut i = 0;
+  for partition in table.row_iter::<Trade>(0, i64::MAX).unwrap() {
+    for row in partition.unwrap().iter() {
+      assert_eq!(row, trades[i]);
+      i += 1;
+    }
+  }
+  assert_eq!(i, trades.len());
+
+  // Scan from the middle of a partition
+  for partition in table.partition_iter(30 * hour, 40 * hour, vec!["id", "conditions"]).unwrap() {
+    let partition = partition.unwrap();
+    assert_eq!(partition[0].get_string(0), trades[30].id);
+    assert_eq!(partition[1].get_binary(1), &trades[31].conditions[..]);
+  }
+}
+
+#[derive(ZdbRow, Debug, PartialEq)]
+struct Quote {
+  #[zdb(timestamp)]
+  ts:  i64,
+  #[zdb(decimal = 2)]
+  bid: i32,
+  #[zdb(decimal = 4)]
+  ask: i64
+}
+
+#[test]
+fn decimal_columns() {
+  let name = "decimals_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Quote::schema(name).partition_by(PartitionBy::Day);
+  assert_eq!(schema.columns[1].r#type, ColumnType::Decimal32 { scale: 2 });
+  assert_eq!(schema.columns[2].r#type, ColumnType::Decimal64 { scale: 4 });
+  let mut table = Table::create(schema).unwrap();
+
+  let quotes = (0..100)
+    .map(|i| Quote {
+      ts:  i,
+      bid: 10_000 + i as i32,
+      ask: -i
+    })
+    .collect::<Vec<_>>();
+  for quote in &quotes {
+    table.insert(quote).unwrap();
+  }
+  table.put_timestamp(100).unwrap();
+  assert!(matches!(
+    table.put_decimal(i64::MAX),
+    Err(Error::DecimalOverflow { .. })
+  ));
+  assert!(matches!(table.put_f32(1.0), Err(Error::TypeMismatch { .. })));
+  table.flush().unwrap();
+
+  let mut i = 0;
+  for partition in table.row_iter::<Quote>(0, i64::MAX).unwrap() {
+    let partition = partition.unwrap();
+    assert_eq!(partition.columns[1].format_decimal(1), "100.01");
+    assert_eq!(partition.columns[2].format_decimal(1), "-0.0001");
+    for row in partition.iter() {
+      assert_eq!(row, quotes[i]);
+      i += 1;
+    }
+  }
+  assert_eq!(i, quotes.len());
+  // Sums are exact
+  let partitions = table.partition_iter(0, i64::MAX, vec!["bid"]).unwrap();
+  let sum = partitions
+    .map(|p| p.unwrap()[0].get_i32().iter().map(|v| *v as i64).sum::<i64>())
+    .sum::<i64>();
+  assert_eq!(sum, 100 * 10_000 + 4950);
+}
+
+#[test]
+fn alter_columns() {
+  let name = "alter_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("price", ColumnType::I32),
+      Column::new("ticker", ColumnType::Symbol8),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Two partitions written before the new columns exist
+  let hour = 60 * 60 * 1_000_000_000;
+  for i in 0..48 {
+    table.put_timestamp(i * hour).unwrap();
+    table.put_i32(i as i32 * 1000).unwrap();
+    table.put_symbol("AAPL").unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  table.add_column(Column::new("size", ColumnType::U32).with_nulls()).unwrap();
+  table.add_column(Column::new("note", ColumnType::String)).unwrap();
+  assert!(matches!(
+    table.add_column(Column::new("note", ColumnType::Bytes)),
+    Err(Error::ColumnExists { .. })
+  ));
+  for i in 48..72 {
+    table.put_timestamp(i * hour).unwrap();
+    table.put_i32(i as i32 * 1000).unwrap();
+    table.put_symbol("MSFT").unwrap();
+    table.put_u32(i as u32).unwrap();
+    table.put_string("new").unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  table.rename_column("price", "px").unwrap();
+  table.rename_column("ticker", "symbol").unwrap();
+  assert!(matches!(
+    table.rename_column("px", "ts"),
+    Err(Error::ColumnExists { .. })
+  ));
+  table.alter_column_type("px", ColumnType::Decimal64 { scale: 2 }).unwrap();
+  table.alter_column_type("symbol", ColumnType::Symbol16).unwrap();
+  table.alter_column_type("note", ColumnType::Bytes).unwrap();
+  // Values that don't fit leave the column as it was
+  assert!(table.alter_column_type("px", ColumnType::I8).is_err());
+  assert!(table.alter_column_type("ts", ColumnType::I64).is_err());
+  table.drop_column("size").unwrap();
+  assert!(matches!(
+    table.drop_column("size"),
+    Err(Error::ColumnNotFound { .. })
+  ));
+  drop(table);
+
+  let table = Table::open(name).unwrap();
+  let names = table.schema.columns.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+  assert_eq!(names, vec!["ts", "px", "symbol", "note"]);
+  let mut i = 0;
+  for partition in table.partition_iter(0, i64::MAX, vec!["ts", "px", "symbol", "note"]).unwrap() {
+    let partition = partition.unwrap();
+    for row_index in 0..partition[0].row_count {
+      assert_eq!(partition[0].get_timestamp(row_index), i * hour);
+      assert_eq!(partition[1].get_decimal(row_index), i * 100_000);
+      let (symbol, note) = if i < 48 { ("AAPL", "") } else { ("MSFT", "new") };
+      assert_eq!(partition[2].get_symbol(row_index), symbol);
+      assert_eq!(partition[3].get_binary(row_index), note.as_bytes());
+      i += 1;
+    }
+  }
+  assert_eq!(i, 72);
+
+  // Older partitions read new nullable columns as null
+  let mut table = Table::open(name).unwrap();
+  table.add_column(Column::new("bid", ColumnType::F64).with_nulls()).unwrap();
+  for partition in table.partition_iter(0, i64::MAX, vec!["bid"]).unwrap() {
+    let partition = partition.unwrap();
+    assert_eq!(partition[0].null_count(), partition[0].row_count);
+  }
+}
+
+#[test]
+fn compressed_columns() {
+  let name = "codecs_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let bad_schema = Schema::new(name).add_cols(vec![
+    Column::new("ts", ColumnType::Timestamp),
+    Column::new("price", ColumnType::F64).with_codec(Codec::Delta),
+  ]);
+  assert!(matches!(
+    Table::create(bad_schema),
+    Err(Error::TypeMismatch { .. })
+  ));
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp).with_codec(Codec::Delta),
+      Column::new("price", ColumnType::F64).with_codec(Codec::Gorilla),
+      Column::new("volume", ColumnType::U64).with_codec(Codec::FrameOfReference),
+      Column::new("flags", ColumnType::U8),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // 3 days of seconds. More than a block per column per day.
+  let day = 24 * 60 * 60;
+  let row_count = 3 * day;
+  let price = |i: i64| 100.0 + (i / 100) as f64 * 0.01;
+  for i in 0..row_count {
+    table.put_timestamp(i * 1_000_000_000).unwrap();
+    table.put_f64(price(i)).unwrap();
+    table.put_u64(1000 + (i % 100) as u64).unwrap();
+    table.put_u8(i as u8).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  let check = |table: &Table, from: i64, to: i64| {
+    let columns = vec!["ts", "price", "volume", "flags"];
+    let partitions = table.partition_iter(from * 1_000_000_000, to * 1_000_000_000, columns);
+    let mut i = from;
+    for partition in partitions.unwrap() {
+      let partition = partition.unwrap();
+      for row_index in 0..partition[0].row_count {
+        assert_eq!(partition[0].get_timestamp(row_index), i * 1_000_000_000);
+        assert_eq!(partition[1].get_f64()[row_index], price(i));
+        assert_eq!(partition[2].get_u64()[row_index], 1000 + (i % 100) as u64);
+        assert_eq!(partition[3].get_u8()[row_index], i as u8);
+        i += 1;
+      }
+    }
+    assert_eq!(i, to + 1);
+  };
+
+  // Seals the first two days
+  table.seal(2 * day * 1_000_000_000).unwrap();
+  assert_eq!(table.partition_meta.values().filter(|m| m.sealed).count(), 2);
+  let mut partition_path = get_home_path();
+  partition_path.push(&table.partition_meta["1970-01-01"].dir);
+  partition_path.push(name);
+  partition_path.push("1970-01-01");
+  assert!(!partition_path.join("ts.timestamp").exists());
+  assert!(partition_path.join("flags.u8").exists());
+  let blocks_len = metadata(partition_path.join("ts.timestamp.blocks")).unwrap().len();
+  assert!(blocks_len < (day * 8 / 4) as u64);
+  check(&table, 0, row_count - 1);
+  check(&Table::open(name).unwrap(), day / 2, day + 70_000);
+
+  // Late rows unseal their partition
+  table.put_timestamp(day * 1_000_000_000 + 500_000_000).unwrap();
+  table.put_f64(0.0).unwrap();
+  table.put_u64(0).unwrap();
+  table.put_u8(0).unwrap();
+  table.write().unwrap();
+  table.flush().unwrap();
+  let table = Table::open(name).unwrap();
+  assert_eq!(table.partition_meta.values().filter(|m| m.sealed).count(), 1);
+  check(&table, 0, day);
+  let to_ts = 2 * day * 1_000_000_000 - 1;
+  let partitions = table.partition_iter(day * 1_000_000_000, to_ts, vec!["ts"]);
+  let partition = partitions.unwrap().next().unwrap().unwrap();
+  assert_eq!(partition[0].row_count, day as usize + 1);
+  assert_eq!(partition[0].get_timestamp(1), day * 1_000_000_000 + 500_000_000);
+}
+
+#[test]
+fn zone_maps() {
+  let name = "zones_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("close", ColumnType::F64),
+      Column::new("volume", ColumnType::U32).with_nulls(),
+      Column::new("ticker", ColumnType::Symbol8),
+    ])
+    .partition_by(PartitionBy::Year);
+  let mut table = Table::create(schema).unwrap();
+
+  // Close climbs so only the last blocks are above 90. One big volume lands late.
+  let row_count = 100_000;
+  let close = |i: i64| i as f64 / 1000.0;
+  for i in (0..row_count).filter(|i| *i != 5000) {
+    table.put_timestamp(i).unwrap();
+    table.put_f64(close(i)).unwrap();
+    if i % 2 == 0 {
+      table.put_null().unwrap();
+    } else {
+      table.put_u32(100).unwrap();
+    }
+    table.put_symbol("AAPL").unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+  table.put_timestamp(5000).unwrap();
+  table.put_f64(close(5000)).unwrap();
+  table.put_u32(1_000_000).unwrap();
+  table.put_symbol("AAPL").unwrap();
+  table.write().unwrap();
+  table.flush().unwrap();
+
+  let table = Table::open(name).unwrap();
+  let scan = |column: &str, op: Compare, value: f64| {
+    let partitions = table.partition_iter(0, i64::MAX, vec!["ts", "close", "volume"]);
+    let partitions = partitions.unwrap().filter_blocks(column, op, value).unwrap();
+    partitions.map(|p| p.unwrap()).collect::<Vec<_>>()
+  };
+  // 8192 F64s per block
+  let chunks = scan("close", Compare::Gt, 90.0);
+  assert_eq!(chunks.len(), 1);
+  assert_eq!(chunks[0][0].start_row, 10 * 8192);
+  assert_eq!(chunks[0][0].get_timestamp(0), 10 * 8192);
+  let matches = chunks[0][1].get_f64().iter().filter(|c| **c > 90.0).count();
+  assert_eq!(matches, 9999);
+
+  // Nulls aren't counted and the late row was merged into the first block of 16384 u32s
+  let chunks = scan("volume", Compare::Ge, 1e6);
+  assert_eq!(chunks.len(), 1);
+  assert_eq!(chunks[0][2].row_count, 16384);
+  assert_eq!(chunks[0][2].get_u32()[5000], 1_000_000);
+  assert!(scan("volume", Compare::Lt, 100.0).is_empty());
+  assert!(scan("close", Compare::Lt, 0.0).is_empty());
+  assert_eq!(scan("close", Compare::Ge, 0.0)[0][0].row_count, row_count as usize);
+
+  // Both filters must match
+  let partitions = table.partition_iter(0, i64::MAX, vec!["ts"]).unwrap();
+  let partitions = partitions
+    .filter_blocks("close", Compare::Lt, 1.0)
+    .unwrap()
+    .filter_blocks("volume", Compare::Gt, 100.0)
+    .unwrap();
+  assert_eq!(partitions.map(|p| p.unwrap()[0].row_count).sum::<usize>(), 8192);
+
+  let partitions = table.partition_iter(0, i64::MAX, vec!["ts"]).unwrap();
+  assert!(matches!(
+    partitions.filter_blocks("ticker", Compare::Eq, 1.0),
+    Err(Error::TypeMismatch { .. })
+  ));
+}
+
+#[test]
+fn symbol_index() {
+  let name = "symbol_index_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("ticker", ColumnType::Symbol16),
+      Column::new("size", ColumnType::U32).with_nulls(),
+      Column::new("venue", ColumnType::String),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Tickers take turns over two days. Late rows get merged on flush.
+  let tickers = ["A", "B", "C"];
+  let hour = 60 * 60 * 1_000_000_000;
+  let put_row = |table: &mut Table, i: i64| {
+    table.put_timestamp(i * hour / 100).unwrap();
+    table.put_symbol(tickers[i as usize % 3]).unwrap();
+    if i % 7 == 0 {
+      table.put_null().unwrap();
+    } else {
+      table.put_u32(i as u32).unwrap();
+    }
+    table.put_string(&format!("venue{}", i)).unwrap();
+    table.write().unwrap();
+  };
+  for i in (0..4800).filter(|i| i % 100 != 50) {
+    put_row(&mut table, i);
+  }
+  table.flush().unwrap();
+  for i in (0..4800).filter(|i| i % 100 == 50) {
+    put_row(&mut table, i);
+  }
+  table.flush().unwrap();
+
+  let check = |table: &Table, symbols: &[&str], expected: Vec<i64>| {
+    let columns = vec!["ts", "ticker", "size", "venue"];
+    let partitions = table.partition_iter(0, i64::MAX, columns).unwrap();
+    let mut actual = Vec::new();
+    for partition in partitions.filter_symbols("ticker", symbols).unwrap() {
+      let partition = partition.unwrap();
+      for row_index in 0..partition[0].row_count {
+        let i = partition[0].get_timestamp(row_index) / (hour / 100);
+        assert_eq!(partition[1].get_symbol(row_index), tickers[i as usize % 3]);
+        assert_eq!(partition[2].is_null(row_index), i % 7 == 0);
+        if i % 7 != 0 {
+          assert_eq!(partition[2].get_u32()[row_index], i as u32);
+        }
+        assert_eq!(partition[3].get_string(row_index), format!("venue{}", i));
+        actual.push(i);
+      }
+    }
+    assert_eq!(actual, expected);
+  };
+  let table = Table::open(name).unwrap();
+  check(&table, &["B"], (0..4800).filter(|i| i % 3 == 1).collect());
+  check(&table, &["C", "A", "Z"], (0..4800).filter(|i| i % 3 != 1).collect());
+  check(&table, &["Z"], Vec::new());
+
+  // Indexes are rebuilt from the column when missing
+  let mut index_path = get_home_path();
+  index_path.push(&table.partition_meta["1970-01-01"].dir);
+  index_path.push(name);
+  index_path.push("1970-01-01");
+  index_path.push("ticker.symbol16.index");
+  remove_file(&index_path).unwrap();
+  check(&table, &["A"], (0..4800).filter(|i| i % 3 == 0).collect());
+
+  let partitions = table.partition_iter(0, i64::MAX, vec!["ts"]).unwrap();
+  assert!(matches!(
+    partitions.filter_symbols("size", &["A"]),
+    Err(Error::TypeMismatch { .. })
+  ));
+}
+
+#[test]
+fn filter_pushdown() {
+  let name = "filter_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("size", ColumnType::U32).with_nulls(),
+      Column::new("price", ColumnType::Decimal64 { scale: 2 }),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  let syms = ["AAPL", "MSFT", "TSLA"];
+  let minute = 60 * 1_000_000_000;
+  let size = |i: i64| if i % 11 == 0 { None } else { Some((i * 37 % 200) as u32) };
+  let price = |i: i64| 10_000 + i % 500;
+  let row_count = 3 * 24 * 60;
+  for i in 0..row_count {
+    table.put_timestamp(i * minute).unwrap();
+    table.put_symbol(syms[i as usize % 3]).unwrap();
+    match size(i) {
+      Some(size) => table.put_u32(size).unwrap(),
+      None => table.put_null().unwrap()
+    }
+    table.put_decimal(price(i)).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  let table = Table::open(name).unwrap();
+  let scan = |from_ts: i64, filter: Filter| {
+    let partitions = table.partition_iter(from_ts, i64::MAX, vec!["ts", "sym", "size"]).unwrap();
+    let mut res = Vec::new();
+    for partition in partitions.filter(filter).unwrap() {
+      let partition = partition.unwrap();
+      assert_ne!(partition[0].row_count, 0);
+      for row_index in 0..partition[0].row_count {
+        let i = partition[0].get_timestamp(row_index) / minute;
+        assert_eq!(partition[1].get_symbol(row_index), syms[i as usize % 3]);
+        assert_eq!(partition[2].is_null(row_index), size(i).is_none());
+        res.push(i);
+      }
+    }
+    res
+  };
+
+  // AAPL trades with size > 100
+  let filter = Filter::is_in("sym", &["AAPL"]).and(Filter::compare("size", Compare::Gt, 100.0));
+  let expected = (0..row_count)
+    .filter(|i| i % 3 == 0 && size(*i).map_or(false, |s| s > 100))
+    .collect::<Vec<_>>();
+  assert!(!expected.is_empty());
+  assert_eq!(scan(0, filter.clone()), expected);
+  let from_ts = 2000 * minute;
+  let expected = expected.into_iter().filter(|i| *i >= 2000).collect::<Vec<_>>();
+  assert_eq!(scan(from_ts, filter), expected);
+
+  // Columns that aren't yielded can be filtered on and nulls never match
+  let filter = Filter::compare("price", Compare::Le, 100.05)
+    .or(Filter::is_in("sym", &["TSLA", "NOPE"]))
+    .or(Filter::compare("size", Compare::Ne, 1.0));
+  let expected = (0..row_count)
+    .filter(|i| price(*i) <= 10_005 || i % 3 == 2 || size(*i).map_or(false, |s| s != 1))
+    .collect::<Vec<_>>();
+  assert_eq!(scan(0, filter), expected);
+  let filter = Filter::compare("ts", Compare::Lt, 10.0 * minute as f64);
+  assert_eq!(scan(0, filter), (0..10).collect::<Vec<_>>());
+  assert!(scan(0, Filter::is_in("sym", &["NOPE"])).is_empty());
+
+  let partitions = || table.partition_iter(0, i64::MAX, vec!["ts"]).unwrap();
+  assert!(matches!(
+    partitions().filter(Filter::compare("sym", Compare::Eq, 1.0)),
+    Err(Error::TypeMismatch { .. })
+  ));
+  assert!(matches!(
+    partitions().filter(Filter::is_in("size", &["AAPL"])),
+    Err(Error::TypeMismatch { .. })
+  ));
+  assert!(matches!(
+    partitions().filter(Filter::compare("nope", Compare::Eq, 1.0)),
+    Err(Error::ColumnNotFound { .. })
+  ));
+}
+
+#[test]
+fn resample() {
+  let name = "resample_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("price", ColumnType::Decimal64 { scale: 2 }).with_nulls(),
+      Column::new("size", ColumnType::U32),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Trades every 10s from 20:00 UTC on Memorial Day to 20:30 UTC on 2021-06-01 which is EDT
+  let second = 1_000_000_000;
+  let day_start = 1622505600 * second;
+  let ts = |i: i64| day_start - 4 * 60 * 60 * second + i * 10 * second;
+  let syms = ["AAPL", "MSFT"];
+  let price = |i: i64| if i % 13 == 0 { None } else { Some(10_000 + (i * 7919) % 1000) };
+  let trade_count = 49 * 6 * 60 / 2 + 1;
+  for i in 0..trade_count {
+    table.put_timestamp(ts(i)).unwrap();
+    table.put_symbol(syms[i as usize % 2]).unwrap();
+    match price(i) {
+      Some(price) => table.put_decimal(price).unwrap(),
+      None => table.put_null().unwrap()
+    }
+    table.put_u32(i as u32).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  let table = Table::open(name).unwrap();
+  let expected = |sym: &str, get_bar_ts: &dyn Fn(i64) -> Option<i64>| {
+    let mut bars = Bars::default();
+    for i in (0..trade_count).filter(|i| syms[*i as usize % 2] == sym) {
+      let (bar_ts, price) = match (get_bar_ts(ts(i)), price(i)) {
+        (Some(bar_ts), Some(price)) => (bar_ts, price as f64 / 100.0),
+        _ => continue
+      };
+      if bars.t.last() == Some(&bar_ts) {
+        let last = bars.t.len() - 1;
+        bars.h[last] = bars.h[last].max(price);
+        bars.l[last] = bars.l[last].min(price);
+        bars.c[last] = price;
+        bars.v[last] += i as u64;
+      } else {
+        bars.t.push(bar_ts);
+        bars.o.push(price);
+        bars.h.push(price);
+        bars.l.push(price);
+        bars.c.push(price);
+        bars.v.push(i as u64);
+      }
+    }
+    bars
+  };
+  let trades = Resample::new(parse_duration("5m").unwrap()).trades("price", "size");
+  let res = table.resample(0, i64::MAX, &trades).unwrap();
+  let five_minutes = 5 * 60 * second;
+  for sym in &syms {
+    assert_eq!(res[*sym], expected(sym, &|ts| Some(ts - ts % five_minutes)));
+  }
+  // Bars spanning partitions are merged
+  let seven_hours = 7 * 60 * 60 * second;
+  let res = table
+    .resample(0, i64::MAX, &Resample { duration: seven_hours, ..trades.clone() })
+    .unwrap();
+  for sym in &syms {
+    assert_eq!(res[*sym], expected(sym, &|ts| Some(ts - ts % seven_hours)));
+  }
+
+  // Bars start at 13:30 and rows after the 20:00 close are dropped
+  let session = day_start + (13 * 60 + 30) * 60 * second..day_start + 20 * 60 * 60 * second;
+  let hour = 60 * 60 * second;
+  let trades = trades.align(Alignment::UsEquity).symbols(&["MSFT"]);
+  let res = table
+    .resample(0, i64::MAX, &Resample { duration: hour, ..trades.clone() })
+    .unwrap();
+  assert_eq!(res.len(), 1);
+  let get_bar_ts = |ts: i64| {
+    Some(session.start + (ts - session.start) / hour * hour).filter(|_| session.contains(&ts))
+  };
+  assert_eq!(res["MSFT"], expected("MSFT", &get_bar_ts));
+  assert_eq!(res["MSFT"].t.len(), 7);
+  let res = table.resample(0, i64::MAX, &Resample { duration: 24 * hour, ..trades }).unwrap();
+  assert_eq!(res["MSFT"].t, vec![session.start]);
+
+  let route = format!("/ohlcv/{}/1h/2021-06-01/2021-06-02?symbols=AAPL&calendar=us_equity", name);
+  let res = serde_json::from_slice::<serde_json::Value>(&ohlcv(&route).unwrap()).unwrap();
+  let bars = expected("AAPL", &get_bar_ts);
+  assert_eq!(res["results"]["AAPL"]["t"], serde_json::json!(bars.t));
+  assert_eq!(res["results"]["AAPL"]["v"], serde_json::json!(bars.v));
+  assert_eq!(res["min_date"], session.start);
+  assert!(ohlcv(&format!("/ohlcv/{}/5x/2021-06-01/2021-06-02", name)).is_err());
+  let bars_only = Resample::new(hour).symbol_column("size");
+  assert!(matches!(
+    table.resample(0, i64::MAX, &bars_only),
+    Err(Error::ColumnNotFound { .. })
+  ));
+}
+
+#[test]
+fn continuous_aggregates() {
+  let source_name = "agg_source_test";
+  let name = "agg_daily_test";
+  for table_name in &[source_name, name] {
+    let mut data_path = get_home_path();
+    data_path.push("data");
+    data_path.push(table_name);
+    let _ = remove_dir_all(&data_path);
+  }
+  let bar_columns = |price_type: ColumnType| {
+    vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("open", price_type),
+      Column::new("high", price_type),
+      Column::new("low", price_type),
+      Column::new("close", price_type),
+      Column::new("volume", ColumnType::U64),
+    ]
+  };
+  let schema = Schema::new(source_name)
+    .add_cols(bar_columns(ColumnType::F32))
+    .partition_by(PartitionBy::Day);
+  let mut source = Table::create(schema).unwrap();
+  let minute = 60 * 1_000_000_000;
+  let day = 24 * 60 * minute;
+  let syms = ["AAPL", "MSFT"];
+  let write_bar = |table: &mut Table, i: i64| {
+    table.put_timestamp(i * minute).unwrap();
+    table.put_symbol(syms[i as usize % 2]).unwrap();
+    for price in 0..4 {
+      table.put_f32(((i * 31 + price * 7) % 1000) as f32 / 8.0).unwrap();
+    }
+    table.put_u64(i as u64).unwrap();
+    table.write().unwrap();
+  };
+  for i in 0..3 * 24 * 60 {
+    write_bar(&mut source, i);
+  }
+  source.flush().unwrap();
+
+  let resample = Resample::new(day);
+  let schema = || {
+    Schema::new(name)
+      .add_cols(bar_columns(ColumnType::F64))
+      .partition_by(PartitionBy::Month)
+      .aggregate(source_name, resample.clone())
+  };
+  // The source's writer has to let go for the lineage to be recorded
+  assert!(matches!(Table::create(schema()), Err(Error::TableLocked(_))));
+  let _ = remove_dir_all(get_home_path().join("data").join(name));
+  source.unlock();
+  Table::create(schema()).unwrap();
+  source.lock().unwrap();
+  assert_eq!(source.derived, vec![name.to_string()]);
+
+  let read_bars = || {
+    let table = Table::open(name).unwrap();
+    let columns = vec!["ts", "sym", "open", "high", "low", "close", "volume"];
+    let mut res = std::collections::HashMap::<String, Bars>::new();
+    for partition in table.partition_iter(i64::MIN, i64::MAX, columns).unwrap() {
+      let partition = partition.unwrap();
+      for i in 0..partition[0].row_count {
+        let bars = res.entry(partition[1].get_symbol(i).to_string()).or_default();
+        bars.t.push(partition[0].get_timestamp(i));
+        bars.o.push(partition[2].get_f64()[i]);
+        bars.h.push(partition[3].get_f64()[i]);
+        bars.l.push(partition[4].get_f64()[i]);
+        bars.c.push(partition[5].get_f64()[i]);
+        bars.v.push(partition[6].get_u64()[i]);
+      }
+    }
+    res
+  };
+  let bars = read_bars();
+  assert_eq!(bars, source.resample(i64::MIN, i64::MAX, &resample).unwrap());
+  assert_eq!(bars["AAPL"].t, vec![0, day, 2 * day]);
+
+  // A new day and a late bar for the second day
+  for i in 3 * 24 * 60..4 * 24 * 60 {
+    write_bar(&mut source, i);
+  }
+  write_bar(&mut source, day / minute + 1);
+  source.flush().unwrap();
+  let bars = read_bars();
+  assert_eq!(bars, source.resample(i64::MIN, i64::MAX, &resample).unwrap());
+  assert_eq!(bars["MSFT"].t.len(), 4);
+  let day_rows = day / minute;
+  let volume = (day_rows..2 * day_rows).filter(|i| i % 2 == 1).sum::<i64>() + day_rows + 1;
+  assert_eq!(bars["MSFT"].v[1], volume as u64);
+
+  let table = Table::open(name).unwrap();
+  assert_eq!(table.schema.aggregate.unwrap().source, source_name);
+}
+
+#[test]
+fn asof_join() {
+  let trades_name = "asof_trades_test";
+  let quotes_name = "asof_quotes_test";
+  for table_name in &[trades_name, quotes_name] {
+    let mut data_path = get_home_path();
+    data_path.push("data");
+    data_path.push(table_name);
+    let _ = remove_dir_all(&data_path);
+  }
+  let schema = Schema::new(trades_name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("size", ColumnType::U32),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut trades = Table::create(schema).unwrap();
+  let schema = Schema::new(quotes_name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol16),
+      Column::new("bid", ColumnType::Decimal64 { scale: 2 }).with_nulls(),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut quotes = Table::create(schema).unwrap();
+
+  // Two days of trades every 10s and quotes every 7s with gaps. Symbols are interned in a
+  // different order and TSLA is never quoted.
+  let second = 1_000_000_000;
+  let day = 24 * 60 * 60 * second;
+  let trade_syms = ["AAPL", "MSFT", "TSLA"];
+  let trade_count = 2 * day / (10 * second);
+  for i in 0..trade_count {
+    trades.put_timestamp(i * 10 * second).unwrap();
+    trades.put_symbol(trade_syms[i as usize % 3]).unwrap();
+    trades.put_u32(i as u32).unwrap();
+    trades.write().unwrap();
+  }
+  trades.flush().unwrap();
+  let quote_syms = ["MSFT", "AAPL"];
+  let mut quote_rows = Vec::new();
+  for i in (0..2 * day / (7 * second)).filter(|i| !(3..8).contains(&(i % 10))) {
+    let ts = 30 * second + i * 7 * second;
+    let bid = if i % 11 == 0 { None } else { Some(10_000 + i % 500) };
+    quotes.put_timestamp(ts).unwrap();
+    quotes.put_symbol(quote_syms[i as usize % 2]).unwrap();
+    match bid {
+      Some(bid) => quotes.put_decimal(bid).unwrap(),
+      None => quotes.put_null().unwrap()
+    }
+    quotes.write().unwrap();
+    quote_rows.push((ts, quote_syms[i as usize % 2], bid));
+  }
+  quotes.flush().unwrap();
+
+  // The last quote at or before each trade
+  let expected = |from_ts: i64, to_ts: i64, by: bool, tolerance: Option<i64>| {
+    let quotes_of = |sym: &str| {
+      quote_rows
+        .iter()
+        .filter(|(_quote_ts, quote_sym, _bid)| !by || *quote_sym == sym)
+        .collect::<Vec<_>>()
+    };
+    let quotes = trade_syms.iter().map(|sym| quotes_of(sym)).collect::<Vec<_>>();
+    (0..trade_count)
+      .map(|i| (i * 10 * second, i as usize % 3))
+      .filter(|(ts, _sym)| (from_ts..=to_ts).contains(ts))
+      .map(|(ts, sym)| {
+        let quotes = &quotes[sym];
+        let end = quotes.partition_point(|(quote_ts, ..)| *quote_ts <= ts);
+        let quote = end
+          .checked_sub(1)
+          .map(|i| quotes[i])
+          .filter(|(quote_ts, ..)| tolerance.map_or(true, |t| ts - quote_ts <= t));
+        let quote_ts = quote.map(|(quote_ts, ..)| *quote_ts);
+        (ts, trade_syms[sym].to_string(), quote_ts, quote.and_then(|(.., bid)| *bid))
+      })
+      .collect::<Vec<_>>()
+  };
+  let trades = Table::open(trades_name).unwrap();
+  let quotes = Table::open(quotes_name).unwrap();
+  let join = |from_ts: i64, to_ts: i64, options: &AsOf| {
+    let res = trades.asof_join(&quotes, from_ts, to_ts, options).unwrap();
+    let names = res.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["ts", "sym", "ts", "bid"]);
+    let (ts, sym, quote_ts, bid) = match &res[..] {
+      [ts, sym, quote_ts, bid] => (ts, sym, quote_ts, bid),
+      _ => unreachable!()
+    };
+    assert!(ts.nulls.is_none());
+    let quote_ts_nulls = quote_ts.nulls.as_ref().unwrap();
+    let bid_nulls = bid.nulls.as_ref().unwrap();
+    match (&ts.data, &sym.data, &quote_ts.data, &bid.data) {
+      (
+        ColumnBuffer::Timestamp(ts),
+        ColumnBuffer::Symbol(sym),
+        ColumnBuffer::Timestamp(quote_ts),
+        ColumnBuffer::I64(bid)
+      ) => (0..ts.len())
+        .map(|i| {
+          (
+            ts[i],
+            sym[i].clone(),
+            Some(quote_ts[i]).filter(|_| !quote_ts_nulls[i]),
+            Some(bid[i]).filter(|_| !bid_nulls[i])
+          )
+        })
+        .collect::<Vec<_>>(),
+      columns => panic!("unexpected columns {:?}", columns)
+    }
+  };
+  let options = AsOf::new(&["ts", "sym"], &["ts", "bid"]);
+  assert_eq!(join(0, i64::MAX, &options), expected(0, i64::MAX, false, None));
+  let options = options.by("sym");
+  let res = join(0, i64::MAX, &options);
+  assert_eq!(res, expected(0, i64::MAX, true, None));
+  assert!(res.iter().any(|(_ts, sym, quote_ts, _bid)| *sym == "AAPL" && quote_ts.is_some()));
+  assert!(res
+    .iter()
+    .all(|(_ts, sym, quote_ts, _bid)| *sym != "TSLA" || quote_ts.is_none()));
+  // Prevailing quotes come from before the range and earlier partitions
+  let from_ts = day + 5 * second;
+  assert_eq!(join(from_ts, 2 * day, &options), expected(from_ts, 2 * day, true, None));
+  let options = options.tolerance(10 * second);
+  let res = join(from_ts, 2 * day, &options);
+  assert_eq!(res, expected(from_ts, 2 * day, true, Some(10 * second)));
+  assert!(res
+    .iter()
+    .any(|(_ts, sym, quote_ts, _bid)| *sym == "MSFT" && quote_ts.is_none()));
+
+  let route = format!(
+    "/asof/{}/{}/1970-01-01/1970-01-02?left=ts,size&right=bid&by=sym&tolerance=10s",
+    trades_name, quotes_name
+  );
+  let res = serde_json::from_slice::<serde_json::Value>(&asof(&route).unwrap()).unwrap();
+  let expected = expected(0, day, true, Some(10 * second));
+  assert_eq!(res[0]["name"], "ts");
+  assert_eq!(res[1]["values"][4], 4);
+  let bids = expected
+    .iter()
+    .map(|(.., bid)| bid.map(|bid| bid as f64 / 100.0))
+    .collect::<Vec<_>>();
+  assert_eq!(res[2]["values"], serde_json::json!(bids));
+  assert!(asof(&format!("/asof/{}/0/1", trades_name)).is_err());
+
+  let options = AsOf::new(&["ts"], &["bid"]);
+  assert!(matches!(
+    trades.asof_join(&quotes, 0, i64::MAX, &options.clone().by("size")),
+    Err(Error::TypeMismatch { .. })
+  ));
+  assert!(matches!(
+    trades.asof_join(&quotes, 0, i64::MAX, &AsOf::new(&["ts"], &["ask"])),
+    Err(Error::ColumnNotFound { .. })
+  ));
+}
+
+#[test]
+fn latest_by() {
+  let name = "latest_by_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("price", ColumnType::F64).with_nulls(),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Three days of rows every minute. TSLA only trades at the start and GOOG only on the first day.
+  let minute = 60 * 1_000_000_000;
+  let day = 24 * 60 * minute;
+  let mut rows = Vec::new();
+  for i in 0..3 * 24 * 60 {
+    let sym = match i {
+      0 => "TSLA",
+      i if i < 24 * 60 && i % 3 == 0 => "GOOG",
+      i if i % 2 == 0 => "AAPL",
+      _ => "MSFT"
+    };
+    let price = if i % 7 == 0 { None } else { Some(i as f64 / 4.0) };
+    table.put_timestamp(i * minute).unwrap();
+    table.put_symbol(sym).unwrap();
+    match price {
+      Some(price) => table.put_f64(price).unwrap(),
+      None => table.put_null().unwrap()
+    }
+    table.write().unwrap();
+    rows.push((i * minute, sym, price));
+  }
+  table.flush().unwrap();
+
+  let expected = |as_of_ts: i64, symbols: &[&str]| {
+    let mut res: Vec<(String, i64, Option<f64>)> = Vec::new();
+    for (ts, sym, price) in rows.iter().rev().filter(|(ts, ..)| *ts <= as_of_ts) {
+      let is_requested = symbols.is_empty() || symbols.contains(sym);
+      if is_requested && !res.iter().any(|(s, ..)| s == sym) {
+        res.push((sym.to_string(), *ts, *price));
+      }
+    }
+    res
+  };
+  let table = Table::open(name).unwrap();
+  let latest_by = |as_of_ts: i64, symbols: &[&str]| {
+    let res = table.latest_by("sym", as_of_ts, &["ts", "price"], symbols).unwrap();
+    let names = res.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["sym", "ts", "price"]);
+    let price_nulls = res[2].nulls.as_ref().unwrap();
+    let (sym, ts, price) = match (&res[0].data, &res[1].data, &res[2].data) {
+      (ColumnBuffer::Symbol(sym), ColumnBuffer::Timestamp(ts), ColumnBuffer::F64(price)) => {
+        (sym, ts, price)
+      }
+      columns => panic!("unexpected columns {:?}", columns)
+    };
+    (0..sym.len())
+      .map(|i| (sym[i].clone(), ts[i], Some(price[i]).filter(|_| !price_nulls[i])))
+      .collect::<Vec<_>>()
+  };
+  let res = latest_by(i64::MAX, &[]);
+  assert_eq!(res, expected(i64::MAX, &[]));
+  assert_eq!(res.len(), 4);
+  assert_eq!(res[3], (String::from("TSLA"), 0, None));
+  assert_eq!(latest_by(day + 30 * minute, &[]), expected(day + 30 * minute, &[]));
+  assert_eq!(latest_by(day / 2, &["MSFT", "GOOG"]), expected(day / 2, &["MSFT", "GOOG"]));
+  assert_eq!(latest_by(i64::MAX, &["AAPL", "NOPE"]), expected(i64::MAX, &["AAPL", "NOPE"]));
+  assert!(latest_by(-1, &[]).is_empty());
+
+  let route = format!("/latest/{}/sym/1970-01-02?columns=price&symbols=AAPL,MSFT", name);
+  let res = serde_json::from_slice::<serde_json::Value>(&latest(&route).unwrap()).unwrap();
+  let expected_rows = expected(day, &["AAPL", "MSFT"]);
+  let syms = expected_rows.iter().map(|(sym, ..)| sym.as_str()).collect::<Vec<_>>();
+  let prices = expected_rows.iter().map(|(.., price)| *price).collect::<Vec<_>>();
+  assert_eq!(res[0]["values"], serde_json::json!(syms));
+  assert_eq!(res[1]["name"], "price");
+  assert_eq!(res[1]["values"], serde_json::json!(prices));
+  assert!(latest(&format!("/latest/{}", name)).is_err());
+  assert!(matches!(
+    table.latest_by("price", i64::MAX, &[], &[]),
+    Err(Error::TypeMismatch { .. })
+  ));
+
+  // Symbols resolved in later partitions don't read earlier ones
+  remove_dir_all(data_path.join("1970-01-01")).unwrap();
+  assert_eq!(latest_by(i64::MAX, &["AAPL", "MSFT"]), expected(i64::MAX, &["AAPL", "MSFT"]));
+  assert!(table.latest_by("sym", i64::MAX, &["price"], &["GOOG"]).is_err());
+}
+
+#[test]
+fn group_by() {
+  let name = "group_by_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("exchange", ColumnType::Symbol16),
+      Column::new("price", ColumnType::Decimal64 { scale: 2 }).with_nulls(),
+      Column::new("size", ColumnType::U32),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Two days of trades every 37s
+  let second = 1_000_000_000;
+  let hour = 60 * 60 * second;
+  let syms = ["MSFT", "AAPL", "GOOG"];
+  let exchanges = ["NYSE", "ARCA"];
+  let mut rows = Vec::new();
+  for i in 0..2 * 24 * 60 * 60 / 37 {
+    let ts = i * 37 * second;
+    let sym = syms[i as usize % 3];
+    let exchange = exchanges[i as usize / 7 % 2];
+    let price = if i % 5 == 0 { None } else { Some(10_000 + (i * 7919) % 2000) };
+    let size = (i % 100 + 1) as u32;
+    table.put_timestamp(ts).unwrap();
+    table.put_symbol(sym).unwrap();
+    table.put_symbol(exchange).unwrap();
+    match price {
+      Some(price) => table.put_decimal(price).unwrap(),
+      None => table.put_null().unwrap()
+    }
+    table.put_u32(size).unwrap();
+    table.write().unwrap();
+    rows.push((ts, sym, exchange, price.map(|p| p as f64 / 100.0), size as f64));
+  }
+  table.flush().unwrap();
+  let table = Table::open(name).unwrap();
+
+  let functions = [
+    AggregateFn::Sum,
+    AggregateFn::Count,
+    AggregateFn::Min,
+    AggregateFn::Max,
+    AggregateFn::Mean,
+    AggregateFn::First,
+    AggregateFn::Last,
+    AggregateFn::Stddev,
+  ];
+  let mut group_by = GroupBy::new();
+  for function in &functions {
+    group_by = group_by.aggregate(*function, "price");
+  }
+  let group_by = group_by.vwap("price", "size").aggregate(AggregateFn::Sum, "size");
+  let aggregate = |rows: &[(Option<f64>, f64)]| {
+    let prices = rows.iter().filter_map(|(price, _size)| *price).collect::<Vec<_>>();
+    let count = prices.len() as f64;
+    let mean = prices.iter().sum::<f64>() / count;
+    let variance = prices.iter().map(|p| (p - mean) * (p - mean)).sum::<f64>() / (count - 1.0);
+    let priced = rows.iter().filter_map(|(price, size)| price.map(|p| (p, *size)));
+    let (value, volume) = priced.fold((0.0, 0.0), |(v, w), (p, size)| (v + p * size, w + size));
+    vec![
+      prices.iter().sum::<f64>(),
+      count,
+      prices.iter().cloned().fold(f64::MAX, f64::min),
+      prices.iter().cloned().fold(f64::MIN, f64::max),
+      mean,
+      prices[0],
+      prices[prices.len() - 1],
+      variance.sqrt(),
+      value / volume,
+      rows.iter().map(|(_price, size)| size).sum::<f64>(),
+    ]
+  };
+  let get_f64 = |column: &OwnedColumn| match &column.data {
+    ColumnBuffer::F64(v) => v.clone(),
+    ColumnBuffer::U64(v) => v.iter().map(|v| *v as f64).collect(),
+    data => panic!("unexpected data {:?}", data)
+  };
+  let get_strings = |column: &OwnedColumn| match &column.data {
+    ColumnBuffer::Symbol(v) => v.clone(),
+    data => panic!("unexpected data {:?}", data)
+  };
+  let assert_close = |actual: f64, expected: f64| {
+    let tolerance = 1e-6 * expected.abs().max(1.0);
+    assert!((actual - expected).abs() < tolerance, "{} != {}", actual, expected);
+  };
+
+  // By symbol and hour
+  let res = table
+    .group_by(0, i64::MAX, &GroupBy { bucket: Some(hour), ..group_by.clone().by("sym") })
+    .unwrap();
+  let names = res.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+  assert_eq!(names[..5], ["sym", "ts", "sum_price", "count_price", "min_price"]);
+  assert_eq!(names[names.len() - 2..], ["vwap_price", "sum_size"]);
+  assert!(matches!(res[3].data, ColumnBuffer::U64(_)));
+  let mut expected_groups = rows
+    .iter()
+    .map(|(ts, sym, ..)| (ts - ts % hour, sym.to_string()))
+    .collect::<Vec<_>>();
+  expected_groups.sort();
+  expected_groups.dedup();
+  let buckets = match &res[1].data {
+    ColumnBuffer::Timestamp(v) => v.clone(),
+    data => panic!("unexpected data {:?}", data)
+  };
+  let groups = buckets.into_iter().zip(get_strings(&res[0])).collect::<Vec<_>>();
+  assert_eq!(groups, expected_groups);
+  for (i, (bucket, sym)) in groups.iter().enumerate() {
+    let group_rows = rows
+      .iter()
+      .filter(|(ts, s, ..)| ts - ts % hour == *bucket && s == sym)
+      .map(|(.., price, size)| (*price, *size))
+      .collect::<Vec<_>>();
+    for (column, expected) in res[2..].iter().zip(aggregate(&group_rows)) {
+      assert_close(get_f64(column)[i], expected);
+    }
+  }
+
+  // By two symbol columns with a filter
+  let filter = Filter::compare("size", Compare::Gt, 50.0);
+  let res = table
+    .group_by(0, i64::MAX, &group_by.clone().by("exchange").by("sym").filter(filter))
+    .unwrap();
+  let exchange_column = get_strings(&res[0]);
+  let sym_column = get_strings(&res[1]);
+  assert_eq!(exchange_column, vec!["ARCA", "ARCA", "ARCA", "NYSE", "NYSE", "NYSE"]);
+  assert_eq!(sym_column, vec!["AAPL", "GOOG", "MSFT", "AAPL", "GOOG", "MSFT"]);
+  for i in 0..exchange_column.len() {
+    let group_rows = rows
+      .iter()
+      .filter(|(_ts, sym, exchange, _price, size)| {
+        *exchange == exchange_column[i] && *sym == sym_column[i] && *size > 50.0
+      })
+      .map(|(.., price, size)| (*price, *size))
+      .collect::<Vec<_>>();
+    for (column, expected) in res[2..].iter().zip(aggregate(&group_rows)) {
+      assert_close(get_f64(column)[i], expected);
+    }
+  }
+
+  // Everything in one group
+  let from_ts = 5 * hour;
+  let res = table.group_by(from_ts, 30 * hour, &group_by).unwrap();
+  assert_eq!(res.len(), functions.len() + 2);
+  let group_rows = rows
+    .iter()
+    .filter(|(ts, ..)| (from_ts..=30 * hour).contains(ts))
+    .map(|(.., price, size)| (*price, *size))
+    .collect::<Vec<_>>();
+  for (column, expected) in res.iter().zip(aggregate(&group_rows)) {
+    assert_eq!(get_f64(column).len(), 1);
+    assert_close(get_f64(column)[0], expected);
+  }
+
+  // Groups with only null prices
+  let nulls = Filter::compare("size", Compare::Eq, 1.0);
+  let res = table.group_by(0, i64::MAX, &group_by.clone().filter(nulls)).unwrap();
+  assert_eq!(get_f64(&res[1]), vec![0.0]);
+  assert_eq!(res[2].nulls, Some(vec![true]));
+  assert_eq!(get_f64(&res[res.len() - 1]), vec![(rows.len() / 100 + 1) as f64]);
+
+  assert!(matches!(
+    table.group_by(0, i64::MAX, &GroupBy::new().by("price")),
+    Err(Error::TypeMismatch { .. })
+  ));
+  assert!(matches!(
+    table.group_by(0, i64::MAX, &GroupBy::new().aggregate(AggregateFn::Sum, "sym")),
+    Err(Error::TypeMismatch { .. })
+  ));
+  assert!(matches!(
+    table.group_by(0, i64::MAX, &GroupBy::new().by("nope")),
+    Err(Error::ColumnNotFound { .. })
+  ));
+}
+
+#[test]
+fn sql() {
+  let name = "sql_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("price", ColumnType::F64).with_nulls(),
+      Column::new("size", ColumnType::U32),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Two days of trades every 61s
+  let second = 1_000_000_000;
+  let hour = 60 * 60 * second;
+  let day = 24 * hour;
+  let syms = ["AAPL", "MSFT", "GOOG"];
+  let mut rows = Vec::new();
+  for i in 0..2 * day / (61 * second) {
+    let ts = i * 61 * second;
+    let sym = syms[i as usize % 3];
+    let price = if i % 9 == 0 { None } else { Some(100.0 + (i * 7919 % 1000) as f64 / 8.0) };
+    let size = (i * 31 % 100) as u32;
+    table.put_timestamp(ts).unwrap();
+    table.put_symbol(sym).unwrap();
+    match price {
+      Some(price) => table.put_f64(price).unwrap(),
+      None => table.put_null().unwrap()
+    }
+    table.put_u32(size).unwrap();
+    table.write().unwrap();
+    rows.push((ts, sym, price, size));
+  }
+  table.flush().unwrap();
+  let table = Table::open(name).unwrap();
+  let names = |columns: &[OwnedColumn]| columns.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+  let timestamps = |column: &OwnedColumn| match &column.data {
+    ColumnBuffer::Timestamp(v) => v.clone(),
+    data => panic!("unexpected data {:?}", data)
+  };
+
+  // The range comes from WHERE on ts and the rest is a filter
+  let res = run_sql(&format!(
+    "SELECT ts, sym AS ticker, size FROM {} WHERE ts >= '1970-01-02' AND sym = 'AAPL' AND size \
+     > 50 LIMIT 5",
+    name
+  ))
+  .unwrap();
+  assert_eq!(names(&res), vec!["ts", "ticker", "size"]);
+  let expected = rows
+    .iter()
+    .filter(|(ts, sym, _price, size)| *ts >= day && *sym == "AAPL" && *size > 50)
+    .take(5)
+    .collect::<Vec<_>>();
+  assert_eq!(timestamps(&res[0]), expected.iter().map(|r| r.0).collect::<Vec<_>>());
+  assert!(matches!(&res[1].data, ColumnBuffer::Symbol(v) if v.iter().all(|s| s == "AAPL")));
+  assert!(matches!(&res[2].data, ColumnBuffer::U32(v) if v.len() == 5));
+
+  // GROUP BY compiles to Table::group_by
+  let res = run_sql(&format!(
+    "select sym, count(*), sum(size) as volume, vwap(price, size) from {} group by sym order by \
+     volume desc",
+    name
+  ))
+  .unwrap();
+  assert_eq!(names(&res), vec!["sym", "count", "volume", "vwap_price"]);
+  let group_by = GroupBy::new()
+    .by("sym")
+    .aggregate(AggregateFn::Count, "ts")
+    .aggregate(AggregateFn::Sum, "size")
+    .vwap("price", "size");
+  let expected = table.group_by(i64::MIN, i64::MAX, &group_by).unwrap();
+  let volumes = match &expected[2].data {
+    ColumnBuffer::F64(v) => v.clone(),
+    data => panic!("unexpected data {:?}", data)
+  };
+  let mut rows_by_volume = (0..volumes.len()).collect::<Vec<_>>();
+  rows_by_volume.sort_by(|a, b| volumes[*b].partial_cmp(&volumes[*a]).unwrap());
+  for (actual, expected) in res.iter().zip(&expected) {
+    assert_eq!(actual.data, expected.take(&rows_by_volume).data);
+  }
+
+  // SAMPLE BY and time_bucket
+  let res = run_sql(&format!(
+    "SELECT ts, sym, first(price), max(price) FROM {} WHERE ts < 7200000000000 AND sym IN \
+     ('MSFT', 'GOOG') SAMPLE BY 1h",
+    name
+  ))
+  .unwrap();
+  assert_eq!(timestamps(&res[0]), vec![0, 0, hour, hour]);
+  let expected = table
+    .group_by(0, 2 * hour - 1, &GroupBy {
+      bucket: Some(hour),
+      ..GroupBy::new()
+        .by("sym")
+        .aggregate(AggregateFn::First, "price")
+        .aggregate(AggregateFn::Max, "price")
+        .filter(Filter::is_in("sym", &["MSFT", "GOOG"]))
+    })
+    .unwrap();
+  // group_by returns the bucket after the symbol
+  let expected = [&expected[1], &expected[0], &expected[2], &expected[3]];
+  for (actual, expected) in res.iter().zip(&expected) {
+    assert_eq!(actual.data, expected.data);
+  }
+  let res = run_sql(&format!(
+    "SELECT time_bucket('1h', ts) AS hour, sym, count(price) FROM {} GROUP BY hour, sym ORDER \
+     BY hour DESC, sym LIMIT 4",
+    name
+  ))
+  .unwrap();
+  assert_eq!(names(&res), vec!["hour", "sym", "count_price"]);
+  assert_eq!(timestamps(&res[0]), vec![47 * hour, 47 * hour, 47 * hour, 46 * hour]);
+  let hour_rows = |bucket: i64, sym: &str| {
+    rows
+      .iter()
+      .filter(|(ts, s, price, _size)| ts / hour == bucket && *s == sym && price.is_some())
+      .count() as u64
+  };
+  assert_eq!(
+    res[2].data,
+    ColumnBuffer::U64(vec![
+      hour_rows(47, "AAPL"),
+      hour_rows(47, "GOOG"),
+      hour_rows(47, "MSFT"),
+      hour_rows(46, "AAPL"),
+    ])
+  );
+
+  // ORDER BY sorts nulls first
+  let res = run_sql(&format!(
+    "SELECT * FROM {} WHERE ts BETWEEN 0 AND 3600000000000 ORDER BY price, ts DESC LIMIT 3",
+    name
+  ))
+  .unwrap();
+  assert_eq!(names(&res), vec!["ts", "sym", "price", "size"]);
+  let mut expected = rows.iter().filter(|(ts, ..)| *ts <= hour).collect::<Vec<_>>();
+  expected.sort_by(|a, b| {
+    let price = |p: Option<f64>| p.unwrap_or(f64::MIN);
+    price(a.2).partial_cmp(&price(b.2)).unwrap().then(b.0.cmp(&a.0))
+  });
+  assert_eq!(timestamps(&res[0]), expected.iter().take(3).map(|r| r.0).collect::<Vec<_>>());
+  assert!(res[2].nulls.as_ref().unwrap()[0]);
+
+  assert!(matches!(run_sql("SELECT * FROM nope"), Err(Error::TableNotFound(_))));
+  for sql in &[
+    "SELECT price FROM sql_test GROUP BY sym",
+    "SELECT * FROM sql_test WHERE sym > 1",
+    "SELECT * FROM sql_test WHERE ts > 'yesterday'",
+    "SELECT * FROM sql_test ORDER BY volume",
+    "SELECT sym, time_bucket('1h', size) FROM sql_test GROUP BY sym",
+    "SELECT FROM sql_test",
+  ] {
+    assert!(matches!(run_sql(sql), Err(Error::Query(_))), "{}", sql);
+  }
+  assert!(matches!(
+    run_sql("SELECT * FROM sql_test WHERE nope = 1"),
+    Err(Error::ColumnNotFound { .. })
+  ));
+}
+
+#[test]
+fn arrow_export() {
+  let name = "arrow_export_test";
+  let mut data_path = get_home_path();
+  data_path.push("data");
+  data_path.push(name);
+  let _ = remove_dir_all(&data_path);
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol8),
+      Column::new("price", ColumnType::F64).with_nulls(),
+      Column::new("size", ColumnType::U32),
+      Column::new("cond", ColumnType::String),
+      Column::new("px", ColumnType::Decimal64 { scale: 2 }),
+    ])
+    .partition_by(PartitionBy::Day);
+  let mut table = Table::create(schema).unwrap();
+
+  // Two days of rows every hour
+  let hour = 60 * 60 * 1_000_000_000;
+  let syms = ["AAPL", "MSFT", "TSLA"];
+  for i in 0..48 {
+    table.put_timestamp(i * hour).unwrap();
+    table.put_symbol(syms[i as usize % 3]).unwrap();
+    if i % 5 == 0 {
+      table.put_null().unwrap();
+    } else {
+      table.put_f64(i as f64 / 2.0).unwrap();
+    }
+    table.put_u32(i as u32 * 100).unwrap();
+    table.put_string(&"x".repeat(i as usize % 4)).unwrap();
+    table.put_decimal(i * 125).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  let table = Table::open(name).unwrap();
+  // Fixed width columns point into the partition's files
+  let items = table.partition_iter(0, 47 * hour, vec!["ts", "price"]).unwrap();
+  for partition in items {
+    let mut partition = partition.unwrap();
+    let price = partition.pop().unwrap();
+    let ptr = price.slice.as_ptr();
+    let array = price.into_arrow(None).unwrap();
+    assert_eq!(array.to_data().buffers()[0].as_ptr(), ptr);
+  }
+
+  let columns = vec!["ts", "sym", "price", "size", "cond", "px"];
+  let batches = table.partition_iter(0, 47 * hour, columns).unwrap().into_arrow();
+  let schema = batches.schema();
+  let mut buf = Vec::new();
+  let mut writer = StreamWriter::try_new(&mut buf, &schema).unwrap();
+  for batch in batches {
+    writer.write(&batch.unwrap()).unwrap();
+  }
+  writer.finish().unwrap();
+  drop(writer);
+
+  let batches = StreamReader::try_new(&buf[..], None)
+    .unwrap()
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap();
+  assert_eq!(batches.len(), 2);
+  let mut i = 0;
+  for batch in batches {
+    assert_eq!(batch.schema(), schema);
+    let ts = batch.column(0).as_primitive::<TimestampNanosecondType>();
+    let sym = batch.column(1).as_dictionary::<UInt8Type>();
+    let sym_values = sym.values().as_string::<i32>();
+    let price = batch.column(2).as_primitive::<Float64Type>();
+    let cond = batch.column(4).as_string::<i64>();
+    let px = batch.column(5).as_primitive::<Decimal128Type>();
+    assert_eq!(batch.num_rows(), 24);
+    for row in 0..batch.num_rows() {
+      assert_eq!(ts.value(row), i * hour);
+      assert_eq!(sym_values.value(sym.keys().value(row) as usize), syms[i as usize % 3]);
+      assert_eq!(price.is_null(row), i % 5 == 0);
+      if i % 5 != 0 {
+        assert_eq!(price.value(row), i as f64 / 2.0);
+      }
+      assert_eq!(cond.value(row), "x".repeat(i as usize % 4));
+      assert_eq!(px.value(row), i as i128 * 125);
+      i += 1;
+    }
+  }
+  assert_eq!(i, 48);
+
+  // Query results are copied into one batch
+  let res = table.latest_by("sym", i64::MAX, &["ts", "price"], &[]).unwrap();
+  let batch = to_record_batch(&res).unwrap();
+  assert_eq!(batch.num_rows(), 3);
+  let sym = batch.column(0).as_dictionary::<UInt8Type>();
+  let sym_values = sym.values().as_string::<i32>();
+  let latest = (0..3).map(|row| sym_values.value(sym.keys().value(row) as usize));
+  assert_eq!(latest.collect::<Vec<_>>(), vec!["TSLA", "MSFT", "AAPL"]);
+  assert!(batch.column(2).as_primitive::<Float64Type>().is_null(2));
+}
+
+#[test]
+fn parquet() {
+  let name = "parquet_test";
+  let import_name = "parquet_import_test";
+  let mismatch_name = "parquet_mismatch_test";
+  let home = get_home_path();
+  let export_dir = home.join("parquet_export");
+  let _ = remove_dir_all(&export_dir);
+  for table_name in &[name, import_name, mismatch_name] {
+    let _ = remove_dir_all(home.join("data").join(table_name));
+  }
+  let schema = Schema::new(name)
+    .add_cols(vec![
+      Column::new("ts", ColumnType::Timestamp),
+      Column::new("sym", ColumnType::Symbol16),
+      Column::new("price", ColumnType::F64).with_nulls(),
+      Column::new("size", ColumnType::U32),
+      Column::new("cond", ColumnType::String),
+      Column::new("px", ColumnType::Decimal64 { scale: 2 }),
+    ])
+    .partition_by(PartitionBy::Day);
+  let parquet_schema = get_parquet_schema(&schema).unwrap();
+  let mut table = Table::create(schema).unwrap();
+
+  // Three days of rows every hour. Only the first day has nulls.
+  let hour = 60 * 60 * 1_000_000_000;
+  let syms = ["AAPL", "MSFT", "TSLA", "GOOG"];
+  for i in 0..72 {
+    table.put_timestamp(i * hour).unwrap();
+    table.put_symbol(syms[i as usize % 4]).unwrap();
+    if i < 24 && i % 5 == 0 {
+      table.put_null().unwrap();
+    } else {
+      table.put_f64(i as f64 / 4.0).unwrap();
+    }
+    table.put_u32(i as u32).unwrap();
+    table.put_string(&"c".repeat(i as usize % 3)).unwrap();
+    table.put_decimal(i * 101).unwrap();
+    table.write().unwrap();
+  }
+  table.flush().unwrap();
+
+  let types = parquet_schema
+    .columns()
+    .iter()
+    .map(|c| (c.name().to_owned(), c.physical_type().to_string()))
+    .collect::<Vec<_>>();
+  let expected = vec![
+    ("ts", "INT64"),
+    ("sym", "BYTE_ARRAY"),
+    ("price", "DOUBLE"),
+    ("size", "INT32"),
+    ("cond", "BYTE_ARRAY"),
+    ("px", "FIXED_LEN_BYTE_ARRAY"),
+  ];
+  let expected = expected.iter().map(|(n, t)| (n.to_string(), t.to_string())).collect::<Vec<_>>();
+  assert_eq!(types, expected);
+
+  // Skips rows outside the range
+  let table = Table::open(name).unwrap();
+  let paths = table.export_parquet(hour, 71 * hour, &export_dir).unwrap();
+  let names = paths.iter().map(|p| p.file_name().unwrap().to_str().unwrap()).collect::<Vec<_>>();
+  assert_eq!(names, vec!["1970-01-01.parquet", "1970-01-02.parquet", "1970-01-03.parquet"]);
+  for path in &paths {
+    Table::import_parquet(import_name, path, PartitionBy::Day).unwrap();
+  }
+
+  let imported = Table::open(import_name).unwrap();
+  let columns = |table: &Table| {
+    table.schema.columns.iter().map(|c| (c.name.clone(), c.r#type, c.nullable)).collect::<Vec<_>>()
+  };
+  assert_eq!(columns(&imported), columns(&table));
+  let query = |name: &str| {
+    run_sql(&format!("SELECT * FROM {} WHERE ts BETWEEN 1 AND {}", name, 71 * hour))
+  };
+  let res = query(import_name).unwrap();
+  assert_eq!(res, query(name).unwrap());
+  assert_eq!(res[0].len(), 71);
+
+  // Columns have to line up with an existing table
+  let schema = Schema::new(mismatch_name).add_cols(vec![
+    Column::new("ts", ColumnType::Timestamp),
+    Column::new("px", ColumnType::F64),
+  ]);
+  Table::create(schema).unwrap();
+  assert!(matches!(
+    Table::import_parquet(mismatch_name, &paths[0], PartitionBy::Day),
+    Err(Error::TypeMismatch { .. })
+  ));
+}
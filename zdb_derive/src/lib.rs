@@ -0,0 +1,227 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::{
+  parse_macro_input, spanned::Spanned, Data, DeriveInput, Error, Field, Fields, Ident, Lit, Meta,
+  NestedMeta, Type
+};
+
+// Column options from `#[zdb(...)]`
+#[derive(Default)]
+struct FieldOptions {
+  timestamp:   bool,
+  // Symbol8/16/32 or String for String fields
+  string_type: Option<&'static str>,
+  resolution:  Option<Lit>,
+  // Scale of i32/i64 Decimal fields
+  decimal:     Option<Lit>,
+  rename:      Option<String>,
+  sym_name:    Option<String>
+}
+
+fn get_options(field: &Field) -> Result<FieldOptions, Error> {
+  let mut res = FieldOptions::default();
+  for attr in field.attrs.iter().filter(|a| a.path.is_ident("zdb")) {
+    let list = match attr.parse_meta()? {
+      Meta::List(list) => list,
+      meta => return Err(Error::new(meta.span(), "expected #[zdb(...)]"))
+    };
+    for nested in list.nested {
+      match nested {
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("timestamp") => res.timestamp = true,
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("string") => {
+          res.string_type = Some("String")
+        }
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("symbol8") => {
+          res.string_type = Some("Symbol8")
+        }
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("symbol16") => {
+          res.string_type = Some("Symbol16")
+        }
+        NestedMeta::Meta(Meta::Path(path)) if path.is_ident("symbol32") => {
+          res.string_type = Some("Symbol32")
+        }
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("resolution") => {
+          res.resolution = Some(nv.lit)
+        }
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("decimal") => {
+          res.decimal = Some(nv.lit)
+        }
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("rename") => match nv.lit {
+          Lit::Str(s) => res.rename = Some(s.value()),
+          lit => return Err(Error::new(lit.span(), "expected a string"))
+        },
+        NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("sym_name") => match nv.lit {
+          Lit::Str(s) => res.sym_name = Some(s.value()),
+          lit => return Err(Error::new(lit.span(), "expected a string"))
+        },
+        nested => return Err(Error::new(nested.span(), "unknown zdb attribute"))
+      }
+    }
+  }
+  Ok(res)
+}
+
+fn get_type_name(ty: &Type) -> Option<String> {
+  match ty {
+    Type::Path(p) if p.qself.is_none() => p.path.segments.last().map(|s| s.ident.to_string()),
+    _ => None
+  }
+}
+
+// Returns (ColumnType, put fn, get expression)
+fn get_column_type(
+  field: &Field,
+  options: &FieldOptions,
+  index: usize
+) -> Result<(TokenStream2, TokenStream2, TokenStream2), Error> {
+  let name = field.ident.as_ref().unwrap();
+  let type_name = get_type_name(&field.ty).unwrap_or_default();
+  if let Some(scale) = &options.decimal {
+    let (variant, get) = match type_name.as_str() {
+      "i32" => (
+        quote! { Decimal32 },
+        quote! { columns[#index].get_decimal(row_index) as i32 }
+      ),
+      "i64" => (
+        quote! { Decimal64 },
+        quote! { columns[#index].get_decimal(row_index) }
+      ),
+      _ => return Err(Error::new(field.ty.span(), "decimal columns must be i32 or i64"))
+    };
+    return Ok((
+      quote! { ::zdb::schema::ColumnType::#variant { scale: #scale } },
+      quote! { table.put_decimal(self.#name as i64)?; },
+      get
+    ));
+  }
+  let variant = match (type_name.as_str(), options.timestamp, options.string_type) {
+    ("i64", true, None) => "Timestamp",
+    ("String", false, Some(string_type)) => string_type,
+    ("String", false, None) => "Symbol16",
+    ("Vec", false, None) => "Bytes",
+    (t, false, None) => match t {
+      "i8" => "I8",
+      "u8" => "U8",
+      "i16" => "I16",
+      "u16" => "U16",
+      "i32" => "I32",
+      "u32" => "U32",
+      "f32" => "F32",
+      "i64" => "I64",
+      "u64" => "U64",
+      "f64" => "F64",
+      _ => {
+        return Err(Error::new(
+          field.ty.span(),
+          "unsupported column type. Expected a number, String or Vec<u8>"
+        ))
+      }
+    },
+    (_, true, _) => return Err(Error::new(field.ty.span(), "timestamp columns must be i64")),
+    (_, _, _) => {
+      return Err(Error::new(
+        field.ty.span(),
+        "symbol and string columns must be String"
+      ))
+    }
+  };
+  let (put, get) = match variant {
+    "Timestamp" => (
+      quote! { table.put_timestamp(self.#name)?; },
+      quote! { columns[#index].get_timestamp(row_index) }
+    ),
+    "String" => (
+      quote! { table.put_string(&self.#name)?; },
+      quote! { columns[#index].get_string(row_index).to_owned() }
+    ),
+    "Bytes" => (
+      quote! { table.put_binary(&self.#name)?; },
+      quote! { columns[#index].get_binary(row_index).to_vec() }
+    ),
+    "Symbol8" | "Symbol16" | "Symbol32" => (
+      quote! { table.put_symbol(&self.#name)?; },
+      quote! { columns[#index].get_symbol(row_index).to_owned() }
+    ),
+    _ => {
+      let put_fn = Ident::new(&format!("put_{}", type_name), Span::call_site());
+      let get_fn = Ident::new(&format!("get_{}", type_name), Span::call_site());
+      (
+        quote! { table.#put_fn(self.#name)?; },
+        quote! { columns[#index].#get_fn()[row_index] }
+      )
+    }
+  };
+
+  let variant = Ident::new(variant, Span::call_site());
+  Ok((quote! { ::zdb::schema::ColumnType::#variant }, put, get))
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+  let name = &input.ident;
+  let fields = match &input.data {
+    Data::Struct(data) => match &data.fields {
+      Fields::Named(fields) => &fields.named,
+      _ => return Err(Error::new(input.span(), "ZdbRow requires named fields"))
+    },
+    _ => return Err(Error::new(input.span(), "ZdbRow can only be derived for structs"))
+  };
+
+  let mut columns = Vec::with_capacity(fields.len());
+  let mut puts = Vec::with_capacity(fields.len());
+  let mut gets = Vec::with_capacity(fields.len());
+  for (index, field) in fields.iter().enumerate() {
+    let options = get_options(field)?;
+    if index == 0 && !options.timestamp {
+      return Err(Error::new(
+        field.span(),
+        "the first field must be an i64 marked #[zdb(timestamp)]"
+      ));
+    }
+    let (column_type, put, get) = get_column_type(field, &options, index)?;
+    let field_name = field.ident.as_ref().unwrap();
+    let column_name = options
+      .rename
+      .clone()
+      .unwrap_or_else(|| field_name.to_string());
+    let mut column = quote! {
+      ::zdb::schema::Column::new(#column_name, #column_type)
+    };
+    if let Some(resolution) = &options.resolution {
+      column = quote! { #column.with_resolution(#resolution) };
+    }
+    if let Some(sym_name) = &options.sym_name {
+      column = quote! { #column.with_sym_name(#sym_name) };
+    }
+    columns.push(column);
+    puts.push(put);
+    gets.push(quote! { #field_name: #get });
+  }
+
+  Ok(quote! {
+    impl ::zdb::ZdbRow for #name {
+      fn columns() -> Vec<::zdb::schema::Column> {
+        vec![#(#columns),*]
+      }
+
+      fn put(&self, table: &mut ::zdb::table::Table) -> Result<(), ::zdb::Error> {
+        #(#puts)*
+        Ok(())
+      }
+
+      fn get(columns: &[::zdb::table::scan::PartitionColumn], row_index: usize) -> Self {
+        Self {
+          #(#gets),*
+        }
+      }
+    }
+  })
+}
+
+#[proc_macro_derive(ZdbRow, attributes(zdb))]
+pub fn derive_zdb_row(input: TokenStream) -> TokenStream {
+  let input = parse_macro_input!(input as DeriveInput);
+  expand(input)
+    .unwrap_or_else(|e| e.to_compile_error())
+    .into()
+}